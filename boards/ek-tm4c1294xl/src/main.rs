@@ -6,12 +6,13 @@
 
 extern crate capsules;
 #[allow(unused_imports)]
-#[macro_use(debug, static_init)]
+#[macro_use(debug, static_init, create_capability)]
 extern crate kernel;
 extern crate cortexm4;
 extern crate tm4c129x;
 
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::capabilities;
 use kernel::hil;
 use kernel::hil::Controller;
 use kernel::Platform;
@@ -75,6 +76,15 @@ impl Platform for EkTm4c1294xl {
 /// Reset Handler
 #[no_mangle]
 pub unsafe fn reset_handler() {
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let kernel_configuration_capability =
+        create_capability!(capabilities::KernelConfigurationCapability);
+
     tm4c129x::init();
 
     tm4c129x::sysctl::PSYSCTLM
@@ -87,7 +97,7 @@ pub unsafe fn reset_handler() {
             115200,
             &mut capsules::console::WRITE_BUF,
             &mut capsules::console::READ_BUF,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     hil::uart::UART::set_client(&tm4c129x::uart::UART0, console);
@@ -106,7 +116,10 @@ pub unsafe fn reset_handler() {
     );
     let alarm = static_init!(
         capsules::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>>,
-        capsules::alarm::AlarmDriver::new(virtual_alarm1, kernel::Grant::create())
+        capsules::alarm::AlarmDriver::new(
+            virtual_alarm1,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     virtual_alarm1.set_client(alarm);
 
@@ -156,7 +169,10 @@ pub unsafe fn reset_handler() {
     );
     let button = static_init!(
         capsules::button::Button<'static, tm4c129x::gpio::GPIOPin>,
-        capsules::button::Button::new(button_pins, kernel::Grant::create())
+        capsules::button::Button::new(
+            button_pins,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
@@ -184,7 +200,7 @@ pub unsafe fn reset_handler() {
         console: console,
         alarm: alarm,
         gpio: gpio,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(&memory_allocation_capability),
         led: led,
         button: button,
     };
@@ -195,7 +211,11 @@ pub unsafe fn reset_handler() {
 
     // Attach the kernel debug interface to this console
     let kc = static_init!(capsules::console::App, capsules::console::App::default());
-    kernel::debug::assign_console_driver(Some(tm4c1294.console), kc);
+    kernel::debug::assign_console_driver(
+        Some(tm4c1294.console),
+        kc,
+        &kernel_configuration_capability,
+    );
 
     debug!("Initialization complete. Entering main loop...\r");
 
@@ -213,6 +233,16 @@ pub unsafe fn reset_handler() {
         &mut APP_MEMORY,
         &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
+        &process_management_capability,
+    );
+    let scheduler = kernel::scheduler::RoundRobin::new();
+    board_kernel.kernel_loop(
+        &tm4c1294,
+        &mut chip,
+        &mut PROCESSES,
+        Some(&tm4c1294.ipc),
+        &scheduler,
+        &main_loop_capability,
     );
-    board_kernel.kernel_loop(&tm4c1294, &mut chip, &mut PROCESSES, Some(&tm4c1294.ipc));
 }
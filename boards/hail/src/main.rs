@@ -10,7 +10,7 @@
 
 extern crate capsules;
 #[allow(unused_imports)]
-#[macro_use(debug, debug_gpio, static_init)]
+#[macro_use(debug, debug_gpio, static_init, create_capability)]
 extern crate kernel;
 extern crate cortexm4;
 extern crate sam4l;
@@ -18,6 +18,7 @@ extern crate sam4l;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
+use kernel::capabilities;
 use kernel::hil;
 use kernel::hil::spi::SpiMaster;
 use kernel::hil::Controller;
@@ -46,6 +47,10 @@ const FAULT_RESPONSE: kernel::procs::FaultResponse = kernel::procs::FaultRespons
 #[link_section = ".app_memory"]
 static mut APP_MEMORY: [u8; 49152] = [0; 49152];
 
+// The most recent syscalls, which a privileged app can read.
+static mut SYSCALL_TRACE_BUF: [kernel::procs::SyscallRecord; 64] =
+    [kernel::procs::SyscallRecord::EMPTY; 64];
+
 // Actual memory for holding the active process structures.
 static mut PROCESSES: [Option<&'static mut kernel::procs::Process<'static>>; NUM_PROCS] = [
     None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
@@ -82,6 +87,10 @@ struct Hail {
     ipc: kernel::ipc::IPC,
     crc: &'static capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
     dac: &'static capsules::dac::Dac<'static>,
+    process_loader: &'static capsules::process_loader::ProcessLoaderDriver<'static>,
+    process_manager: &'static capsules::process_manager::ProcessManagerDriver<'static>,
+    crash_record: &'static capsules::crash_record::CrashRecordDriver,
+    syscall_trace: &'static capsules::syscall_trace::SyscallTraceDriver<'static>,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -111,6 +120,11 @@ impl Platform for Hail {
 
             capsules::dac::DRIVER_NUM => f(Some(self.dac)),
 
+            capsules::process_loader::DRIVER_NUM => f(Some(self.process_loader)),
+            capsules::process_manager::DRIVER_NUM => f(Some(self.process_manager)),
+            capsules::crash_record::DRIVER_NUM => f(Some(self.crash_record)),
+            capsules::syscall_trace::DRIVER_NUM => f(Some(self.syscall_trace)),
+
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -179,6 +193,15 @@ unsafe fn set_pin_primary_functions() {
 /// execution begins here.
 #[no_mangle]
 pub unsafe fn reset_handler() {
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let kernel_configuration_capability =
+        create_capability!(capabilities::KernelConfigurationCapability);
+
     sam4l::init();
 
     sam4l::pm::PM.setup_system_clock(sam4l::pm::SystemClockSource::PllExternalOscillatorAt48MHz {
@@ -207,7 +230,7 @@ pub unsafe fn reset_handler() {
             115200,
             &mut capsules::console::WRITE_BUF,
             &mut capsules::console::READ_BUF,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     hil::uart::UART::set_client(&sam4l::usart::USART0, console);
@@ -257,13 +280,19 @@ pub unsafe fn reset_handler() {
 
     let temp = static_init!(
         capsules::temperature::TemperatureSensor<'static>,
-        capsules::temperature::TemperatureSensor::new(si7021, kernel::Grant::create())
+        capsules::temperature::TemperatureSensor::new(
+            si7021,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     kernel::hil::sensors::TemperatureDriver::set_client(si7021, temp);
 
     let humidity = static_init!(
         capsules::humidity::HumiditySensor<'static>,
-        capsules::humidity::HumiditySensor::new(si7021, kernel::Grant::create())
+        capsules::humidity::HumiditySensor::new(
+            si7021,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     kernel::hil::sensors::HumidityDriver::set_client(si7021, humidity);
 
@@ -286,7 +315,10 @@ pub unsafe fn reset_handler() {
 
     let ambient_light = static_init!(
         capsules::ambient_light::AmbientLight<'static>,
-        capsules::ambient_light::AmbientLight::new(isl29035, kernel::Grant::create())
+        capsules::ambient_light::AmbientLight::new(
+            isl29035,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     hil::sensors::AmbientLight::set_client(isl29035, ambient_light);
 
@@ -297,7 +329,10 @@ pub unsafe fn reset_handler() {
     );
     let alarm = static_init!(
         capsules::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
-        capsules::alarm::AlarmDriver::new(virtual_alarm1, kernel::Grant::create())
+        capsules::alarm::AlarmDriver::new(
+            virtual_alarm1,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     virtual_alarm1.set_client(alarm);
 
@@ -316,7 +351,10 @@ pub unsafe fn reset_handler() {
 
     let ninedof = static_init!(
         capsules::ninedof::NineDof<'static>,
-        capsules::ninedof::NineDof::new(fxos8700, kernel::Grant::create())
+        capsules::ninedof::NineDof::new(
+            fxos8700,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     hil::sensors::NineDof::set_client(fxos8700, ninedof);
 
@@ -379,7 +417,10 @@ pub unsafe fn reset_handler() {
     );
     let button = static_init!(
         capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
-        capsules::button::Button::new(button_pins, kernel::Grant::create())
+        capsules::button::Button::new(
+            button_pins,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
@@ -412,7 +453,10 @@ pub unsafe fn reset_handler() {
     // Setup RNG
     let rng = static_init!(
         capsules::rng::SimpleRng<'static, sam4l::trng::Trng>,
-        capsules::rng::SimpleRng::new(&sam4l::trng::TRNG, kernel::Grant::create())
+        capsules::rng::SimpleRng::new(
+            &sam4l::trng::TRNG,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     sam4l::trng::TRNG.set_client(rng);
 
@@ -437,7 +481,10 @@ pub unsafe fn reset_handler() {
     // CRC
     let crc = static_init!(
         capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
-        capsules::crc::Crc::new(&mut sam4l::crccu::CRCCU, kernel::Grant::create())
+        capsules::crc::Crc::new(
+            &mut sam4l::crccu::CRCCU,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     sam4l::crccu::CRCCU.set_client(crc);
 
@@ -447,6 +494,99 @@ pub unsafe fn reset_handler() {
         capsules::dac::Dac::new(&mut sam4l::dac::DAC)
     );
 
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new());
+
+    // Lets `FaultResponse::RestartWithBackoff` wait before restarting a
+    // process that faulted.
+    let restart_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let restart_timer = static_init!(
+        kernel::procs::AlarmRestartTimer<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+        kernel::procs::AlarmRestartTimer::new(restart_alarm)
+    );
+    restart_alarm.set_client(restart_timer);
+    board_kernel.set_restart_timer(restart_timer, &kernel_configuration_capability);
+
+    // Charge each process for the energy the chip uses while it runs. Hail is
+    // powered with 3.3 V.
+    let power_model = static_init!(
+        sam4l::power_model::ClockPowerModel,
+        sam4l::power_model::ClockPowerModel::new(3300)
+    );
+    board_kernel.set_power_model(power_model, &kernel_configuration_capability);
+
+    // Record the syscalls processes make, timestamped with the AST.
+    let syscall_trace_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let syscall_trace = static_init!(
+        kernel::procs::SyscallTrace,
+        kernel::procs::SyscallTrace::new(&mut SYSCALL_TRACE_BUF, Some(syscall_trace_alarm))
+    );
+    board_kernel.set_syscall_trace(syscall_trace, &kernel_configuration_capability);
+
+    extern "C" {
+        /// Beginning of the ROM region containing app images.
+        ///
+        /// This symbol is defined in the linker script.
+        static _sapps: u8;
+
+        /// End of the ROM region containing app images.
+        ///
+        /// This symbol is defined in the linker script.
+        static _eapps: u8;
+    }
+
+    // Process loader, so that a privileged app can start and stop apps
+    let process_loader = static_init!(
+        kernel::procs::ProcessLoader,
+        kernel::procs::ProcessLoader::new(
+            board_kernel,
+            &_sapps as *const u8,
+            &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+            APP_MEMORY.as_mut_ptr(),
+            APP_MEMORY.len(),
+            FAULT_RESPONSE,
+            None,
+            &process_management_capability,
+        )
+    );
+    let process_loader_driver = static_init!(
+        capsules::process_loader::ProcessLoaderDriver<'static>,
+        capsules::process_loader::ProcessLoaderDriver::new(process_loader)
+    );
+
+    let process_manager = static_init!(
+        kernel::procs::ProcessManager,
+        kernel::procs::ProcessManager::new(&process_management_capability)
+    );
+    let process_manager_driver = static_init!(
+        capsules::process_manager::ProcessManagerDriver<'static>,
+        capsules::process_manager::ProcessManagerDriver::new(
+            process_manager,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
+    );
+
+    // Lets a privileged app upload the record of the last kernel panic.
+    let crash_record = static_init!(
+        capsules::crash_record::CrashRecordDriver,
+        capsules::crash_record::CrashRecordDriver::new(kernel::Grant::create(
+            &memory_allocation_capability
+        ))
+    );
+
+    let syscall_trace_driver = static_init!(
+        capsules::syscall_trace::SyscallTraceDriver<'static>,
+        capsules::syscall_trace::SyscallTraceDriver::new(
+            syscall_trace,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
+    );
+
     let hail = Hail {
         console: console,
         gpio: gpio,
@@ -461,9 +601,13 @@ pub unsafe fn reset_handler() {
         led: led,
         button: button,
         rng: rng,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(&memory_allocation_capability),
         crc: crc,
         dac: dac,
+        process_loader: process_loader_driver,
+        process_manager: process_manager_driver,
+        crash_record: crash_record,
+        syscall_trace: syscall_trace_driver,
     };
 
     // Need to reset the nRF on boot
@@ -479,7 +623,7 @@ pub unsafe fn reset_handler() {
     hail.console.initialize();
     // Attach the kernel debug interface to this console
     let kc = static_init!(capsules::console::App, capsules::console::App::default());
-    kernel::debug::assign_console_driver(Some(hail.console), kc);
+    kernel::debug::assign_console_driver(Some(hail.console), kc, &kernel_configuration_capability);
 
     hail.nrf51822.initialize();
 
@@ -488,14 +632,13 @@ pub unsafe fn reset_handler() {
 
     // debug!("Initialization complete. Entering main loop");
 
-    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new());
-
-    extern "C" {
-        /// Beginning of the ROM region containing app images.
-        ///
-        /// This symbol is defined in the linker script.
-        static _sapps: u8;
-    }
+    // Do not start apps whose SHA-256 digest footer does not match. Apps
+    // without a digest still start, so that apps built without footers keep
+    // working.
+    let app_verifier = static_init!(
+        capsules::sha256_verifier::Sha256Verifier,
+        capsules::sha256_verifier::Sha256Verifier::new(false)
+    );
 
     kernel::procs::load_processes(
         board_kernel,
@@ -503,6 +646,16 @@ pub unsafe fn reset_handler() {
         &mut APP_MEMORY,
         &mut PROCESSES,
         FAULT_RESPONSE,
+        Some(app_verifier),
+        &process_management_capability,
+    );
+    let scheduler = kernel::scheduler::RoundRobin::new();
+    board_kernel.kernel_loop(
+        &hail,
+        &mut chip,
+        &mut PROCESSES,
+        Some(&hail.ipc),
+        &scheduler,
+        &main_loop_capability,
     );
-    board_kernel.kernel_loop(&hail, &mut chip, &mut PROCESSES, Some(&hail.ipc));
 }
@@ -12,7 +12,7 @@
 
 extern crate capsules;
 #[allow(unused_imports)]
-#[macro_use(debug, debug_gpio, static_init)]
+#[macro_use(debug, debug_gpio, static_init, create_capability)]
 extern crate kernel;
 extern crate cortexm4;
 extern crate sam4l;
@@ -24,6 +24,7 @@ use capsules::rf233::RF233;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
+use kernel::capabilities;
 use kernel::hil;
 use kernel::hil::radio;
 use kernel::hil::radio::{RadioConfig, RadioData};
@@ -105,6 +106,7 @@ struct Imix {
         sam4l::usart::USART,
     >,
     nonvolatile_storage: &'static capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    crash_record: &'static capsules::crash_record::CrashRecordDriver,
 }
 
 // The RF233 radio stack requires our buffers for its SPI operations:
@@ -153,6 +155,7 @@ impl kernel::Platform for Imix {
             capsules::ieee802154::DRIVER_NUM => f(Some(self.radio_driver)),
             capsules::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
+            capsules::crash_record::DRIVER_NUM => f(Some(self.crash_record)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -234,6 +237,15 @@ unsafe fn set_pin_primary_functions() {
 /// execution begins here.
 #[no_mangle]
 pub unsafe fn reset_handler() {
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let kernel_configuration_capability =
+        create_capability!(capabilities::KernelConfigurationCapability);
+
     sam4l::init();
 
     sam4l::pm::PM.setup_system_clock(sam4l::pm::SystemClockSource::PllExternalOscillatorAt48MHz {
@@ -262,7 +274,7 @@ pub unsafe fn reset_handler() {
             115200,
             &mut capsules::console::WRITE_BUF,
             &mut capsules::console::READ_BUF,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     hil::uart::UART::set_client(&sam4l::usart::USART3, console);
@@ -270,7 +282,7 @@ pub unsafe fn reset_handler() {
 
     // Attach the kernel debug interface to this console
     let kc = static_init!(capsules::console::App, capsules::console::App::default());
-    kernel::debug::assign_console_driver(Some(console), kc);
+    kernel::debug::assign_console_driver(Some(console), kc, &kernel_configuration_capability);
 
     // Create the Nrf51822Serialization driver for passing BLE commands
     // over UART to the nRF51822 radio.
@@ -284,6 +296,25 @@ pub unsafe fn reset_handler() {
     );
     hil::uart::UART::set_client(&sam4l::usart::USART2, nrf_serialization);
 
+    // A text console on USART0 for listing, stopping and starting processes.
+    let process_manager = static_init!(
+        kernel::procs::ProcessManager,
+        kernel::procs::ProcessManager::new(&process_management_capability)
+    );
+    let process_console = static_init!(
+        capsules::process_console::ProcessConsole<'static, sam4l::usart::USART>,
+        capsules::process_console::ProcessConsole::new(
+            &sam4l::usart::USART0,
+            115200,
+            process_manager,
+            &mut capsules::process_console::WRITE_BUF,
+            &mut capsules::process_console::READ_BUF,
+            &mut capsules::process_console::COMMAND_BUF
+        )
+    );
+    hil::uart::UART::set_client(&sam4l::usart::USART0, process_console);
+    process_console.start();
+
     // # TIMER
 
     let ast = &sam4l::ast::AST;
@@ -300,7 +331,10 @@ pub unsafe fn reset_handler() {
     );
     let alarm = static_init!(
         AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
-        AlarmDriver::new(virtual_alarm1, kernel::Grant::create())
+        AlarmDriver::new(
+            virtual_alarm1,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     virtual_alarm1.set_client(alarm);
 
@@ -328,7 +362,10 @@ pub unsafe fn reset_handler() {
 
     let ambient_light = static_init!(
         capsules::ambient_light::AmbientLight<'static>,
-        capsules::ambient_light::AmbientLight::new(isl29035, kernel::Grant::create())
+        capsules::ambient_light::AmbientLight::new(
+            isl29035,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     hil::sensors::AmbientLight::set_client(isl29035, ambient_light);
 
@@ -374,12 +411,18 @@ pub unsafe fn reset_handler() {
     si7021_alarm.set_client(si7021);
     let temp = static_init!(
         capsules::temperature::TemperatureSensor<'static>,
-        capsules::temperature::TemperatureSensor::new(si7021, kernel::Grant::create())
+        capsules::temperature::TemperatureSensor::new(
+            si7021,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     kernel::hil::sensors::TemperatureDriver::set_client(si7021, temp);
     let humidity = static_init!(
         capsules::humidity::HumiditySensor<'static>,
-        capsules::humidity::HumiditySensor::new(si7021, kernel::Grant::create())
+        capsules::humidity::HumiditySensor::new(
+            si7021,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     kernel::hil::sensors::HumidityDriver::set_client(si7021, humidity);
 
@@ -415,7 +458,10 @@ pub unsafe fn reset_handler() {
     sam4l::gpio::PC[13].set_client(fxos8700);
     let ninedof = static_init!(
         capsules::ninedof::NineDof<'static>,
-        capsules::ninedof::NineDof::new(fxos8700, kernel::Grant::create())
+        capsules::ninedof::NineDof::new(
+            fxos8700,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     hil::sensors::NineDof::set_client(fxos8700, ninedof);
 
@@ -501,7 +547,10 @@ pub unsafe fn reset_handler() {
 
     let button = static_init!(
         capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
-        capsules::button::Button::new(button_pins, kernel::Grant::create())
+        capsules::button::Button::new(
+            button_pins,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
@@ -509,7 +558,10 @@ pub unsafe fn reset_handler() {
 
     let crc = static_init!(
         capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
-        capsules::crc::Crc::new(&mut sam4l::crccu::CRCCU, kernel::Grant::create())
+        capsules::crc::Crc::new(
+            &mut sam4l::crccu::CRCCU,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
 
     rf233_spi.set_client(rf233);
@@ -556,7 +608,11 @@ pub unsafe fn reset_handler() {
 
     let radio_driver = static_init!(
         capsules::ieee802154::RadioDriver<'static>,
-        capsules::ieee802154::RadioDriver::new(radio_mac, kernel::Grant::create(), &mut RADIO_BUF)
+        capsules::ieee802154::RadioDriver::new(
+            radio_mac,
+            kernel::Grant::create(&memory_allocation_capability),
+            &mut RADIO_BUF
+        )
     );
 
     mac_device.set_key_procedure(radio_driver);
@@ -579,7 +635,10 @@ pub unsafe fn reset_handler() {
             'static,
             capsules::usbc_client::Client<'static, sam4l::usbc::Usbc<'static>>,
         >,
-        capsules::usb_user::UsbSyscallDriver::new(usb_client, kernel::Grant::create())
+        capsules::usb_user::UsbSyscallDriver::new(
+            usb_client,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
 
     sam4l::flashcalw::FLASH_CONTROLLER.configure();
@@ -598,7 +657,7 @@ pub unsafe fn reset_handler() {
         capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
         capsules::nonvolatile_storage_driver::NonvolatileStorage::new(
             nv_to_page,
-            kernel::Grant::create(),
+            kernel::Grant::create(&memory_allocation_capability),
             0x60000, // Start address for userspace accessible region
             0x20000, // Length of userspace accessible region
             0,       // Start address of kernel accessible region
@@ -608,6 +667,14 @@ pub unsafe fn reset_handler() {
     );
     hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, nonvolatile_storage);
 
+    // Lets a privileged app upload the record of the last kernel panic.
+    let crash_record = static_init!(
+        capsules::crash_record::CrashRecordDriver,
+        capsules::crash_record::CrashRecordDriver::new(kernel::Grant::create(
+            &memory_allocation_capability
+        ))
+    );
+
     let imix = Imix {
         console: console,
         alarm: alarm,
@@ -620,12 +687,13 @@ pub unsafe fn reset_handler() {
         button: button,
         crc: crc,
         spi: spi_syscalls,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(&memory_allocation_capability),
         ninedof: ninedof,
         radio_driver: radio_driver,
         usb_driver: usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage: nonvolatile_storage,
+        crash_record: crash_record,
     };
 
     let mut chip = sam4l::chip::Sam4l::new();
@@ -651,6 +719,19 @@ pub unsafe fn reset_handler() {
 
     let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new());
 
+    // Lets `FaultResponse::RestartWithBackoff` wait before restarting a
+    // process that faulted.
+    let restart_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let restart_timer = static_init!(
+        kernel::procs::AlarmRestartTimer<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+        kernel::procs::AlarmRestartTimer::new(restart_alarm)
+    );
+    restart_alarm.set_client(restart_timer);
+    board_kernel.set_restart_timer(restart_timer, &kernel_configuration_capability);
+
     extern "C" {
         /// Beginning of the ROM region containing app images.
         static _sapps: u8;
@@ -661,7 +742,17 @@ pub unsafe fn reset_handler() {
         &mut APP_MEMORY,
         &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
+        &process_management_capability,
     );
 
-    board_kernel.kernel_loop(&imix, &mut chip, &mut PROCESSES, Some(&imix.ipc));
+    let scheduler = kernel::scheduler::RoundRobin::new();
+    board_kernel.kernel_loop(
+        &imix,
+        &mut chip,
+        &mut PROCESSES,
+        Some(&imix.ipc),
+        &scheduler,
+        &main_loop_capability,
+    );
 }
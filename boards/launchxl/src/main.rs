@@ -9,11 +9,12 @@ extern crate cc26x2;
 extern crate cc26xx;
 
 #[allow(unused_imports)]
-#[macro_use(debug, debug_gpio, static_init)]
+#[macro_use(debug, debug_gpio, static_init, create_capability)]
 extern crate kernel;
 
 use cc26xx::aon;
 use cc26xx::prcm;
+use kernel::capabilities;
 
 #[macro_use]
 pub mod io;
@@ -66,6 +67,15 @@ impl kernel::Platform for Platform {
 
 #[no_mangle]
 pub unsafe fn reset_handler() {
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let kernel_configuration_capability =
+        create_capability!(capabilities::KernelConfigurationCapability);
+
     cc26x2::init();
 
     // Setup AON event defaults
@@ -118,7 +128,10 @@ pub unsafe fn reset_handler() {
     );
     let button = static_init!(
         capsules::button::Button<'static, cc26xx::gpio::GPIOPin>,
-        capsules::button::Button::new(button_pins, kernel::Grant::create())
+        capsules::button::Button::new(
+            button_pins,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
@@ -133,7 +146,7 @@ pub unsafe fn reset_handler() {
             115200,
             &mut capsules::console::WRITE_BUF,
             &mut capsules::console::READ_BUF,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     kernel::hil::uart::UART::set_client(&cc26xx::uart::UART0, console);
@@ -141,7 +154,7 @@ pub unsafe fn reset_handler() {
 
     // Attach the kernel debug interface to this console
     let kc = static_init!(capsules::console::App, capsules::console::App::default());
-    kernel::debug::assign_console_driver(Some(console), kc);
+    kernel::debug::assign_console_driver(Some(console), kc, &kernel_configuration_capability);
 
     // Setup for remaining GPIO pins
     let gpio_pins = static_init!(
@@ -197,13 +210,19 @@ pub unsafe fn reset_handler() {
             'static,
             capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26xx::rtc::Rtc>,
         >,
-        capsules::alarm::AlarmDriver::new(virtual_alarm1, kernel::Grant::create())
+        capsules::alarm::AlarmDriver::new(
+            virtual_alarm1,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     virtual_alarm1.set_client(alarm);
 
     let rng = static_init!(
         capsules::rng::SimpleRng<'static, cc26xx::trng::Trng>,
-        capsules::rng::SimpleRng::new(&cc26xx::trng::TRNG, kernel::Grant::create())
+        capsules::rng::SimpleRng::new(
+            &cc26xx::trng::TRNG,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     cc26xx::trng::TRNG.set_client(rng);
 
@@ -231,12 +250,17 @@ pub unsafe fn reset_handler() {
         &mut APP_MEMORY,
         &mut PROCESSES,
         FAULT_RESPONSE,
+        None,
+        &process_management_capability,
     );
 
+    let scheduler = kernel::scheduler::RoundRobin::new();
     board_kernel.kernel_loop(
         &launchxl,
         &mut chip,
         &mut PROCESSES,
-        Some(&kernel::ipc::IPC::new()),
+        Some(&kernel::ipc::IPC::new(&memory_allocation_capability)),
+        &scheduler,
+        &main_loop_capability,
     );
 }
@@ -4,13 +4,14 @@
 
 extern crate capsules;
 #[allow(unused_imports)]
-#[macro_use(debug, debug_verbose, debug_gpio, static_init)]
+#[macro_use(debug, debug_verbose, debug_gpio, static_init, create_capability)]
 extern crate kernel;
 extern crate nrf52;
 extern crate nrf5x;
 
 use capsules::virtual_alarm::VirtualMuxAlarm;
 use capsules::virtual_spi::MuxSpiMaster;
+use kernel::capabilities;
 use kernel::hil;
 use nrf5x::rtc::Rtc;
 
@@ -76,6 +77,15 @@ pub unsafe fn setup_board(
     app_fault_response: kernel::procs::FaultResponse,
     has_mx25r6435f: bool,
 ) {
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let kernel_configuration_capability =
+        create_capability!(capabilities::KernelConfigurationCapability);
+
     // Make non-volatile memory writable and activate the reset button
     let uicr = nrf52::uicr::Uicr::new();
     nrf52::nvmc::NVMC.erase_uicr();
@@ -109,7 +119,10 @@ pub unsafe fn setup_board(
     // Buttons
     let button = static_init!(
         capsules::button::Button<'static, nrf5x::gpio::GPIOPin>,
-        capsules::button::Button::new(button_pins, kernel::Grant::create())
+        capsules::button::Button::new(
+            button_pins,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     for &(btn, _) in button_pins.iter() {
         use kernel::hil::gpio::PinCtl;
@@ -134,7 +147,10 @@ pub unsafe fn setup_board(
             'static,
             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
         >,
-        capsules::alarm::AlarmDriver::new(virtual_alarm1, kernel::Grant::create())
+        capsules::alarm::AlarmDriver::new(
+            virtual_alarm1,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     virtual_alarm1.set_client(alarm);
     let ble_radio_virtual_alarm = static_init!(
@@ -155,7 +171,7 @@ pub unsafe fn setup_board(
             115200,
             &mut capsules::console::WRITE_BUF,
             &mut capsules::console::READ_BUF,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     kernel::hil::uart::UART::set_client(&nrf52::uart::UARTE0, console);
@@ -163,7 +179,7 @@ pub unsafe fn setup_board(
 
     // Attach the kernel debug interface to this console
     let kc = static_init!(capsules::console::App, capsules::console::App::default());
-    kernel::debug::assign_console_driver(Some(console), kc);
+    kernel::debug::assign_console_driver(Some(console), kc, &kernel_configuration_capability);
 
     let ble_radio = static_init!(
         capsules::ble_advertising_driver::BLE<
@@ -173,7 +189,7 @@ pub unsafe fn setup_board(
         >,
         capsules::ble_advertising_driver::BLE::new(
             &mut nrf52::radio::RADIO,
-            kernel::Grant::create(),
+            kernel::Grant::create(&memory_allocation_capability),
             &mut capsules::ble_advertising_driver::BUF,
             ble_radio_virtual_alarm
         )
@@ -192,14 +208,17 @@ pub unsafe fn setup_board(
         capsules::temperature::TemperatureSensor<'static>,
         capsules::temperature::TemperatureSensor::new(
             &mut nrf5x::temperature::TEMP,
-            kernel::Grant::create()
+            kernel::Grant::create(&memory_allocation_capability)
         )
     );
     kernel::hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, temp);
 
     let rng = static_init!(
         capsules::rng::SimpleRng<'static, nrf5x::trng::Trng>,
-        capsules::rng::SimpleRng::new(&mut nrf5x::trng::TRNG, kernel::Grant::create())
+        capsules::rng::SimpleRng::new(
+            &mut nrf5x::trng::TRNG,
+            kernel::Grant::create(&memory_allocation_capability)
+        )
     );
     nrf5x::trng::TRNG.set_client(rng);
 
@@ -272,7 +291,7 @@ pub unsafe fn setup_board(
             capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
             capsules::nonvolatile_storage_driver::NonvolatileStorage::new(
                 nv_to_page,
-                kernel::Grant::create(),
+                kernel::Grant::create(&memory_allocation_capability),
                 0x60000, // Start address for userspace accessible region
                 0x20000, // Length of userspace accessible region
                 0,       // Start address of kernel accessible region
@@ -308,7 +327,7 @@ pub unsafe fn setup_board(
         temp: temp,
         alarm: alarm,
         nonvolatile_storage: nonvolatile_storage,
-        ipc: kernel::ipc::IPC::new(),
+        ipc: kernel::ipc::IPC::new(&memory_allocation_capability),
     };
 
     let mut chip = nrf52::chip::NRF52::new();
@@ -328,7 +347,17 @@ pub unsafe fn setup_board(
         app_memory,
         process_pointers,
         app_fault_response,
+        None,
+        &process_management_capability,
     );
 
-    board_kernel.kernel_loop(&platform, &mut chip, process_pointers, Some(&platform.ipc));
+    let scheduler = kernel::scheduler::RoundRobin::new();
+    board_kernel.kernel_loop(
+        &platform,
+        &mut chip,
+        process_pointers,
+        Some(&platform.ipc),
+        &scheduler,
+        &main_loop_capability,
+    );
 }
@@ -6,7 +6,7 @@
 //! let light = static_init!(
 //!     capsules::sensors::AmbientLight<'static>,
 //!     capsules::sensors::AmbientLight::new(isl29035,
-//!         kernel::Grant::create()));
+//!         kernel::Grant::create(&memory_allocation_capability)));
 //! hil::sensors::AmbientLight::set_client(isl29035, ambient_light);
 //! ```
 
@@ -17,7 +17,7 @@
 //! let app_flash = static_init!(
 //!     capsules::app_flash_driver::AppFlash<'static>,
 //!     capsules::app_flash_driver::AppFlash::new(nv_to_page,
-//!         kernel::Grant::create(), &mut APP_FLASH_BUFFER));
+//!         kernel::Grant::create(&memory_allocation_capability), &mut APP_FLASH_BUFFER));
 //! ```
 
 use core::cell::Cell;
@@ -68,7 +68,7 @@
 //!     <'static, nrf52::radio::Radio, VirtualMuxAlarm<'static, Rtc>>,
 //!     nrf5x::ble_advertising_driver::BLE::new(
 //!         &mut nrf52::radio::RADIO,
-//!     kernel::Grant::create(),
+//!     kernel::Grant::create(&memory_allocation_capability),
 //!         &mut nrf5x::ble_advertising_driver::BUF,
 //!         ble_radio_virtual_alarm));
 //!    nrf5x::ble_advertising_hil::BleAdvertisementDriver::set_rx_client(&nrf52::radio::RADIO,
@@ -13,7 +13,7 @@
 //!     [&sam4l::gpio::PA[16]]);
 //! let button = static_init!(
 //!     capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
-//!     capsules::button::Button::new(button_pins, kernel::Grant::create()));
+//!     capsules::button::Button::new(button_pins, kernel::Grant::create(&memory_allocation_capability)));
 //! for btn in button_pins.iter() {
 //!     btn.set_client(button);
 //! }
@@ -12,7 +12,7 @@
 //!                  115200,
 //!                  &mut console::WRITE_BUF,
 //!                  &mut console::READ_BUF,
-//!                  kernel::Grant::create()));
+//!                  kernel::Grant::create(&memory_allocation_capability)));
 //! hil::uart::UART::set_client(&usart::USART0, console);
 //! ```
 //!
@@ -0,0 +1,145 @@
+//! Lets a privileged app upload the crash record of the last kernel panic.
+//!
+//! When the kernel panics it saves a crash record that survives the reboot
+//! that follows. This driver copies the record, as text, to an app, for
+//! example one that sends it over the network, and lets it clear the record
+//! once it has been uploaded.
+//!
+//! Only privileged apps may use this driver, see `capsules::privileged`.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let crash_record_driver = static_init!(
+//!     capsules::crash_record::CrashRecordDriver,
+//!     capsules::crash_record::CrashRecordDriver::new(
+//!         kernel::Grant::create(&memory_allocation_capability)));
+//! ```
+
+use core::fmt::{self, Write};
+use kernel::debug;
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+use privileged::{self, PrivilegedBuffers};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10004;
+
+pub struct CrashRecordDriver {
+    buffers: PrivilegedBuffers,
+}
+
+/// Writes the part of a text that starts at byte `skip` into a buffer, and
+/// counts the bytes of the whole text.
+struct ChunkWriter<'a> {
+    buffer: &'a mut [u8],
+    skip: usize,
+    copied: usize,
+    total: usize,
+}
+
+impl Write for ChunkWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.total >= self.skip && self.copied < self.buffer.len() {
+                self.buffer[self.copied] = byte;
+                self.copied += 1;
+            }
+            self.total += 1;
+        }
+        Ok(())
+    }
+}
+
+impl CrashRecordDriver {
+    pub fn new(grant: Grant<privileged::App>) -> CrashRecordDriver {
+        CrashRecordDriver {
+            buffers: PrivilegedBuffers::new(DRIVER_NUM, grant),
+        }
+    }
+
+    /// Length in bytes of the record as text, or `0` if there is none.
+    fn record_len(&self) -> usize {
+        debug::crash_record().map_or(0, |record| {
+            let mut writer = ChunkWriter {
+                buffer: &mut [],
+                skip: 0,
+                copied: 0,
+                total: 0,
+            };
+            let _ = record.write_to(&mut writer);
+            writer.total
+        })
+    }
+
+    /// Copy as much of the record as fits in the buffer of the calling app,
+    /// starting at byte `offset`. Returns how many bytes were copied.
+    fn copy_record(&self, offset: usize, appid: AppId) -> ReturnCode {
+        let record = match debug::crash_record() {
+            Some(record) => record,
+            None => return ReturnCode::FAIL,
+        };
+        self.buffers.with_buffer(appid, |buffer| {
+            let mut writer = ChunkWriter {
+                buffer: buffer.as_mut(),
+                skip: offset,
+                copied: 0,
+                total: 0,
+            };
+            let _ = record.write_to(&mut writer);
+            ReturnCode::SuccessWithValue {
+                value: writer.copied,
+            }
+        })
+    }
+}
+
+impl Driver for CrashRecordDriver {
+    /// Share the buffer the record is copied to.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The buffer.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        self.buffers.allow(appid, allow_num, slice)
+    }
+
+    /// Read and clear the crash record.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Length in bytes of the record as text, or `0` if there is no
+    ///        record.
+    /// - `2`: Copy the record to the shared buffer, starting at byte `data`.
+    ///        Returns how many bytes were copied, or `FAIL` if there is no
+    ///        record.
+    /// - `3`: Clear the record.
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        if !privileged::command_permitted(DRIVER_NUM, command_num, appid) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => ReturnCode::SuccessWithValue {
+                value: self.record_len(),
+            },
+
+            2 => self.copy_record(data, appid),
+
+            3 => {
+                debug::clear_crash_record();
+                ReturnCode::SUCCESS
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
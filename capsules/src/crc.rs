@@ -10,7 +10,7 @@
 //! ```rust
 //! let crc = static_init!(
 //!     capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
-//!     capsules::crc::Crc::new(&mut sam4l::crccu::CRCCU, kernel::Grant::create()));
+//!     capsules::crc::Crc::new(&mut sam4l::crccu::CRCCU, kernel::Grant::create(&memory_allocation_capability)));
 //! sam4l::crccu::CRCCU.set_client(crc);
 //!
 //! ```
@@ -103,7 +103,7 @@ impl<C: hil::crc::CRC> Crc<'a, C> {
     /// ## Example
     ///
     /// ```
-    /// capsules::crc::Crc::new(&sam4l::crccu::CRCCU, kernel::Grant::create()),
+    /// capsules::crc::Crc::new(&sam4l::crccu::CRCCU, kernel::Grant::create(&memory_allocation_capability)),
     ///
     /// ```
     ///
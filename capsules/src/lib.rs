@@ -5,7 +5,7 @@
 #![no_std]
 
 #[allow(unused_imports)]
-#[macro_use(debug)]
+#[macro_use(debug, log, log_trace)]
 extern crate kernel;
 
 pub mod test;
@@ -21,6 +21,7 @@ pub mod app_flash_driver;
 pub mod ble_advertising_driver;
 pub mod button;
 pub mod console;
+pub mod crash_record;
 pub mod crc;
 pub mod dac;
 pub mod fm25cl;
@@ -42,13 +43,19 @@ pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
 pub mod pca9544a;
+pub mod privileged;
+pub mod process_console;
+pub mod process_loader;
+pub mod process_manager;
 pub mod rf233;
 pub mod rf233_const;
 pub mod rng;
 pub mod sdcard;
 pub mod segger_rtt;
+pub mod sha256_verifier;
 pub mod si7021;
 pub mod spi;
+pub mod syscall_trace;
 pub mod temperature;
 pub mod tmp006;
 pub mod tsl2561;
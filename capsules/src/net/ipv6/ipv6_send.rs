@@ -193,7 +193,7 @@ impl IP6SendStruct<'a> {
 impl TxClient for IP6SendStruct<'a> {
     fn send_done(&self, tx_buf: &'static mut [u8], acked: bool, result: ReturnCode) {
         self.tx_buf.replace(tx_buf);
-        debug!("sendDone return code is: {:?}, acked: {}", result, acked);
+        log_trace!("sendDone return code is: {:?}, acked: {}", result, acked);
         //The below code introduces a delay between frames to prevent
         // a race condition on the receiver
         //it is sorta complicated bc I was having some trouble with dead code eliminationa
@@ -204,7 +204,7 @@ impl TxClient for IP6SendStruct<'a> {
             array[i % 100] = (i % 100) as u8;
             i = i + 1;
             if i % 100000 == 0 {
-                debug!("Delay, step {:?}", i / 100000);
+                log_trace!("Delay, step {:?}", i / 100000);
             }
         }
         let result = self.send_next_fragment();
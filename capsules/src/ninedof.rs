@@ -8,7 +8,7 @@
 //! ```rust
 //! let ninedof = static_init!(
 //!     capsules::ninedof::NineDof<'static>,
-//!     capsules::ninedof::NineDof::new(fxos8700, kernel::Grant::create()));
+//!     capsules::ninedof::NineDof::new(fxos8700, kernel::Grant::create(&memory_allocation_capability)));
 //! hil::sensors::NineDof::set_client(fxos8700, ninedof);
 //! ```
 
@@ -0,0 +1,86 @@
+//! Common parts of drivers that only privileged apps may use.
+//!
+//! A privileged app is one whose TBF header explicitly permits it to use the
+//! driver, as described on `AppId::has_explicit_driver_permission`. Every app
+//! may still call command `0` to find out whether the driver exists.
+//!
+//! Drivers that pass data to the app do so through one buffer it shares with
+//! allow `0`, which `PrivilegedBuffers` keeps for them:
+//!
+//! ```
+//! fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>)
+//!     -> ReturnCode {
+//!     self.buffers.allow(appid, allow_num, slice)
+//! }
+//!
+//! fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+//!     if !privileged::command_permitted(DRIVER_NUM, command_num, appid) {
+//!         return ReturnCode::ENOPERM;
+//!     }
+//!     ...
+//! }
+//! ```
+
+use kernel::{AppId, AppSlice, Grant, ReturnCode, Shared};
+
+/// Whether `appid` may call command `command_num` of the privileged driver
+/// `driver_num`.
+pub fn command_permitted(driver_num: usize, command_num: usize, appid: AppId) -> bool {
+    command_num == 0 || appid.has_explicit_driver_permission(driver_num)
+}
+
+#[derive(Default)]
+pub struct App {
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+/// The buffers privileged apps share with the driver `driver_num`.
+pub struct PrivilegedBuffers {
+    driver_num: usize,
+    apps: Grant<App>,
+}
+
+impl PrivilegedBuffers {
+    pub fn new(driver_num: usize, grant: Grant<App>) -> PrivilegedBuffers {
+        PrivilegedBuffers {
+            driver_num: driver_num,
+            apps: grant,
+        }
+    }
+
+    /// Keep the buffer a privileged app shares with allow `0`.
+    pub fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        if !appid.has_explicit_driver_permission(self.driver_num) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                })
+                .unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Call `f` with the buffer `appid` shared, or return `ERESERVE` if it has
+    /// not shared one.
+    pub fn with_buffer<F>(&self, appid: AppId, f: F) -> ReturnCode
+    where
+        F: FnOnce(&mut AppSlice<Shared, u8>) -> ReturnCode,
+    {
+        self.apps
+            .enter(appid, |app, _| {
+                app.buffer.as_mut().map_or(ReturnCode::ERESERVE, f)
+            })
+            .unwrap_or_else(|err| err.into())
+    }
+}
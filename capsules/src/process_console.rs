@@ -0,0 +1,408 @@
+//! Text console on a UART for inspecting and controlling processes.
+//!
+//! The process console lets someone with a serial terminal see which
+//! processes are running and stop, start or fault them, without attaching a
+//! debugger. It needs a UART of its own, since the console capsule that apps
+//! use for `printf` expects to own its UART.
+//!
+//! Setup
+//! -----
+//!
+//! ```rust
+//! let process_console = static_init!(
+//!     capsules::process_console::ProcessConsole<'static, usart::USART>,
+//!     capsules::process_console::ProcessConsole::new(
+//!         &usart::USART2,
+//!         115200,
+//!         process_manager,
+//!         &mut capsules::process_console::WRITE_BUF,
+//!         &mut capsules::process_console::READ_BUF,
+//!         &mut capsules::process_console::COMMAND_BUF
+//!     )
+//! );
+//! hil::uart::UART::set_client(&usart::USART2, process_console);
+//! process_console.start();
+//! ```
+//!
+//! Commands
+//! --------
+//!
+//! - `help`: List the commands.
+//! - `list`: Show the index, name, state, restart count, syscall count and
+//!   CPU time of each process.
+//! - `status <name>`: Show the memory layout and registers of a process.
+//! - `stop <name>`: Stop a process.
+//! - `start <name>`: Start a stopped or faulted process.
+//! - `fault <name>`: Make a process fault, which is then handled with the
+//!   board's fault response.
+//! - `panic-info`: Show what the kernel prints when it panics: the cause of
+//!   the last fault and the status of every process.
+//!
+//! Typed characters are echoed only when the UART is not busy. A command typed
+//! while the output of the last one is still being sent runs once it is done.
+//! The status of a process can be longer than `WRITE_BUF`, so it is sent in
+//! as many transmissions as it needs.
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::str;
+use kernel::common::cells::TakeCell;
+use kernel::hil::uart::{self, Client, UART};
+use kernel::procs::{ManagementError, ProcessManager, State};
+
+pub static mut WRITE_BUF: [u8; 2048] = [0; 2048];
+pub static mut READ_BUF: [u8; 1] = [0; 1];
+pub static mut COMMAND_BUF: [u8; 32] = [0; 32];
+
+const PROMPT: &str = "tock$ ";
+
+/// Output that did not fit in one transmission.
+#[derive(Clone, Copy, PartialEq)]
+enum PendingOutput {
+    None,
+    /// `panic-info` still has to print the processes from this index on.
+    PanicInfo(usize),
+    /// The status of process `app_idx` still has to be sent from byte
+    /// `offset` on. For `panic-info` the next processes follow it, otherwise
+    /// the prompt does.
+    Status {
+        app_idx: usize,
+        offset: usize,
+        panic_info: bool,
+    },
+}
+
+/// `fmt::Write` into a buffer. The first `skip` bytes written are dropped,
+/// which lets output that was partly sent be written again from where it
+/// stopped, and so is whatever does not fit.
+struct BufferWriter<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+    skip: usize,
+    /// Some output did not fit.
+    full: bool,
+}
+
+impl Write for BufferWriter<'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.skip > 0 {
+                self.skip -= 1;
+            } else if self.len < self.buffer.len() {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.full = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct ProcessConsole<'a, U: UART + 'a> {
+    uart: &'a U,
+    baud_rate: u32,
+    manager: &'a ProcessManager,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    command_buffer: TakeCell<'static, [u8]>,
+    command_len: Cell<usize>,
+    /// A full command line was typed and waits for the UART to be free.
+    command_ready: Cell<bool>,
+    pending_output: Cell<PendingOutput>,
+}
+
+impl<U: UART> ProcessConsole<'a, U> {
+    pub fn new(
+        uart: &'a U,
+        baud_rate: u32,
+        manager: &'a ProcessManager,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        command_buffer: &'static mut [u8],
+    ) -> ProcessConsole<'a, U> {
+        ProcessConsole {
+            uart: uart,
+            baud_rate: baud_rate,
+            manager: manager,
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            command_buffer: TakeCell::new(command_buffer),
+            command_len: Cell::new(0),
+            command_ready: Cell::new(false),
+            pending_output: Cell::new(PendingOutput::None),
+        }
+    }
+
+    /// Set up the UART, print the prompt and wait for commands.
+    pub fn start(&self) {
+        self.uart.init(uart::UARTParams {
+            baud_rate: self.baud_rate,
+            stop_bits: uart::StopBits::One,
+            parity: uart::Parity::None,
+            hw_flow_control: false,
+        });
+        self.write(|writer| {
+            let _ = writer.write_str("Tock process console. Type help for the commands.\r\n");
+            let _ = writer.write_str(PROMPT);
+        });
+        self.rx_buffer.take().map(|buffer| {
+            self.uart.receive(buffer, 1);
+        });
+    }
+
+    /// Transmit what `fun` writes, if the UART is not busy. Returns whether
+    /// anything was sent.
+    fn write<F>(&self, fun: F) -> bool
+    where
+        F: FnOnce(&mut BufferWriter),
+    {
+        self.write_from(0, fun).is_some()
+    }
+
+    /// Transmit what `fun` writes from byte `offset` on, if the UART is not
+    /// busy. Returns how many bytes were sent and whether that was all.
+    fn write_from<F>(&self, offset: usize, fun: F) -> Option<(usize, bool)>
+    where
+        F: FnOnce(&mut BufferWriter),
+    {
+        self.tx_buffer.take().map(|buffer| {
+            let (len, full) = {
+                let mut writer = BufferWriter {
+                    buffer: buffer,
+                    len: 0,
+                    skip: offset,
+                    full: false,
+                };
+                fun(&mut writer);
+                (writer.len, writer.full)
+            };
+            self.uart.transmit(buffer, len);
+            (len, !full)
+        })
+    }
+
+    /// Handle one character typed on the terminal.
+    fn handle_char(&self, c: u8) {
+        if self.command_ready.get() {
+            return;
+        }
+
+        match c {
+            b'\r' | b'\n' => {
+                self.command_ready.set(true);
+                self.run_command();
+            }
+            // Backspace or delete.
+            0x08 | 0x7F => {
+                if self.command_len.get() > 0 {
+                    self.command_len.set(self.command_len.get() - 1);
+                    self.write(|writer| {
+                        let _ = writer.write_str("\x08 \x08");
+                    });
+                }
+            }
+            0x20...0x7E => {
+                let len = self.command_len.get();
+                let stored = self.command_buffer.map_or(false, |command| {
+                    if len < command.len() {
+                        command[len] = c;
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if stored {
+                    self.command_len.set(len + 1);
+                    self.write(|writer| {
+                        let _ = writer.write_char(c as char);
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the typed command line, once the UART is free to print its output.
+    fn run_command(&self) {
+        if !self.command_ready.get() {
+            return;
+        }
+        let command_buffer = match self.command_buffer.take() {
+            Some(command_buffer) => command_buffer,
+            None => return,
+        };
+
+        let sent = {
+            let line = str::from_utf8(&command_buffer[..self.command_len.get()]).unwrap_or("");
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next().unwrap_or("");
+            self.write(|writer| {
+                let _ = writer.write_str("\r\n");
+                self.execute(command, argument, writer);
+                if self.pending_output.get() == PendingOutput::None {
+                    let _ = writer.write_str(PROMPT);
+                }
+            })
+        };
+        self.command_buffer.replace(command_buffer);
+
+        // If the UART was busy this runs again when the transmission is done.
+        if sent {
+            self.command_len.set(0);
+            self.command_ready.set(false);
+        }
+    }
+
+    fn execute(&self, command: &str, argument: &str, writer: &mut BufferWriter) {
+        match command {
+            "" => {}
+            "help" => {
+                let _ = writer.write_str(
+                    "Commands: help list status stop start fault panic-info\r\n\
+                     status, stop, start and fault take a process name.\r\n",
+                );
+            }
+            "list" => self.list(writer),
+            "status" | "stop" | "start" | "fault" => {
+                let app_idx = match self.manager.find_by_name(argument) {
+                    Some(app_idx) => app_idx,
+                    None => {
+                        let _ = writer.write_fmt(format_args!("No process named {}\r\n", argument));
+                        return;
+                    }
+                };
+                let result = match command {
+                    "status" => {
+                        // Sent from the next transmission on, since it may
+                        // need several.
+                        self.pending_output.set(PendingOutput::Status {
+                            app_idx: app_idx,
+                            offset: 0,
+                            panic_info: false,
+                        });
+                        return;
+                    }
+                    "stop" => self.manager.stop(app_idx),
+                    "start" => self.manager.start(app_idx),
+                    _ => self.manager.fault(app_idx),
+                };
+                let _ = match result {
+                    Ok(()) => writer.write_fmt(format_args!("{} {}: done\r\n", command, argument)),
+                    Err(ManagementError::AlreadyInState) => writer.write_fmt(format_args!(
+                        "{} {}: already in that state\r\n",
+                        command, argument
+                    )),
+                    Err(ManagementError::NoSuchProcess) => writer.write_fmt(format_args!(
+                        "{} {}: no such process\r\n",
+                        command, argument
+                    )),
+                };
+            }
+            "panic-info" => {
+                self.manager.write_fault_info(writer);
+                let _ = writer.write_str("\r\n---| App Status |---\r\n");
+                self.pending_output.set(PendingOutput::PanicInfo(0));
+            }
+            _ => {
+                let _ = writer.write_fmt(format_args!(
+                    "Unknown command {}. Type help for the commands.\r\n",
+                    command
+                ));
+            }
+        }
+    }
+
+    fn list(&self, writer: &mut BufferWriter) {
+        let _ = writer
+            .write_str(" Index  Name                  State    Restarts  Syscalls  CPU (ms)\r\n");
+        for app_idx in 0..self.manager.number_of_slots() {
+            self.manager.info(app_idx).map(|info| {
+                let state = match info.state {
+                    State::Running => "Running",
+                    State::Yielded => "Yielded",
+                    State::Fault => "Fault",
+                    State::Stopped => "Stopped",
+                };
+                let _ = writer.write_fmt(format_args!(
+                    " {:<5}  {:<20}  {:<7}  {:>8}  {:>8}  {:>8}\r\n",
+                    app_idx,
+                    info.name,
+                    state,
+                    info.restart_count,
+                    info.syscall_count,
+                    info.cpu_time_us / 1000
+                ));
+            });
+        }
+    }
+
+    /// Send the next part of output that did not fit in one transmission.
+    fn continue_output(&self) {
+        match self.pending_output.get() {
+            PendingOutput::None => {}
+            PendingOutput::PanicInfo(app_idx) => {
+                // Skip empty slots, so that every transmission prints a process.
+                let slots = self.manager.number_of_slots();
+                match (app_idx..slots).find(|&idx| self.manager.info(idx).is_some()) {
+                    Some(idx) => self.continue_status(idx, 0, true),
+                    None => {
+                        self.write(|writer| {
+                            let _ = writer.write_str(PROMPT);
+                        });
+                        self.pending_output.set(PendingOutput::None);
+                    }
+                }
+            }
+            PendingOutput::Status {
+                app_idx,
+                offset,
+                panic_info,
+            } => self.continue_status(app_idx, offset, panic_info),
+        }
+    }
+
+    /// Send as much of the status of process `app_idx` from byte `offset` on
+    /// as fits in one transmission. The status is written again each time,
+    /// and the part that was already sent skipped.
+    fn continue_status(&self, app_idx: usize, offset: usize, panic_info: bool) {
+        let sent = self.write_from(offset, |writer| {
+            let _ = self.manager.write_statistics(app_idx, writer);
+            if !panic_info {
+                let _ = writer.write_str("\r\n");
+                let _ = writer.write_str(PROMPT);
+            }
+        });
+        let next = match sent {
+            Some((_, true)) if panic_info => PendingOutput::PanicInfo(app_idx + 1),
+            Some((_, true)) => PendingOutput::None,
+            Some((len, false)) => PendingOutput::Status {
+                app_idx: app_idx,
+                offset: offset + len,
+                panic_info: panic_info,
+            },
+            None => return,
+        };
+        self.pending_output.set(next);
+    }
+}
+
+impl<U: UART> Client for ProcessConsole<'a, U> {
+    fn transmit_complete(&self, buffer: &'static mut [u8], _error: uart::Error) {
+        self.tx_buffer.replace(buffer);
+        if self.pending_output.get() != PendingOutput::None {
+            self.continue_output();
+        } else {
+            self.run_command();
+        }
+    }
+
+    fn receive_complete(&self, buffer: &'static mut [u8], rx_len: usize, error: uart::Error) {
+        let c = buffer[0];
+        self.uart.receive(buffer, 1);
+        if error == uart::Error::CommandComplete && rx_len > 0 {
+            self.handle_char(c);
+        }
+    }
+}
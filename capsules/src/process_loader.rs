@@ -0,0 +1,73 @@
+//! Lets a privileged app load and unload other apps while the kernel runs.
+//!
+//! An updater app writes a new TBF into free app flash, for example with the
+//! nonvolatile storage driver, and then asks this driver to start it. It can
+//! also unload an app before overwriting its flash.
+//!
+//! Only privileged apps may use this driver, see `capsules::privileged`.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let process_loader = static_init!(
+//!     capsules::process_loader::ProcessLoaderDriver<'static>,
+//!     capsules::process_loader::ProcessLoaderDriver::new(loader));
+//! ```
+
+use kernel::procs::ProcessLoader;
+use kernel::{AppId, Driver, ReturnCode};
+use privileged;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10001;
+
+pub struct ProcessLoaderDriver<'a> {
+    loader: &'a ProcessLoader,
+}
+
+impl ProcessLoaderDriver<'a> {
+    pub fn new(loader: &'a ProcessLoader) -> ProcessLoaderDriver<'a> {
+        ProcessLoaderDriver { loader: loader }
+    }
+}
+
+impl Driver for ProcessLoaderDriver<'a> {
+    /// Load and unload apps.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Load and start the app whose TBF starts at flash address `data`.
+    ///        Returns the index of the new process.
+    /// - `2`: Stop and unload the process with index `data`. An app cannot
+    ///        unload itself.
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        if !privileged::command_permitted(DRIVER_NUM, command_num, appid) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => match self.loader.load(data) {
+                Ok(new_appid) => ReturnCode::SuccessWithValue {
+                    value: new_appid.idx(),
+                },
+                Err(err) => err.into(),
+            },
+
+            2 => {
+                if data == appid.idx() {
+                    return ReturnCode::EINVAL;
+                }
+                match self.loader.unload(data) {
+                    Ok(()) => ReturnCode::SUCCESS,
+                    Err(err) => err.into(),
+                }
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
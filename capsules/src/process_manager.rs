@@ -0,0 +1,240 @@
+//! Lets a privileged supervisor app inspect and control other apps.
+//!
+//! The supervisor can read the name, state, restart count, syscall count,
+//! memory use and CPU time of every process, and stop, start and restart
+//! processes by their index. Names are turned into indices with command `6`.
+//!
+//! Only privileged apps may use this driver, see `capsules::privileged`.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let process_manager = static_init!(
+//!     capsules::process_manager::ProcessManagerDriver<'static>,
+//!     capsules::process_manager::ProcessManagerDriver::new(
+//!         manager,
+//!         kernel::Grant::create(&memory_allocation_capability)));
+//! ```
+//!
+//! Process information
+//! -------------------
+//!
+//! Command `2` writes the information about a process to the buffer shared
+//! with allow `0`, as little-endian 32-bit words followed by the name:
+//!
+//! | Offset | Field                                              |
+//! |--------|----------------------------------------------------|
+//! | 0      | State: 0 running, 1 yielded, 2 fault, 3 stopped    |
+//! | 4      | Restart count                                      |
+//! | 8      | Syscall count                                      |
+//! | 12     | Bytes of RAM used, including grants                |
+//! | 16     | Bytes of RAM given to the process                  |
+//! | 20     | Length of the name in bytes                        |
+//! | 24     | Name, truncated to fit the buffer                  |
+//!
+//! CPU accounting
+//! --------------
+//!
+//! Command `7` writes the CPU accounting of a process, since it last started,
+//! to the same buffer, as little-endian 32-bit words:
+//!
+//! | Offset | Field                                              |
+//! |--------|----------------------------------------------------|
+//! | 0      | CPU time in microseconds, low word                 |
+//! | 4      | CPU time in microseconds, high word                |
+//! | 8      | Preemption count                                   |
+//! | 12     | Timeslice expiration count                         |
+//! | 16     | Energy in the board's unit, low word               |
+//! | 20     | Energy in the board's unit, high word              |
+//!
+//! Energy is `0` unless the board gives the kernel a `PowerModel`.
+
+use kernel::procs::{ProcessManager, State};
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+use privileged::{self, PrivilegedBuffers};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10002;
+
+/// Length of the fixed fields of the process information.
+const INFO_HEADER_LEN: usize = 24;
+
+/// Length of the CPU accounting of a process.
+const ACCOUNTING_LEN: usize = 24;
+
+pub struct ProcessManagerDriver<'a> {
+    manager: &'a ProcessManager,
+    buffers: PrivilegedBuffers,
+}
+
+impl ProcessManagerDriver<'a> {
+    pub fn new(
+        manager: &'a ProcessManager,
+        grant: Grant<privileged::App>,
+    ) -> ProcessManagerDriver<'a> {
+        ProcessManagerDriver {
+            manager: manager,
+            buffers: PrivilegedBuffers::new(DRIVER_NUM, grant),
+        }
+    }
+
+    /// Write the information about the process at `app_idx` to the buffer of
+    /// the calling app.
+    fn write_info(&self, app_idx: usize, appid: AppId) -> ReturnCode {
+        let info = match self.manager.info(app_idx) {
+            Some(info) => info,
+            None => return ReturnCode::EINVAL,
+        };
+        let state = match info.state {
+            State::Running => 0,
+            State::Yielded => 1,
+            State::Fault => 2,
+            State::Stopped => 3,
+        };
+        let words = [
+            state,
+            info.restart_count as u32,
+            info.syscall_count as u32,
+            info.memory_used as u32,
+            info.memory_size as u32,
+            info.name.len() as u32,
+        ];
+
+        self.buffers.with_buffer(appid, |buffer| {
+            if buffer.len() < INFO_HEADER_LEN {
+                return ReturnCode::ESIZE;
+            }
+            let buffer = buffer.as_mut();
+            write_words(buffer, &words);
+            for (dst, src) in buffer[INFO_HEADER_LEN..].iter_mut().zip(info.name.bytes()) {
+                *dst = src;
+            }
+            ReturnCode::SUCCESS
+        })
+    }
+
+    /// Write the CPU accounting of the process at `app_idx` to the buffer of
+    /// the calling app.
+    fn write_accounting(&self, app_idx: usize, appid: AppId) -> ReturnCode {
+        let info = match self.manager.info(app_idx) {
+            Some(info) => info,
+            None => return ReturnCode::EINVAL,
+        };
+        let words = [
+            info.cpu_time_us as u32,
+            (info.cpu_time_us >> 32) as u32,
+            info.preemption_count as u32,
+            info.timeslice_expiration_count as u32,
+            info.energy as u32,
+            (info.energy >> 32) as u32,
+        ];
+
+        self.buffers.with_buffer(appid, |buffer| {
+            if buffer.len() < ACCOUNTING_LEN {
+                return ReturnCode::ESIZE;
+            }
+            write_words(buffer.as_mut(), &words);
+            ReturnCode::SUCCESS
+        })
+    }
+
+    /// Find the process whose name is in the first `len` bytes of the buffer
+    /// of the calling app.
+    fn find_by_name(&self, len: usize, appid: AppId) -> ReturnCode {
+        let manager = self.manager;
+        self.buffers.with_buffer(appid, |buffer| {
+            if len > buffer.len() {
+                return ReturnCode::ESIZE;
+            }
+            let name = &buffer.as_ref()[..len];
+            let found = ::core::str::from_utf8(name)
+                .ok()
+                .and_then(|name| manager.find_by_name(name));
+            match found {
+                Some(idx) => ReturnCode::SuccessWithValue { value: idx },
+                None => ReturnCode::EINVAL,
+            }
+        })
+    }
+}
+
+impl Driver for ProcessManagerDriver<'a> {
+    /// Share the buffer process information is written to, and names are read
+    /// from.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The buffer.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        self.buffers.allow(appid, allow_num, slice)
+    }
+
+    /// Inspect and control processes.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Number of process slots. Process indices are less than this.
+    /// - `2`: Write the information about the process with index `data` to
+    ///        the shared buffer.
+    /// - `3`: Stop the process with index `data`.
+    /// - `4`: Start the stopped or faulted process with index `data`.
+    /// - `5`: Restart the process with index `data`.
+    /// - `6`: Return the index of the process whose name is in the first
+    ///        `data` bytes of the shared buffer.
+    /// - `7`: Write the CPU accounting of the process with index `data` to
+    ///        the shared buffer.
+    ///
+    /// An app cannot stop, start or restart itself.
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        if !privileged::command_permitted(DRIVER_NUM, command_num, appid) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => ReturnCode::SuccessWithValue {
+                value: self.manager.number_of_slots(),
+            },
+
+            2 => self.write_info(data, appid),
+
+            3...5 => {
+                if data == appid.idx() {
+                    return ReturnCode::EINVAL;
+                }
+                let result = match command_num {
+                    3 => self.manager.stop(data),
+                    4 => self.manager.start(data),
+                    _ => self.manager.restart(data),
+                };
+                match result {
+                    Ok(()) => ReturnCode::SUCCESS,
+                    Err(err) => err.into(),
+                }
+            }
+
+            6 => self.find_by_name(data, appid),
+
+            7 => self.write_accounting(data, appid),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+/// Write `words` to the start of `buffer` as little-endian bytes.
+fn write_words(buffer: &mut [u8], words: &[u32]) {
+    for (i, word) in words.iter().enumerate() {
+        for j in 0..4 {
+            buffer[i * 4 + j] = (word >> (j * 8)) as u8;
+        }
+    }
+}
@@ -10,7 +10,7 @@
 //! ```rust
 //! let rng = static_init!(
 //!         capsules::rng::SimpleRng<'static, sam4l::trng::Trng>,
-//!         capsules::rng::SimpleRng::new(&sam4l::trng::TRNG, kernel::Grant::create()));
+//!         capsules::rng::SimpleRng::new(&sam4l::trng::TRNG, kernel::Grant::create(&memory_allocation_capability)));
 //! sam4l::trng::TRNG.set_client(rng);
 //! ```
 
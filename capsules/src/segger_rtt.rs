@@ -21,10 +21,9 @@
 //! Notes
 //! -----
 //!
-//! This capsule requires a timer, but the timer is only there to defer the
-//! `transmit_complete` callback until the next scheduler loop. In the future,
-//! if there is support for software interrupts or deferred calls in capsules,
-//! this timer should be removed.
+//! Transmitting only copies the data to memory, so this capsule uses a dynamic
+//! deferred call to issue the `transmit_complete` callback from the main loop
+//! instead of from within `transmit`.
 //!
 //! Todo
 //! ----
@@ -39,22 +38,14 @@
 //!     // Other fields omitted for clarity
 //!     console: &'static capsules::console::Console<
 //!         'static,
-//!         capsules::segger_rtt::SeggerRtt<
-//!             'static,
-//!             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
-//!         >,
+//!         capsules::segger_rtt::SeggerRtt<'static>,
 //!     >,
 //! }
 //! ```
 //!
-//! In `reset_handler()`:
+//! In `reset_handler()`, with a `dynamic_deferred_call` the kernel services:
 //!
 //! ```
-//! let virtual_alarm_rtt = static_init!(
-//!     capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
-//!     capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
-//! );
-//!
 //! let rtt_memory = static_init!(
 //!     capsules::segger_rtt::SeggerRttMemory,
 //!     capsules::segger_rtt::SeggerRttMemory::new(b"Terminal\0",
@@ -64,35 +55,51 @@
 //! );
 //!
 //! let rtt = static_init!(
-//!     capsules::segger_rtt::SeggerRtt<VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>>,
-//!     capsules::segger_rtt::SeggerRtt::new(virtual_alarm_rtt, rtt_memory,
+//!     capsules::segger_rtt::SeggerRtt<'static>,
+//!     capsules::segger_rtt::SeggerRtt::new(dynamic_deferred_call, rtt_memory,
 //!         &mut capsules::segger_rtt::UP_BUFFER,
 //!         &mut capsules::segger_rtt::DOWN_BUFFER)
 //! );
-//! virtual_alarm_rtt.set_client(rtt);
+//! rtt.initialize_callback_handle(
+//!     dynamic_deferred_call.register(rtt).expect("no deferred call slot left"),
+//! );
 //!
 //! let console = static_init!(
-//!     capsules::console::Console<
-//!         'static,
-//!         capsules::segger_rtt::SeggerRtt<
-//!             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
-//!         >,
-//!     >,
+//!     capsules::console::Console<'static, capsules::segger_rtt::SeggerRtt<'static>>,
 //!     capsules::console::Console::new(
 //!         rtt,
 //!         0, // Baud rate is meaningless with RTT
 //!         &mut capsules::console::WRITE_BUF,
 //!         &mut capsules::console::READ_BUF,
-//!         kernel::Grant::create()
+//!         kernel::Grant::create(&memory_allocation_capability)
 //!     )
 //! );
 //! kernel::hil::uart::UART::set_client(rtt, console);
 //! console.initialize();
 //! ```
+//!
+//! Debug Messages
+//! --------------
+//!
+//! An RTT channel can also receive the kernel's debug messages directly, as a
+//! debug sink, without going through the console:
+//!
+//! ```
+//! kernel::debug::add_sink(rtt, &kernel_configuration_capability);
+//! ```
+//!
+//! Messages that do not fit in the free part of the up buffer are dropped
+//! instead of overwriting ones the host has not read yet. Do not add the
+//! channel as a sink if the console assigned to the kernel's debug output
+//! already uses it, or every message is printed twice.
 
+use core::fmt::{self, Write};
 use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::common::dynamic_deferred_call::{
+    DeferredCallHandle, DynamicDeferredCall, DynamicDeferredCallClient,
+};
+use kernel::debug;
 use kernel::hil;
-use kernel::hil::time::Frequency;
 
 /// Buffer for transmitting to the host.
 pub static mut UP_BUFFER: [u8; 1024] = [0; 1024];
@@ -154,8 +161,9 @@ impl SeggerRttMemory {
     }
 }
 
-pub struct SeggerRtt<'a, A: hil::time::Alarm> {
-    alarm: &'a A, // Dummy alarm so we can get a callback.
+pub struct SeggerRtt<'a> {
+    deferred_call: &'a DynamicDeferredCall,
+    deferred_call_handle: OptionalCell<DeferredCallHandle>,
     config: TakeCell<'static, SeggerRttMemory>,
     up_buffer: TakeCell<'static, [u8]>,
     _down_buffer: TakeCell<'static, [u8]>,
@@ -163,15 +171,16 @@ pub struct SeggerRtt<'a, A: hil::time::Alarm> {
     client_buffer: TakeCell<'static, [u8]>,
 }
 
-impl<A: hil::time::Alarm> SeggerRtt<'a, A> {
+impl SeggerRtt<'a> {
     pub fn new(
-        alarm: &'a A,
+        deferred_call: &'a DynamicDeferredCall,
         config: &'static mut SeggerRttMemory,
         up_buffer: &'static mut [u8],
         down_buffer: &'static mut [u8],
-    ) -> SeggerRtt<'a, A> {
+    ) -> SeggerRtt<'a> {
         SeggerRtt {
-            alarm: alarm,
+            deferred_call: deferred_call,
+            deferred_call_handle: OptionalCell::empty(),
             config: TakeCell::new(config),
             up_buffer: TakeCell::new(up_buffer),
             _down_buffer: TakeCell::new(down_buffer),
@@ -179,9 +188,15 @@ impl<A: hil::time::Alarm> SeggerRtt<'a, A> {
             client_buffer: TakeCell::empty(),
         }
     }
+
+    /// Set the handle this capsule got when it registered with the deferred
+    /// call.
+    pub fn initialize_callback_handle(&self, handle: DeferredCallHandle) {
+        self.deferred_call_handle.set(handle);
+    }
 }
 
-impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
+impl hil::uart::UART for SeggerRtt<'a> {
     fn set_client(&self, client: &'static hil::uart::Client) {
         self.client.set(client);
     }
@@ -209,11 +224,9 @@ impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
         // Save the client buffer so we can pass it back with the callback.
         self.client_buffer.replace(tx_data);
 
-        // Start a short timer so that we get a callback and can issue the
-        // callback to the client.
-        let interval = (100 as u32) * <A::Frequency>::frequency() / 1000000;
-        let tics = self.alarm.now().wrapping_add(interval);
-        self.alarm.set_alarm(tics);
+        // Issue the callback to the client from the main loop.
+        self.deferred_call_handle
+            .map(|handle| self.deferred_call.set(*handle));
     }
 
     fn receive(&self, _rx_buf: &'static mut [u8], _rx_len: usize) {}
@@ -221,8 +234,8 @@ impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
     fn abort_receive(&self) {}
 }
 
-impl<A: hil::time::Alarm> hil::time::Client for SeggerRtt<'a, A> {
-    fn fired(&self) {
+impl DynamicDeferredCallClient for SeggerRtt<'a> {
+    fn call(&self, _handle: DeferredCallHandle) {
         self.client.map(|client| {
             self.client_buffer.take().map(|buffer| {
                 client.transmit_complete(buffer, hil::uart::Error::CommandComplete);
@@ -230,3 +243,59 @@ impl<A: hil::time::Alarm> hil::time::Client for SeggerRtt<'a, A> {
         });
     }
 }
+
+/// Writes into the free part of an up buffer, failing if there is not enough
+/// room.
+struct UpBufferWriter<'b> {
+    buffer: &'b mut [u8],
+    /// Where the next byte is written.
+    position: usize,
+    /// How many bytes can be written before reaching the host's read
+    /// position.
+    free: usize,
+}
+
+impl Write for UpBufferWriter<'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.len() > self.free {
+            return Err(fmt::Error);
+        }
+        for &byte in s.as_bytes() {
+            self.buffer[self.position] = byte;
+            self.position = (self.position + 1) % self.buffer.len();
+        }
+        self.free -= s.len();
+        Ok(())
+    }
+}
+
+impl debug::DebugSink for SeggerRtt<'a> {
+    fn write_message(&self, _level: debug::Level, message: fmt::Arguments) -> bool {
+        self.up_buffer.map_or(false, |buffer| {
+            self.config.map_or(false, |config| {
+                let buffer_len = config.up_buffer.length as usize;
+                let write_position = config.up_buffer.write_position as usize;
+                let read_position = config.up_buffer.read_position as usize;
+                if buffer_len == 0
+                    || buffer_len > buffer.len()
+                    || write_position >= buffer_len
+                    || read_position >= buffer_len
+                {
+                    return false;
+                }
+                let mut writer = UpBufferWriter {
+                    buffer: &mut buffer[..buffer_len],
+                    position: write_position,
+                    free: (read_position + buffer_len - write_position - 1) % buffer_len,
+                };
+                // Only move the write position, which lets the host read the
+                // message, once all of it is in the buffer.
+                if fmt::write(&mut writer, message).is_err() {
+                    return false;
+                }
+                config.up_buffer.write_position = writer.position as u32;
+                true
+            })
+        })
+    }
+}
@@ -0,0 +1,172 @@
+//! Checks the SHA-256 digests in app footers before apps are started.
+//!
+//! The digest is computed in software over the app from the start of its
+//! header to the end of its binary. Apps whose digest does not match, for
+//! example because the binary was corrupted or modified, are not started. A
+//! digest alone does not tell who built an app: boards that must only run
+//! apps from a trusted source need a verifier for signed credentials.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let verifier = static_init!(
+//!     capsules::sha256_verifier::Sha256Verifier,
+//!     capsules::sha256_verifier::Sha256Verifier::new(true));
+//!
+//! kernel::procs::load_processes(
+//!     board_kernel,
+//!     &_sapps as *const u8,
+//!     &mut APP_MEMORY,
+//!     &mut PROCESSES,
+//!     FAULT_RESPONSE,
+//!     Some(verifier),
+//!     &process_management_capability,
+//! );
+//! ```
+
+use kernel::procs::{
+    AppVerifier, CredentialCheck, TbfFooterV2Credentials, TbfFooterV2CredentialsType,
+};
+
+/// Length in bytes of a SHA-256 digest.
+pub const DIGEST_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 64;
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub struct Sha256Verifier {
+    require_credentials: bool,
+}
+
+impl Sha256Verifier {
+    /// If `require_credentials` is set, apps without a matching SHA-256
+    /// digest are not started. Otherwise only apps whose digest does not
+    /// match are kept from starting.
+    pub fn new(require_credentials: bool) -> Sha256Verifier {
+        Sha256Verifier {
+            require_credentials: require_credentials,
+        }
+    }
+}
+
+impl AppVerifier for Sha256Verifier {
+    fn check_credentials(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        binary: &[u8],
+    ) -> CredentialCheck {
+        match credentials.format() {
+            TbfFooterV2CredentialsType::Sha256 => {
+                if credentials.data() == &digest(binary)[..] {
+                    CredentialCheck::Accept
+                } else {
+                    CredentialCheck::Reject
+                }
+            }
+            _ => CredentialCheck::Pass,
+        }
+    }
+
+    fn require_credentials(&self) -> bool {
+        self.require_credentials
+    }
+}
+
+/// Compute the SHA-256 digest of `data`.
+pub fn digest(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut state = INITIAL_STATE;
+
+    let full_blocks = data.len() / BLOCK_LEN * BLOCK_LEN;
+    for block in data[..full_blocks].chunks(BLOCK_LEN) {
+        compress(&mut state, block);
+    }
+
+    // The rest of the data, a one bit, zeros, and the length in bits fill one
+    // or two more blocks.
+    let rest = &data[full_blocks..];
+    let mut last = [0; 2 * BLOCK_LEN];
+    last[..rest.len()].copy_from_slice(rest);
+    last[rest.len()] = 0x80;
+    let last_len = if rest.len() < BLOCK_LEN - 8 {
+        BLOCK_LEN
+    } else {
+        2 * BLOCK_LEN
+    };
+    let bit_len = (data.len() as u64) * 8;
+    for i in 0..8 {
+        last[last_len - 1 - i] = (bit_len >> (i * 8)) as u8;
+    }
+    for block in last[..last_len].chunks(BLOCK_LEN) {
+        compress(&mut state, block);
+    }
+
+    let mut digest = [0; DIGEST_LEN];
+    for (i, word) in state.iter().enumerate() {
+        for j in 0..4 {
+            digest[i * 4 + j] = (word >> (24 - j * 8)) as u8;
+        }
+    }
+    digest
+}
+
+/// Mix the 64-byte `block` into `state`.
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for i in 0..16 {
+        w[i] = (block[i * 4] as u32) << 24
+            | (block[i * 4 + 1] as u32) << 16
+            | (block[i * 4 + 2] as u32) << 8
+            | block[i * 4 + 3] as u32;
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let mut v = *state;
+    for i in 0..64 {
+        let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+        let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+        let t1 = v[7]
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(ROUND_CONSTANTS[i])
+            .wrapping_add(w[i]);
+        let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+        let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+        let t2 = s0.wrapping_add(maj);
+        v = [
+            t1.wrapping_add(t2),
+            v[0],
+            v[1],
+            v[2],
+            v[3].wrapping_add(t1),
+            v[4],
+            v[5],
+            v[6],
+        ];
+    }
+
+    for (word, value) in state.iter_mut().zip(v.iter()) {
+        *word = word.wrapping_add(*value);
+    }
+}
@@ -0,0 +1,160 @@
+//! Lets a privileged app read the kernel's syscall trace.
+//!
+//! The kernel records recent syscalls when the board gives it a
+//! `SyscallTrace`. This driver copies the records to an app, for example a
+//! debugging tool that reports them over the network, and lets it choose
+//! which processes are traced.
+//!
+//! Only privileged apps may use this driver, see `capsules::privileged`.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let syscall_trace_driver = static_init!(
+//!     capsules::syscall_trace::SyscallTraceDriver<'static>,
+//!     capsules::syscall_trace::SyscallTraceDriver::new(
+//!         syscall_trace,
+//!         kernel::Grant::create(&memory_allocation_capability)));
+//! ```
+//!
+//! Records
+//! -------
+//!
+//! Command `2` writes records to the buffer shared with allow `0`. Each record
+//! is nine little-endian 32-bit words:
+//!
+//! | Offset | Field                                                  |
+//! |--------|--------------------------------------------------------|
+//! | 0      | Index of the process                                   |
+//! | 4      | Syscall number                                         |
+//! | 8      | Driver number, or first argument of other syscalls     |
+//! | 12     | Argument in `r1`                                       |
+//! | 16     | Argument in `r2`                                       |
+//! | 20     | Argument in `r3`                                       |
+//! | 24     | 1 if the syscall returned a code, 0 otherwise          |
+//! | 28     | Return code                                            |
+//! | 32     | Timestamp                                              |
+
+use kernel::procs::SyscallTrace;
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+use privileged::{self, PrivilegedBuffers};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10003;
+
+/// Length in bytes of one record in the app's buffer.
+const RECORD_LEN: usize = 36;
+
+pub struct SyscallTraceDriver<'a> {
+    syscall_trace: &'a SyscallTrace,
+    buffers: PrivilegedBuffers,
+}
+
+impl SyscallTraceDriver<'a> {
+    pub fn new(
+        syscall_trace: &'a SyscallTrace,
+        grant: Grant<privileged::App>,
+    ) -> SyscallTraceDriver<'a> {
+        SyscallTraceDriver {
+            syscall_trace: syscall_trace,
+            buffers: PrivilegedBuffers::new(DRIVER_NUM, grant),
+        }
+    }
+
+    /// Copy as many records as fit in the buffer of the calling app, starting
+    /// with the `first`th oldest. Returns how many were copied.
+    fn copy_records(&self, first: usize, appid: AppId) -> ReturnCode {
+        let syscall_trace = self.syscall_trace;
+        self.buffers.with_buffer(appid, |buffer| {
+            let buffer = buffer.as_mut();
+            let mut copied = 0;
+            for chunk in buffer.chunks_mut(RECORD_LEN) {
+                if chunk.len() < RECORD_LEN {
+                    break;
+                }
+                let record = match syscall_trace.get(first + copied) {
+                    Some(record) => record,
+                    None => break,
+                };
+                let (has_return_code, return_code) = match record.return_code {
+                    Some(return_code) => (1, isize::from(return_code) as u32),
+                    None => (0, 0),
+                };
+                let words = [
+                    record.app_idx as u32,
+                    record.syscall as u32,
+                    record.driver_num as u32,
+                    record.args[0] as u32,
+                    record.args[1] as u32,
+                    record.args[2] as u32,
+                    has_return_code,
+                    return_code,
+                    record.timestamp,
+                ];
+                for (i, word) in words.iter().enumerate() {
+                    for j in 0..4 {
+                        chunk[i * 4 + j] = (word >> (j * 8)) as u8;
+                    }
+                }
+                copied += 1;
+            }
+            ReturnCode::SuccessWithValue { value: copied }
+        })
+    }
+}
+
+impl Driver for SyscallTraceDriver<'a> {
+    /// Share the buffer records are copied to.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The buffer.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        self.buffers.allow(appid, allow_num, slice)
+    }
+
+    /// Read and configure the syscall trace.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Number of records in the trace.
+    /// - `2`: Copy records to the shared buffer, starting with the `data`th
+    ///        oldest. Returns how many were copied.
+    /// - `3`: Only trace the processes whose bit is set in `data`, where bit
+    ///        `i` stands for the process with index `i`.
+    /// - `4`: Clear the trace.
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        if !privileged::command_permitted(DRIVER_NUM, command_num, appid) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => ReturnCode::SuccessWithValue {
+                value: self.syscall_trace.len(),
+            },
+
+            2 => self.copy_records(data, appid),
+
+            3 => {
+                self.syscall_trace.set_filter(data);
+                ReturnCode::SUCCESS
+            }
+
+            4 => {
+                self.syscall_trace.clear();
+                ReturnCode::SUCCESS
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
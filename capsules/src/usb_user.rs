@@ -21,7 +21,7 @@
 //!     capsules::usb_user::UsbSyscallDriver<'static,
 //!         capsules::usbc_client::Client<'static, sam4l::usbc::Usbc<'static>>>,
 //!     capsules::usb_user::UsbSyscallDriver::new(
-//!         usb_client, kernel::Grant::create()));
+//!         usb_client, kernel::Grant::create(&memory_allocation_capability)));
 //! ```
 
 use core::cell::Cell;
@@ -0,0 +1,156 @@
+//! Tests for the userspace alarm driver, with processes simulated on the
+//! hosted chip.
+
+extern crate capsules;
+extern crate hosted;
+extern crate kernel;
+
+use capsules::alarm::{AlarmDriver, DRIVER_NUM};
+use hosted::{App, BoardBuilder, HostedAlarm, HostedChip, HostedPlatform};
+use kernel::hil::time::{Alarm, Time};
+use kernel::procs::Simulator;
+use kernel::ReturnCode;
+
+const CALLBACK_PC: usize = 0x1001;
+
+struct Board {
+    chip: HostedChip,
+    alarm: &'static HostedAlarm,
+    simulator: Simulator<'static, HostedPlatform>,
+}
+
+fn board(apps: &[App]) -> Board {
+    let mut builder = BoardBuilder::new();
+    let alarm = hosted::leak(HostedAlarm::new());
+    let driver = hosted::leak(AlarmDriver::new(&*alarm, builder.grant()));
+    alarm.set_client(driver);
+    builder.chip.set_alarm(alarm);
+    builder.platform.add_driver(DRIVER_NUM, driver);
+
+    let (chip, simulator) = builder.simulate(apps);
+    Board {
+        chip: chip,
+        alarm: alarm,
+        simulator: simulator,
+    }
+}
+
+#[test]
+fn reports_frequency_and_time() {
+    let _lock = hosted::lock();
+    let board = board(&[App::new("app")]);
+    board.alarm.advance(1234);
+
+    let simulator = &board.simulator;
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 0, 0, 0),
+        ReturnCode::SuccessWithValue { value: 1 }
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 1, 0, 0),
+        ReturnCode::SuccessWithValue { value: 16000 }
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 2, 0, 0),
+        ReturnCode::SuccessWithValue { value: 1234 }
+    );
+}
+
+#[test]
+fn calls_back_when_the_alarm_expires() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    assert_eq!(
+        simulator.subscribe(0, DRIVER_NUM, 0, CALLBACK_PC, 7),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 4, 100, 0),
+        ReturnCode::SuccessWithValue { value: 100 }
+    );
+    simulator.run_until_idle(&mut board.chip);
+    assert!(simulator.next_callback(0).is_none());
+
+    simulator.sleep(&mut board.chip);
+    assert_eq!(board.alarm.now(), 100);
+    let callback = simulator.next_callback(0).expect("no callback");
+    assert_eq!(callback.pc, CALLBACK_PC);
+    assert_eq!((callback.r0, callback.r1, callback.r3), (100, 100, 7));
+    assert!(simulator.next_callback(0).is_none());
+    assert!(!board.alarm.is_armed());
+}
+
+#[test]
+fn fires_the_alarms_of_several_apps_in_order() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("first"), App::new("second")]);
+    let simulator = &board.simulator;
+
+    for app in 0..2 {
+        simulator.subscribe(app, DRIVER_NUM, 0, CALLBACK_PC, app);
+    }
+    simulator.command(0, DRIVER_NUM, 4, 300, 0);
+    simulator.command(1, DRIVER_NUM, 4, 200, 0);
+
+    simulator.sleep(&mut board.chip);
+    assert_eq!(board.alarm.now(), 200);
+    assert!(simulator.next_callback(0).is_none());
+    assert_eq!(simulator.next_callback(1).map(|cb| cb.r1), Some(200));
+
+    simulator.sleep(&mut board.chip);
+    assert_eq!(board.alarm.now(), 300);
+    assert_eq!(simulator.next_callback(0).map(|cb| cb.r1), Some(300));
+    assert!(simulator.next_callback(1).is_none());
+}
+
+#[test]
+fn stopped_alarms_do_not_fire() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    simulator.subscribe(0, DRIVER_NUM, 0, CALLBACK_PC, 0);
+    simulator.command(0, DRIVER_NUM, 4, 100, 0);
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 3, 99, 0),
+        ReturnCode::EINVAL
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 3, 100, 0),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 3, 100, 0),
+        ReturnCode::EALREADY
+    );
+
+    simulator.sleep(&mut board.chip);
+    assert!(!board.alarm.is_armed());
+    assert!(simulator.next_callback(0).is_none());
+}
+
+#[test]
+fn apps_only_use_the_commands_they_are_permitted() {
+    let _lock = hosted::lock();
+    let board = board(&[App::new("restricted").permit(DRIVER_NUM, 0b11)]);
+    let simulator = &board.simulator;
+
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 1, 0, 0),
+        ReturnCode::SuccessWithValue { value: 16000 }
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 4, 100, 0),
+        ReturnCode::ENOPERM
+    );
+    assert_eq!(
+        simulator.subscribe(0, DRIVER_NUM, 0, CALLBACK_PC, 0),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM + 1, 0, 0, 0),
+        ReturnCode::ENOPERM
+    );
+}
@@ -0,0 +1,149 @@
+//! Tests for checking app credentials when apps are loaded, with the SHA-256
+//! verifier and with a verifier that decides by the first byte of each
+//! credential.
+
+extern crate capsules;
+extern crate hosted;
+#[macro_use(create_capability)]
+extern crate kernel;
+
+use capsules::sha256_verifier::{self, Sha256Verifier, DIGEST_LEN};
+use hosted::App;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::procs::{AppVerifier, CredentialCheck, TbfFooterV2Credentials};
+use kernel::Kernel;
+
+const SHA256: u32 = 1;
+const HMAC_SHA256: u32 = 2;
+
+const ACCEPT: u8 = 0xAA;
+const REJECT: u8 = 0xBB;
+const PASS: u8 = 0xCC;
+
+/// Accepts, rejects or passes each credential by its first byte.
+struct ByFirstByte {
+    require_credentials: bool,
+}
+
+impl AppVerifier for ByFirstByte {
+    fn check_credentials(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        _binary: &[u8],
+    ) -> CredentialCheck {
+        match credentials.data()[0] {
+            ACCEPT => CredentialCheck::Accept,
+            REJECT => CredentialCheck::Reject,
+            _ => CredentialCheck::Pass,
+        }
+    }
+
+    fn require_credentials(&self) -> bool {
+        self.require_credentials
+    }
+}
+
+/// An app with an HMAC-SHA256 credential for each of `checks`.
+fn app(name: &str, checks: &[u8]) -> App {
+    checks.iter().fold(App::new(name), |app, &check| {
+        app.credential(HMAC_SHA256, &[check; 32])
+    })
+}
+
+/// Load `apps` with `verifier` and return which were started.
+fn started(verifier: &AppVerifier, apps: &[App]) -> Vec<bool> {
+    let kernel = hosted::leak(Kernel::new());
+    let processes = hosted::load_apps(
+        kernel,
+        apps,
+        Some(verifier),
+        &create_capability!(ProcessManagementCapability),
+    );
+    processes.iter().map(|process| process.is_some()).collect()
+}
+
+#[test]
+fn first_credential_that_is_not_passed_decides() {
+    let _lock = hosted::lock();
+    let verifier = ByFirstByte {
+        require_credentials: true,
+    };
+    let apps = [
+        app("accepted", &[ACCEPT]),
+        app("rejected", &[REJECT]),
+        app("pass_then_accept", &[PASS, ACCEPT]),
+        app("pass_then_reject", &[PASS, REJECT]),
+        app("accept_then_reject", &[ACCEPT, REJECT]),
+        app("reject_then_accept", &[REJECT, ACCEPT]),
+    ];
+    assert_eq!(
+        started(&verifier, &apps),
+        vec![true, false, true, false, true, false]
+    );
+}
+
+#[test]
+fn required_credentials_keep_unverified_apps_from_starting() {
+    let _lock = hosted::lock();
+    let verifier = ByFirstByte {
+        require_credentials: true,
+    };
+    let apps = [app("none", &[]), app("passed", &[PASS, PASS])];
+    assert_eq!(started(&verifier, &apps), vec![false, false]);
+}
+
+#[test]
+fn optional_credentials_only_keep_rejected_apps_from_starting() {
+    let _lock = hosted::lock();
+    let verifier = ByFirstByte {
+        require_credentials: false,
+    };
+    let apps = [
+        app("none", &[]),
+        app("passed", &[PASS]),
+        app("accepted", &[ACCEPT]),
+        app("rejected", &[REJECT]),
+    ];
+    assert_eq!(started(&verifier, &apps), vec![true, true, true, false]);
+}
+
+#[test]
+fn computes_known_digests() {
+    assert_eq!(
+        &sha256_verifier::digest(b"abc")[..],
+        &[
+            0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+            0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+            0xf2, 0x00, 0x15, 0xad,
+        ][..]
+    );
+    // Long enough that the padding needs a block of its own.
+    assert_eq!(
+        &sha256_verifier::digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")[..],
+        &[
+            0x24, 0x8d, 0x6a, 0x61, 0xd2, 0x06, 0x38, 0xb8, 0xe5, 0xc0, 0x26, 0x93, 0x0c, 0x3e,
+            0x60, 0x39, 0xa3, 0x3c, 0xe4, 0x59, 0x64, 0xff, 0x21, 0x67, 0xf6, 0xec, 0xed, 0xd4,
+            0x19, 0xdb, 0x06, 0xc1,
+        ][..]
+    );
+}
+
+#[test]
+fn sha256_verifier_starts_apps_whose_digest_matches() {
+    let _lock = hosted::lock();
+    let verifier = Sha256Verifier::new(true);
+    let signed = App::new("signed").computed_credential(SHA256, DIGEST_LEN, |binary| {
+        sha256_verifier::digest(binary).to_vec()
+    });
+    let tampered = App::new("tampered").computed_credential(SHA256, DIGEST_LEN, |binary| {
+        let mut binary = binary.to_vec();
+        binary[16] ^= 1;
+        sha256_verifier::digest(&binary).to_vec()
+    });
+    let apps = [signed, tampered, App::new("unsigned")];
+    assert_eq!(started(&verifier, &apps), vec![true, false, false]);
+
+    let verifier = Sha256Verifier::new(false);
+    let apps = [App::new("unsigned"), app("other_format", &[ACCEPT])];
+    assert_eq!(started(&verifier, &apps), vec![true, true]);
+}
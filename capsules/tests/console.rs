@@ -0,0 +1,182 @@
+//! Tests for the userspace console, with processes simulated on the hosted
+//! chip.
+
+extern crate capsules;
+extern crate hosted;
+extern crate kernel;
+
+use capsules::console::{Console, DRIVER_NUM};
+use hosted::{App, BoardBuilder, HostedChip, HostedPlatform, HostedUart};
+use kernel::hil::uart::UART;
+use kernel::procs::Simulator;
+use kernel::ReturnCode;
+
+const WRITE_DONE_PC: usize = 0x2001;
+const READ_DONE_PC: usize = 0x2003;
+
+struct Board {
+    chip: HostedChip,
+    uart: &'static HostedUart,
+    simulator: Simulator<'static, HostedPlatform>,
+}
+
+fn board(apps: &[App]) -> Board {
+    let mut builder = BoardBuilder::new();
+    let uart = hosted::leak(HostedUart::new());
+    let console = hosted::leak(Console::new(
+        &*uart,
+        115200,
+        hosted::leak([0; 64]),
+        hosted::leak([0; 64]),
+        builder.grant(),
+    ));
+    uart.set_client(console);
+    console.initialize();
+    builder.chip.add_peripheral(uart);
+    builder.platform.add_driver(DRIVER_NUM, console);
+
+    let (chip, simulator) = builder.simulate(apps);
+    Board {
+        chip: chip,
+        uart: uart,
+        simulator: simulator,
+    }
+}
+
+#[test]
+fn initializes_the_uart() {
+    let _lock = hosted::lock();
+    let board = board(&[App::new("app")]);
+
+    assert_eq!(
+        board.uart.params().map(|params| params.baud_rate),
+        Some(115200)
+    );
+}
+
+#[test]
+fn writes_what_the_app_shares() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    let message = b"hello, world\n";
+    let address = hosted::put(simulator, 0, message);
+    simulator.allow(0, DRIVER_NUM, 1, address, message.len());
+    simulator.subscribe(0, DRIVER_NUM, 1, WRITE_DONE_PC, 0);
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 1, message.len(), 0),
+        ReturnCode::SUCCESS
+    );
+    simulator.run_until_idle(&mut board.chip);
+
+    assert_eq!(board.uart.take_output(), message.to_vec());
+    let callback = simulator.next_callback(0).expect("no callback");
+    assert_eq!((callback.pc, callback.r0), (WRITE_DONE_PC, message.len()));
+}
+
+#[test]
+fn writes_messages_longer_than_its_buffer() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    let message: Vec<u8> = (0..150).map(|i| b'a' + (i % 26) as u8).collect();
+    let address = hosted::put(simulator, 0, &message);
+    simulator.allow(0, DRIVER_NUM, 1, address, message.len());
+    simulator.subscribe(0, DRIVER_NUM, 1, WRITE_DONE_PC, 0);
+    simulator.command(0, DRIVER_NUM, 1, message.len(), 0);
+    simulator.run_until_idle(&mut board.chip);
+
+    assert_eq!(board.uart.take_output(), message);
+    assert_eq!(simulator.next_callback(0).map(|cb| cb.r0), Some(150));
+    assert!(simulator.next_callback(0).is_none());
+}
+
+#[test]
+fn takes_turns_writing_for_several_apps() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("first"), App::new("second")]);
+    let simulator = &board.simulator;
+
+    for (app, message) in [&b"first\n"[..], &b"second\n"[..]].iter().enumerate() {
+        let address = hosted::put(simulator, app, message);
+        simulator.allow(app, DRIVER_NUM, 1, address, message.len());
+        simulator.subscribe(app, DRIVER_NUM, 1, WRITE_DONE_PC, 0);
+        assert_eq!(
+            simulator.command(app, DRIVER_NUM, 1, message.len(), 0),
+            ReturnCode::SUCCESS
+        );
+    }
+    simulator.run_until_idle(&mut board.chip);
+
+    assert_eq!(board.uart.take_output(), b"first\nsecond\n".to_vec());
+    assert_eq!(simulator.next_callback(0).map(|cb| cb.r0), Some(6));
+    assert_eq!(simulator.next_callback(1).map(|cb| cb.r0), Some(7));
+}
+
+#[test]
+fn reads_into_the_shared_buffer() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    let address = hosted::put(simulator, 0, &[0; 8]);
+    simulator.allow(0, DRIVER_NUM, 2, address, 8);
+    simulator.subscribe(0, DRIVER_NUM, 2, READ_DONE_PC, 0);
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 2, 4, 0),
+        ReturnCode::SUCCESS
+    );
+    assert!(board.uart.is_receiving());
+
+    assert_eq!(board.uart.receive_bytes(b"abcdef"), 4);
+    simulator.run_until_idle(&mut board.chip);
+
+    let callback = simulator.next_callback(0).expect("no callback");
+    assert_eq!(
+        (callback.pc, callback.r0, callback.r1),
+        (READ_DONE_PC, 0, 4)
+    );
+    let mut read = [0; 8];
+    simulator.read_memory(0, address, &mut read);
+    assert_eq!(&read, b"abcd\0\0\0\0");
+}
+
+#[test]
+fn aborted_reads_return_what_was_received() {
+    let _lock = hosted::lock();
+    let mut board = board(&[App::new("app")]);
+    let simulator = &board.simulator;
+
+    let address = hosted::put(simulator, 0, &[0; 8]);
+    simulator.allow(0, DRIVER_NUM, 2, address, 8);
+    simulator.subscribe(0, DRIVER_NUM, 2, READ_DONE_PC, 0);
+    simulator.command(0, DRIVER_NUM, 2, 8, 0);
+    board.uart.receive_bytes(b"ab");
+    simulator.run_until_idle(&mut board.chip);
+    assert!(simulator.next_callback(0).is_none());
+
+    simulator.command(0, DRIVER_NUM, 3, 0, 0);
+    simulator.run_until_idle(&mut board.chip);
+
+    assert_eq!(simulator.next_callback(0).map(|cb| cb.r1), Some(2));
+    assert!(!board.uart.is_receiving());
+}
+
+#[test]
+fn rejects_buffers_outside_the_app() {
+    let _lock = hosted::lock();
+    let board = board(&[App::new("first"), App::new("second")]);
+    let simulator = &board.simulator;
+
+    let address = hosted::put(simulator, 1, b"secret");
+    assert_eq!(
+        simulator.allow(0, DRIVER_NUM, 1, address, 6),
+        ReturnCode::EINVAL
+    );
+    assert_eq!(
+        simulator.allow(1, DRIVER_NUM, 1, address, 6),
+        ReturnCode::SUCCESS
+    );
+}
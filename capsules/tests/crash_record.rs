@@ -0,0 +1,99 @@
+//! Tests for saving a kernel panic in the crash record and uploading it to a
+//! privileged app, with processes simulated on the hosted chip.
+
+extern crate capsules;
+extern crate hosted;
+extern crate kernel;
+
+use capsules::crash_record::{CrashRecordDriver, DRIVER_NUM};
+use hosted::{App, BoardBuilder};
+use kernel::debug;
+use kernel::procs::Simulator;
+use kernel::ReturnCode;
+use std::panic;
+
+fn simulator() -> Simulator<'static, hosted::HostedPlatform> {
+    let mut builder = BoardBuilder::new();
+    let driver = hosted::leak(CrashRecordDriver::new(builder.grant()));
+    builder.platform.add_driver(DRIVER_NUM, driver);
+    let (_, simulator) = builder.simulate(&[App::new("uploader").permit(DRIVER_NUM, !0)]);
+    simulator
+}
+
+/// Panic with `message` and save the panic in the crash record, as the
+/// board's panic handler does.
+fn panic_and_record(message: &'static str) {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|panic_info| unsafe {
+        debug::panic_crash_record(panic_info)
+    }));
+    let result = panic::catch_unwind(|| panic!("{}", message));
+    panic::set_hook(hook);
+    assert!(result.is_err());
+}
+
+/// Copy the crash record to the app's buffer, and return it as text.
+fn upload(simulator: &Simulator<hosted::HostedPlatform>) -> String {
+    let len = match simulator.command(0, DRIVER_NUM, 1, 0, 0) {
+        ReturnCode::SuccessWithValue { value } => value,
+        rc => panic!("no record length: {:?}", rc),
+    };
+    let address = hosted::put(simulator, 0, &vec![0; len]);
+    assert_eq!(
+        simulator.allow(0, DRIVER_NUM, 0, address, len),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 2, 0, 0),
+        ReturnCode::SuccessWithValue { value: len }
+    );
+    let mut text = vec![0; len];
+    simulator.read_memory(0, address, &mut text);
+    String::from_utf8(text).unwrap()
+}
+
+#[test]
+fn uploads_the_record_of_the_last_panic() {
+    let _lock = hosted::lock();
+    let simulator = simulator();
+    panic_and_record("ran out of flash");
+
+    let record = debug::crash_record().expect("no crash record");
+    assert!(record.message().ends_with("ran out of flash"));
+    assert!(record.process_name().is_none());
+
+    let text = upload(&simulator);
+    assert!(text.starts_with("Kernel panic: "));
+    assert!(text.contains("ran out of flash\r\n"));
+
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 3, 0, 0),
+        ReturnCode::SUCCESS
+    );
+    assert!(debug::crash_record().is_none());
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 1, 0, 0),
+        ReturnCode::SuccessWithValue { value: 0 }
+    );
+}
+
+#[test]
+fn rejects_a_corrupted_record() {
+    let _lock = hosted::lock();
+    let simulator = simulator();
+    panic_and_record("ran out of flash");
+
+    let record = debug::crash_record().expect("no crash record");
+    // Flip a bit of the message, as a reset that lost power to part of the
+    // RAM might.
+    unsafe {
+        *(record.message().as_ptr() as *mut u8) ^= 1;
+    }
+
+    assert!(debug::crash_record().is_none());
+    assert_eq!(
+        simulator.command(0, DRIVER_NUM, 1, 0, 0),
+        ReturnCode::SuccessWithValue { value: 0 }
+    );
+    assert_eq!(simulator.command(0, DRIVER_NUM, 2, 0, 0), ReturnCode::FAIL);
+}
@@ -0,0 +1,141 @@
+//! Tests for encoding and decoding packets in the net stack.
+
+extern crate capsules;
+
+use capsules::net::ieee802154::MacAddress;
+use capsules::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use capsules::net::ipv6::ipv6::{IP6Header, IP6Packet, IPPayload, TransportHeader};
+use capsules::net::sixlowpan::sixlowpan_compression::{self, Context};
+use capsules::net::stream::SResult;
+use capsules::net::udp::udp::UDPHeader;
+
+const SRC_MAC_ADDR: MacAddress = MacAddress::Long([0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+const DST_MAC_ADDR: MacAddress = MacAddress::Long([0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27]);
+
+fn link_local(iid: u8) -> IPAddr {
+    let mut addr = IPAddr::new();
+    addr.set_unicast_link_local();
+    addr.0[15] = iid;
+    addr
+}
+
+fn mesh_local_context() -> Context {
+    let mut prefix = [0; 16];
+    prefix[..8].copy_from_slice(&[0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    Context {
+        prefix: prefix,
+        prefix_len: 64,
+        id: 0,
+        compress: true,
+    }
+}
+
+#[test]
+fn ip6_header_survives_encoding() {
+    let mut header = IP6Header::new();
+    header.set_dscp(10);
+    header.set_ecn(1);
+    header.set_flow_label(0x12345);
+    header.set_payload_len(300);
+    header.set_next_header(ip6_nh::UDP);
+    header.set_hop_limit(64);
+    header.src_addr = link_local(1);
+    header.dst_addr = link_local(2);
+
+    let mut buf = [0; 40];
+    assert_eq!(header.encode(&mut buf).done().map(|(off, _)| off), Some(40));
+    assert_eq!(buf[0] >> 4, 6);
+    assert_eq!(&buf[4..6], &[0x01, 0x2c]);
+
+    let (off, decoded) = IP6Header::decode(&buf).done().unwrap();
+    assert_eq!(off, 40);
+    assert_eq!(decoded.get_version(), 6);
+    assert_eq!(decoded.get_dscp(), 10);
+    assert_eq!(decoded.get_ecn(), 1);
+    assert_eq!(decoded.get_flow_label(), 0x12345);
+    assert_eq!(decoded.get_payload_len(), 300);
+    assert_eq!(decoded.get_next_header(), ip6_nh::UDP);
+    assert_eq!(decoded.get_hop_limit(), 64);
+    assert_eq!(decoded.src_addr.0, link_local(1).0);
+    assert_eq!(decoded.dst_addr.0, link_local(2).0);
+}
+
+#[test]
+fn ip6_header_needs_the_whole_header() {
+    let mut buf = [0; 39];
+    match IP6Header::new().encode(&mut buf) {
+        SResult::Needed(40) => {}
+        _ => panic!("encoded a header into too small a buffer"),
+    }
+    match IP6Header::decode(&buf) {
+        SResult::Needed(40) => {}
+        _ => panic!("decoded a header from too small a buffer"),
+    }
+}
+
+#[test]
+fn udp_packet_survives_sixlowpan_compression() {
+    let data = b"hello, world";
+    let mut payload = [0; 12];
+    let mut udp_header = UDPHeader::new();
+    udp_header.set_src_port(1000);
+    udp_header.set_dst_port(2000);
+    // As `UDPSender::send` does, since the packet keeps the header it is
+    // created with.
+    udp_header.set_len((8 + data.len()) as u16);
+    let mut packet = IP6Packet::new(IPPayload::new(
+        TransportHeader::UDP(udp_header),
+        &mut payload,
+    ));
+    packet.header.src_addr = link_local(1);
+    packet.header.dst_addr = link_local(2);
+    packet.set_payload(TransportHeader::UDP(udp_header), data);
+    packet.set_transport_checksum();
+    let total_len = packet.get_total_len() as usize;
+    assert_eq!(total_len, 40 + 8 + data.len());
+
+    // The headers are compressed, and the payload follows as it is.
+    let ctx = mesh_local_context();
+    let mut lowpan = [0; 128];
+    let (consumed, written) =
+        sixlowpan_compression::compress(&ctx, &packet, SRC_MAC_ADDR, DST_MAC_ADDR, &mut lowpan)
+            .unwrap();
+    assert_eq!(consumed, 40 + 8);
+    assert!(written < consumed);
+    lowpan[written..written + data.len()].copy_from_slice(data);
+    let lowpan_len = written + data.len();
+
+    let mut uncompressed = [0; 128];
+    let (consumed, written) = sixlowpan_compression::decompress(
+        &ctx,
+        &lowpan[..lowpan_len],
+        SRC_MAC_ADDR,
+        DST_MAC_ADDR,
+        &mut uncompressed,
+        total_len as u16,
+        false,
+    ).unwrap();
+    assert_eq!(lowpan_len - consumed, data.len());
+    assert_eq!(written, 40 + 8);
+    uncompressed[written..written + data.len()].copy_from_slice(data);
+
+    let (_, header) = IP6Header::decode(&uncompressed[..40]).done().unwrap();
+    assert_eq!(header.get_payload_len() as usize, 8 + data.len());
+    assert_eq!(header.get_next_header(), ip6_nh::UDP);
+    assert_eq!(header.get_hop_limit(), packet.header.get_hop_limit());
+    assert_eq!(header.src_addr.0, link_local(1).0);
+    assert_eq!(header.dst_addr.0, link_local(2).0);
+
+    let (_, udp_header) = UDPHeader::decode(&uncompressed[40..48]).done().unwrap();
+    assert_eq!(udp_header.get_src_port(), 1000);
+    assert_eq!(udp_header.get_dst_port(), 2000);
+    assert_eq!(udp_header.get_len() as usize, 8 + data.len());
+    assert_eq!(
+        udp_header.get_cksum(),
+        match packet.payload.header {
+            TransportHeader::UDP(udp_header) => udp_header.get_cksum(),
+            _ => unreachable!(),
+        }
+    );
+    assert_eq!(&uncompressed[48..total_len], data);
+}
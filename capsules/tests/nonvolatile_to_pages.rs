@@ -0,0 +1,176 @@
+//! Tests for splitting nonvolatile storage reads and writes into flash page
+//! operations, on the hosted chip's flash.
+
+extern crate capsules;
+extern crate hosted;
+extern crate kernel;
+
+use capsules::nonvolatile_to_pages::NonvolatileToPages;
+use hosted::flash::PAGE_SIZE;
+use hosted::{HostedChip, HostedFlash, HostedPage};
+use kernel::common::cells::TakeCell;
+use kernel::hil::flash::HasClient;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::ReturnCode;
+use std::cell::Cell;
+
+const NUM_PAGES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Done {
+    Read(usize),
+    Write(usize),
+}
+
+/// Keeps the buffer it gets back and what it was told.
+struct Client {
+    buffer: TakeCell<'static, [u8]>,
+    done: Cell<Option<Done>>,
+}
+
+impl NonvolatileStorageClient for Client {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.done.set(Some(Done::Read(length)));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.done.set(Some(Done::Write(length)));
+    }
+}
+
+struct Board {
+    chip: HostedChip,
+    flash: &'static HostedFlash,
+    storage: &'static NonvolatileToPages<'static, HostedFlash>,
+    client: &'static Client,
+}
+
+impl Board {
+    fn new() -> Board {
+        let flash = hosted::leak(HostedFlash::new(NUM_PAGES));
+        let storage = hosted::leak(NonvolatileToPages::new(
+            &*flash,
+            hosted::leak(HostedPage::new()),
+        ));
+        flash.set_client(storage);
+        let client = hosted::leak(Client {
+            buffer: TakeCell::empty(),
+            done: Cell::new(None),
+        });
+        storage.set_client(client);
+
+        let mut chip = HostedChip::new();
+        chip.set_flash(flash);
+
+        Board {
+            chip: chip,
+            flash: flash,
+            storage: storage,
+            client: client,
+        }
+    }
+
+    fn run(&mut self) -> Option<Done> {
+        self.chip.run_until_idle();
+        self.client.done.take()
+    }
+
+    fn returned_buffer(&self) -> Vec<u8> {
+        self.client
+            .buffer
+            .map_or(Vec::new(), |buffer| buffer.to_vec())
+    }
+}
+
+fn buffer(data: &[u8]) -> &'static mut [u8] {
+    Box::leak(data.to_vec().into_boxed_slice())
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+#[test]
+fn reads_across_pages() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let contents = pattern(NUM_PAGES * PAGE_SIZE);
+    board.flash.load(0, &contents);
+
+    let address = PAGE_SIZE - 100;
+    let length = PAGE_SIZE + 200;
+    assert_eq!(
+        board
+            .storage
+            .read(buffer(&vec![0; length]), address, length),
+        ReturnCode::SUCCESS
+    );
+
+    assert_eq!(board.run(), Some(Done::Read(length)));
+    assert_eq!(
+        board.returned_buffer(),
+        &contents[address..address + length]
+    );
+}
+
+#[test]
+fn writes_whole_pages() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+
+    let data = pattern(2 * PAGE_SIZE);
+    assert_eq!(
+        board.storage.write(buffer(&data), PAGE_SIZE, data.len()),
+        ReturnCode::SUCCESS
+    );
+
+    assert_eq!(board.run(), Some(Done::Write(data.len())));
+    let contents = board.flash.contents();
+    assert_eq!(&contents[PAGE_SIZE..3 * PAGE_SIZE], &data[..]);
+    assert!(contents[..PAGE_SIZE].iter().all(|&byte| byte == 0xFF));
+    assert!(contents[3 * PAGE_SIZE..].iter().all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn writes_within_pages_keep_the_rest_of_the_page() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let mut expected = pattern(NUM_PAGES * PAGE_SIZE);
+    board.flash.load(0, &expected);
+
+    let address = PAGE_SIZE / 2;
+    let data = vec![0xA5; 2 * PAGE_SIZE];
+    assert_eq!(
+        board.storage.write(buffer(&data), address, data.len()),
+        ReturnCode::SUCCESS
+    );
+
+    assert_eq!(board.run(), Some(Done::Write(data.len())));
+    expected[address..address + data.len()].copy_from_slice(&data);
+    assert!(board.flash.contents() == expected);
+}
+
+#[test]
+fn is_busy_until_an_operation_completes() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+
+    assert_eq!(
+        board.storage.read(buffer(&[0; 16]), 0, 16),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(
+        board.storage.write(buffer(&[0; 16]), 0, 16),
+        ReturnCode::EBUSY
+    );
+    assert_eq!(board.run(), Some(Done::Read(16)));
+
+    assert_eq!(
+        board.storage.write(buffer(&[1; 16]), 0, 16),
+        ReturnCode::SUCCESS
+    );
+    assert_eq!(board.run(), Some(Done::Write(16)));
+    assert_eq!(&board.flash.contents()[..16], &[1; 16]);
+}
@@ -0,0 +1,149 @@
+//! Tests for the process console, typing commands on a hosted UART with
+//! processes simulated on the hosted chip.
+
+extern crate capsules;
+extern crate hosted;
+#[macro_use(create_capability)]
+extern crate kernel;
+
+use capsules::process_console::ProcessConsole;
+use hosted::{App, BoardBuilder, HostedChip, HostedPlatform, HostedUart};
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::uart::UART;
+use kernel::procs::{ProcessManager, Simulator, State};
+use kernel::ReturnCode;
+
+const PROMPT: &str = "tock$ ";
+
+struct Board {
+    chip: HostedChip,
+    uart: &'static HostedUart,
+    manager: &'static ProcessManager,
+    simulator: Simulator<'static, HostedPlatform>,
+}
+
+/// A board with two apps and a process console whose output buffer is
+/// `write_len` bytes long.
+fn board(write_len: usize) -> Board {
+    let mut builder = BoardBuilder::new();
+    let uart = hosted::leak(HostedUart::new());
+    let manager = hosted::leak(ProcessManager::new(&create_capability!(
+        ProcessManagementCapability
+    )));
+    let console = hosted::leak(ProcessConsole::new(
+        &*uart,
+        115200,
+        manager,
+        hosted::leak(vec![0; write_len]).as_mut_slice(),
+        hosted::leak([0; 1]),
+        hosted::leak([0; 32]),
+    ));
+    uart.set_client(console);
+    builder.chip.add_peripheral(uart);
+    console.start();
+
+    let (mut chip, simulator) = builder.simulate(&[App::new("blink"), App::new("sensors")]);
+    chip.run_until_idle();
+    uart.take_output();
+    Board {
+        chip: chip,
+        uart: uart,
+        manager: manager,
+        simulator: simulator,
+    }
+}
+
+/// Tell the kernel where the app's stack and heap start, as the app's
+/// runtime does before `main`, so that its status can be shown.
+fn set_stack_and_heap_start(board: &Board, app_idx: usize) {
+    let start = match board.simulator.memop(app_idx, 2, 0) {
+        ReturnCode::SuccessWithValue { value } => value,
+        rc => panic!("no memory start: {:?}", rc),
+    };
+    // The stack ends where the kernel initially puts the heap, 128 bytes in.
+    board.simulator.memop(app_idx, 10, start + 128);
+    board.simulator.memop(app_idx, 11, start + 128);
+}
+
+/// Type `line` and return what the console prints, including the echo of
+/// `line` itself.
+fn type_line(board: &mut Board, line: &str) -> String {
+    for &byte in line.as_bytes().iter().chain(b"\r") {
+        assert_eq!(board.uart.receive_bytes(&[byte]), 1);
+        board.chip.run_until_idle();
+    }
+    String::from_utf8(board.uart.take_output()).unwrap()
+}
+
+/// What `status` prints for process `app_idx`.
+fn statistics(board: &Board, app_idx: usize) -> String {
+    let mut statistics = String::new();
+    board
+        .manager
+        .write_statistics(app_idx, &mut statistics)
+        .unwrap();
+    statistics
+}
+
+#[test]
+fn lists_the_processes() {
+    let _lock = hosted::lock();
+    let mut board = board(2048);
+
+    let output = type_line(&mut board, "list");
+    let lines: Vec<&str> = output.split("\r\n").collect();
+    assert_eq!(lines[0], "list");
+    assert!(lines[1].starts_with(" Index  Name"));
+    assert!(lines[2].starts_with(" 0      blink "));
+    assert!(lines[3].starts_with(" 1      sensors "));
+    assert_eq!(lines[4], PROMPT);
+}
+
+#[test]
+fn shows_the_status_of_a_process() {
+    let _lock = hosted::lock();
+    let mut board = board(2048);
+    set_stack_and_heap_start(&board, 1);
+
+    let expected = format!("status sensors\r\n{}\r\n{}", statistics(&board, 1), PROMPT);
+    assert_eq!(type_line(&mut board, "status sensors"), expected);
+    assert_eq!(
+        type_line(&mut board, "status radio"),
+        format!("status radio\r\nNo process named radio\r\n{}", PROMPT)
+    );
+}
+
+#[test]
+fn sends_a_status_longer_than_its_buffer_in_parts() {
+    let _lock = hosted::lock();
+    let mut board = board(64);
+    set_stack_and_heap_start(&board, 0);
+
+    let statistics = statistics(&board, 0);
+    assert!(statistics.len() > 64);
+    let expected = format!("status blink\r\n{}\r\n{}", statistics, PROMPT);
+    assert_eq!(type_line(&mut board, "status blink"), expected);
+}
+
+#[test]
+fn stops_and_starts_a_process() {
+    let _lock = hosted::lock();
+    let mut board = board(2048);
+
+    assert_eq!(
+        type_line(&mut board, "stop blink"),
+        format!("stop blink\r\nstop blink: done\r\n{}", PROMPT)
+    );
+    assert_eq!(board.manager.info(0).unwrap().state, State::Stopped);
+    assert_eq!(board.manager.info(1).unwrap().state, State::Yielded);
+    assert_eq!(
+        type_line(&mut board, "stop blink"),
+        format!(
+            "stop blink\r\nstop blink: already in that state\r\n{}",
+            PROMPT
+        )
+    );
+
+    type_line(&mut board, "start blink");
+    assert_ne!(board.manager.info(0).unwrap().state, State::Stopped);
+}
@@ -0,0 +1,128 @@
+//! Tests for loading and unloading processes at runtime, with the processes
+//! simulated on the hosted chip.
+
+extern crate hosted;
+#[macro_use(create_capability)]
+extern crate kernel;
+
+use hosted::{App, BoardBuilder, HostedPlatform};
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::procs::{FaultResponse, LoadError, ProcessLoader, ProcessManager, Simulator};
+use kernel::ReturnCode;
+
+struct Board {
+    loader: ProcessLoader,
+    manager: ProcessManager,
+    /// The address of each app in flash.
+    apps: Vec<usize>,
+    /// The start of the RAM the loader gives apps.
+    memory: usize,
+    simulator: Simulator<'static, HostedPlatform>,
+}
+
+/// A board with `apps` in flash, none of them loaded, `memory_len` bytes of
+/// RAM for apps and `slots` slots for processes.
+fn board(apps: &[App], memory_len: usize, slots: usize) -> Board {
+    let builder = BoardBuilder::new();
+    let kernel = builder.kernel();
+    let (flash, addresses) = hosted::app_flash(apps);
+    let memory = hosted::app_memory(memory_len);
+    let capability = create_capability!(ProcessManagementCapability);
+    let loader = unsafe {
+        ProcessLoader::new(
+            kernel,
+            flash.as_ptr(),
+            flash.len(),
+            memory.as_mut_ptr(),
+            memory.len(),
+            FaultResponse::Panic,
+            None,
+            &capability,
+        )
+    };
+    let (_, simulator) = builder.simulate_slots(slots);
+    Board {
+        loader: loader,
+        manager: ProcessManager::new(&capability),
+        apps: addresses,
+        memory: memory.as_ptr() as usize,
+        simulator: simulator,
+    }
+}
+
+/// Load app `app` and return the index of its process.
+fn load(board: &Board, app: usize) -> Result<usize, LoadError> {
+    board.loader.load(board.apps[app]).map(|appid| appid.idx())
+}
+
+/// The offset from the start of the app RAM where the memory of process
+/// `app_idx` starts, and its size.
+fn memory(board: &Board, app_idx: usize) -> (usize, usize) {
+    let address = |op_type| match board.simulator.memop(app_idx, op_type, 0) {
+        ReturnCode::SuccessWithValue { value } => value,
+        rc => panic!("no memory address: {:?}", rc),
+    };
+    let (start, end) = (address(2), address(3));
+    (start - board.memory, end - start)
+}
+
+#[test]
+fn reuses_the_slot_and_memory_of_an_unloaded_process() {
+    let _lock = hosted::lock();
+    let apps = [
+        App::new("first").minimum_ram_size(8192),
+        App::new("second").minimum_ram_size(8192),
+        App::new("third").minimum_ram_size(8192),
+    ];
+    let board = board(&apps, 2 * 8192, 3);
+
+    assert_eq!(load(&board, 0), Ok(0));
+    assert_eq!(load(&board, 1), Ok(1));
+    assert_eq!(memory(&board, 0), (0, 8192));
+    assert_eq!(memory(&board, 1), (8192, 8192));
+    assert_eq!(load(&board, 2), Err(LoadError::OutOfMemory));
+
+    assert_eq!(board.loader.unload(0), Ok(()));
+    assert_eq!(board.loader.unload(0), Err(LoadError::NoSuchProcess));
+    assert_eq!(board.manager.find_by_name("first"), None);
+
+    assert_eq!(load(&board, 2), Ok(0));
+    assert_eq!(board.manager.find_by_name("third"), Some(0));
+    assert_eq!(memory(&board, 0), (0, 8192));
+    // The second app is still loaded, so it cannot be loaded again.
+    assert_eq!(load(&board, 1), Err(LoadError::InvalidAddress));
+}
+
+#[test]
+fn places_apps_in_the_first_aligned_gap_that_fits() {
+    let _lock = hosted::lock();
+    let apps = [
+        App::new("small").minimum_ram_size(4096),
+        App::new("large").minimum_ram_size(8192),
+        App::new("other_small").minimum_ram_size(4096),
+        App::new("last_small").minimum_ram_size(4096),
+    ];
+    let board = board(&apps, 4 * 4096, 4);
+
+    assert_eq!(load(&board, 0), Ok(0));
+    // The large app's memory must be aligned to its size, so it skips the
+    // rest of the first 8192 bytes.
+    assert_eq!(load(&board, 1), Ok(1));
+    assert_eq!(memory(&board, 1), (8192, 8192));
+    // Which a small app then fills.
+    assert_eq!(load(&board, 2), Ok(2));
+    assert_eq!(memory(&board, 2), (4096, 4096));
+    assert_eq!(load(&board, 3), Err(LoadError::OutOfMemory));
+}
+
+#[test]
+fn needs_a_free_slot() {
+    let _lock = hosted::lock();
+    let apps = [App::new("first"), App::new("second")];
+    let board = board(&apps, 4 * 4096, 1);
+
+    assert_eq!(load(&board, 0), Ok(0));
+    assert_eq!(load(&board, 1), Err(LoadError::NoFreeSlot));
+    assert_eq!(board.loader.unload(0), Ok(()));
+    assert_eq!(load(&board, 1), Ok(0));
+}
@@ -0,0 +1,197 @@
+//! Tests for the kernel's syscall trace and the driver that lets a privileged
+//! app read it, with processes simulated on the hosted chip.
+
+extern crate capsules;
+extern crate hosted;
+#[macro_use(create_capability)]
+extern crate kernel;
+
+use capsules::syscall_trace::{SyscallTraceDriver, DRIVER_NUM};
+use hosted::{App, BoardBuilder, HostedAlarm, HostedPlatform};
+use kernel::capabilities::KernelConfigurationCapability;
+use kernel::procs::{Simulator, Syscall, SyscallRecord, SyscallTrace};
+use kernel::ReturnCode;
+
+/// The privileged app that reads the trace.
+const TRACER: usize = 0;
+/// An app that may not use the trace driver.
+const APP: usize = 1;
+
+const RECORD_LEN: usize = 36;
+
+/// A driver number that no driver on the board has.
+const NO_DRIVER: usize = 0xff;
+
+struct Board {
+    trace: &'static SyscallTrace,
+    alarm: &'static HostedAlarm,
+    simulator: Simulator<'static, HostedPlatform>,
+}
+
+/// A board whose trace keeps `len` syscalls.
+fn board(len: usize) -> Board {
+    let mut builder = BoardBuilder::new();
+    let alarm = hosted::leak(HostedAlarm::new());
+    let records = hosted::leak(vec![SyscallRecord::EMPTY; len]).as_mut_slice();
+    let trace = hosted::leak(SyscallTrace::new(records, Some(alarm)));
+    builder
+        .kernel()
+        .set_syscall_trace(trace, &create_capability!(KernelConfigurationCapability));
+
+    let driver = hosted::leak(SyscallTraceDriver::new(trace, builder.grant()));
+    builder.platform.add_driver(DRIVER_NUM, driver);
+    let (_, simulator) =
+        builder.simulate(&[App::new("tracer").permit(DRIVER_NUM, !0), App::new("app")]);
+    Board {
+        trace: trace,
+        alarm: alarm,
+        simulator: simulator,
+    }
+}
+
+/// `(app_idx, syscall, driver_num, r1, return_code)` of every record in the
+/// trace, oldest first.
+fn records(trace: &SyscallTrace) -> Vec<(usize, usize, usize, usize, Option<ReturnCode>)> {
+    (0..trace.len())
+        .map(|index| trace.get(index).unwrap())
+        .map(|record| {
+            (
+                record.app_idx,
+                record.syscall as usize,
+                record.driver_num,
+                record.args[0],
+                record.return_code,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn records_syscalls_and_what_they_returned() {
+    let _lock = hosted::lock();
+    let board = board(8);
+    let simulator = &board.simulator;
+
+    board.alarm.advance(100);
+    simulator.command(APP, DRIVER_NUM, 0, 0, 0);
+    board.alarm.advance(50);
+    simulator.command(APP, DRIVER_NUM, 1, 0, 0);
+    simulator.subscribe(APP, NO_DRIVER, 0, 0x1001, 0);
+
+    let command = Syscall::COMMAND as usize;
+    let subscribe = Syscall::SUBSCRIBE as usize;
+    assert_eq!(
+        records(board.trace),
+        vec![
+            (APP, command, DRIVER_NUM, 0, Some(ReturnCode::SUCCESS)),
+            (APP, command, DRIVER_NUM, 1, Some(ReturnCode::ENOPERM)),
+            (APP, subscribe, NO_DRIVER, 0, Some(ReturnCode::ENODEVICE)),
+        ]
+    );
+    assert_eq!(board.trace.get(0).unwrap().timestamp, 100);
+    assert_eq!(board.trace.get(1).unwrap().timestamp, 150);
+    assert!(board.trace.get(3).is_none());
+}
+
+#[test]
+fn overwrites_the_oldest_syscalls_when_full() {
+    let _lock = hosted::lock();
+    let board = board(4);
+    let simulator = &board.simulator;
+
+    for command_num in 0..6 {
+        simulator.command(APP, DRIVER_NUM, command_num, 0, 0);
+    }
+
+    assert_eq!(board.trace.len(), 4);
+    let command_nums: Vec<usize> = records(board.trace).iter().map(|record| record.3).collect();
+    assert_eq!(command_nums, vec![2, 3, 4, 5]);
+}
+
+#[test]
+fn only_traces_the_processes_in_the_filter() {
+    let _lock = hosted::lock();
+    let board = board(8);
+    let simulator = &board.simulator;
+
+    // Trace only the app. The tracer's own command is not recorded, since
+    // the filter is set by the time it returns.
+    assert_eq!(
+        simulator.command(TRACER, DRIVER_NUM, 3, 1 << APP, 0),
+        ReturnCode::SUCCESS
+    );
+    simulator.command(TRACER, DRIVER_NUM, 1, 0, 0);
+    simulator.command(APP, DRIVER_NUM, 0, 0, 0);
+    assert_eq!(board.trace.filter(), 1 << APP);
+    assert_eq!(
+        records(board.trace),
+        vec![(
+            APP,
+            Syscall::COMMAND as usize,
+            DRIVER_NUM,
+            0,
+            Some(ReturnCode::SUCCESS)
+        )]
+    );
+
+    // Trace every process again.
+    simulator.command(TRACER, DRIVER_NUM, 3, !0, 0);
+    simulator.command(TRACER, DRIVER_NUM, 4, 0, 0);
+    simulator.command(APP, DRIVER_NUM, 0, 0, 0);
+    assert_eq!(
+        records(board.trace)
+            .iter()
+            .map(|record| record.0)
+            .collect::<Vec<_>>(),
+        vec![TRACER, APP]
+    );
+}
+
+#[test]
+fn copies_records_to_the_tracer() {
+    let _lock = hosted::lock();
+    let board = board(8);
+    let simulator = &board.simulator;
+
+    simulator.command(APP, DRIVER_NUM, 1, 7, 0);
+    let address = hosted::put(simulator, TRACER, &[0; 2 * RECORD_LEN]);
+    simulator.allow(TRACER, DRIVER_NUM, 0, address, 2 * RECORD_LEN);
+    // The trace now holds the app's command, the tracer's memop and the
+    // tracer's allow, of which two fit in the buffer.
+    assert_eq!(
+        simulator.command(TRACER, DRIVER_NUM, 1, 0, 0),
+        ReturnCode::SuccessWithValue { value: 3 }
+    );
+    assert_eq!(
+        simulator.command(TRACER, DRIVER_NUM, 2, 0, 0),
+        ReturnCode::SuccessWithValue { value: 2 }
+    );
+
+    let mut buffer = [0; 2 * RECORD_LEN];
+    simulator.read_memory(TRACER, address, &mut buffer);
+    let words: Vec<u32> = buffer
+        .chunks(4)
+        .map(|word| {
+            word.iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as u32)
+        })
+        .collect();
+    let enoperm = -14i32 as u32;
+    assert_eq!(
+        &words[..9],
+        &[
+            APP as u32,
+            Syscall::COMMAND as u32,
+            DRIVER_NUM as u32,
+            1,
+            7,
+            0,
+            1,
+            enoperm,
+            0,
+        ]
+    );
+    assert_eq!(words[9], TRACER as u32);
+    assert_eq!(words[10], Syscall::MEMOP as u32);
+}
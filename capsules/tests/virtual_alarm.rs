@@ -0,0 +1,190 @@
+//! Tests for virtualizing one alarm among several clients, on the hosted
+//! chip.
+
+extern crate capsules;
+extern crate hosted;
+extern crate kernel;
+
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use hosted::{HostedAlarm, HostedChip};
+use kernel::hil::time::{self, Alarm, Time};
+use std::cell::{Cell, RefCell};
+
+type Log = RefCell<Vec<(&'static str, u32)>>;
+
+/// Logs when its alarm fires, and sets it again `period` later while it has
+/// repeats left.
+struct Client {
+    name: &'static str,
+    alarm: &'static VirtualMuxAlarm<'static, HostedAlarm>,
+    log: &'static Log,
+    period: Cell<u32>,
+    repeats: Cell<usize>,
+}
+
+impl time::Client for Client {
+    fn fired(&self) {
+        let now = self.alarm.now();
+        self.log.borrow_mut().push((self.name, now));
+        if self.repeats.get() > 0 {
+            self.repeats.set(self.repeats.get() - 1);
+            self.alarm.set_alarm(now.wrapping_add(self.period.get()));
+        }
+    }
+}
+
+struct Board {
+    chip: HostedChip,
+    alarm: &'static HostedAlarm,
+    mux: &'static MuxAlarm<'static, HostedAlarm>,
+    log: &'static Log,
+}
+
+impl Board {
+    fn new() -> Board {
+        let alarm = hosted::leak(HostedAlarm::new());
+        let mux = hosted::leak(MuxAlarm::new(&*alarm));
+        alarm.set_client(mux);
+
+        let mut chip = HostedChip::new();
+        chip.set_alarm(alarm);
+
+        Board {
+            chip: chip,
+            alarm: alarm,
+            mux: mux,
+            log: hosted::leak(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn virtual_alarm(&self, name: &'static str) -> &'static Client {
+        let alarm = hosted::leak(VirtualMuxAlarm::new(self.mux));
+        let client = hosted::leak(Client {
+            name: name,
+            alarm: alarm,
+            log: self.log,
+            period: Cell::new(0),
+            repeats: Cell::new(0),
+        });
+        alarm.set_client(client);
+        client
+    }
+
+    fn sleep(&mut self) {
+        self.chip.wait_for_alarm();
+    }
+
+    fn take_log(&self) -> Vec<(&'static str, u32)> {
+        self.log.replace(Vec::new())
+    }
+}
+
+#[test]
+fn fires_each_alarm_at_its_time() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let first = board.virtual_alarm("first");
+    let second = board.virtual_alarm("second");
+    let third = board.virtual_alarm("third");
+
+    first.alarm.set_alarm(300);
+    second.alarm.set_alarm(100);
+    third.alarm.set_alarm(200);
+    for _ in 0..3 {
+        board.sleep();
+    }
+
+    assert_eq!(
+        board.take_log(),
+        vec![("second", 100), ("third", 200), ("first", 300)]
+    );
+    assert!(!board.alarm.is_armed());
+}
+
+#[test]
+fn fires_alarms_due_at_the_same_time_together() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let first = board.virtual_alarm("first");
+    let second = board.virtual_alarm("second");
+
+    first.alarm.set_alarm(100);
+    second.alarm.set_alarm(100);
+    board.sleep();
+
+    let mut log = board.take_log();
+    log.sort();
+    assert_eq!(log, vec![("first", 100), ("second", 100)]);
+    assert_eq!(board.chip.sleep_count(), 1);
+}
+
+#[test]
+fn disabled_alarms_do_not_fire() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let first = board.virtual_alarm("first");
+    let second = board.virtual_alarm("second");
+
+    first.alarm.set_alarm(100);
+    second.alarm.set_alarm(200);
+    first.alarm.disable();
+    assert!(!first.alarm.is_armed());
+    // The underlying alarm was set for the first alarm, so the chip still
+    // wakes up for it, but nothing fires until the second alarm is due.
+    board.sleep();
+    assert_eq!(board.alarm.now(), 100);
+    assert_eq!(board.take_log(), vec![]);
+    board.sleep();
+    assert_eq!(board.take_log(), vec![("second", 200)]);
+
+    second.alarm.set_alarm(300);
+    second.alarm.disable();
+    assert!(!board.alarm.is_armed());
+    board.sleep();
+    assert_eq!(board.take_log(), vec![]);
+}
+
+#[test]
+fn clients_can_set_their_alarm_again_when_it_fires() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let periodic = board.virtual_alarm("periodic");
+    let oneshot = board.virtual_alarm("oneshot");
+    periodic.period.set(50);
+    periodic.repeats.set(2);
+
+    periodic.alarm.set_alarm(50);
+    oneshot.alarm.set_alarm(120);
+    for _ in 0..4 {
+        board.sleep();
+    }
+
+    assert_eq!(
+        board.take_log(),
+        vec![
+            ("periodic", 50),
+            ("periodic", 100),
+            ("oneshot", 120),
+            ("periodic", 150),
+        ]
+    );
+}
+
+#[test]
+fn handles_the_clock_wrapping_around() {
+    let _lock = hosted::lock();
+    let mut board = Board::new();
+    let first = board.virtual_alarm("first");
+    let second = board.virtual_alarm("second");
+    board.alarm.advance(u32::max_value() - 9);
+
+    first.alarm.set_alarm(20);
+    second.alarm.set_alarm(u32::max_value() - 4);
+    board.sleep();
+    board.sleep();
+
+    assert_eq!(
+        board.take_log(),
+        vec![("second", u32::max_value() - 4), ("first", 20)]
+    );
+}
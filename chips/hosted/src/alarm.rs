@@ -0,0 +1,89 @@
+//! A simulated alarm, whose time only passes when the test or the sleeping
+//! chip moves it forward.
+
+use chip::Peripheral;
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Alarm, Freq16KHz, Time};
+use std::cell::Cell;
+
+pub struct HostedAlarm {
+    now: Cell<u32>,
+    alarm: Cell<u32>,
+    armed: Cell<bool>,
+    interrupt: Cell<bool>,
+    client: OptionalCell<&'static time::Client>,
+}
+
+impl HostedAlarm {
+    pub fn new() -> HostedAlarm {
+        HostedAlarm {
+            now: Cell::new(0),
+            alarm: Cell::new(0),
+            armed: Cell::new(false),
+            interrupt: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'static time::Client) {
+        self.client.set(client);
+    }
+
+    /// Let `ticks` pass. If that reaches the alarm, it fires.
+    pub fn advance(&self, ticks: u32) {
+        let until_alarm = self.alarm.get().wrapping_sub(self.now.get());
+        self.now.set(self.now.get().wrapping_add(ticks));
+        if self.armed.get() && until_alarm <= ticks {
+            self.armed.set(false);
+            self.interrupt.set(true);
+        }
+    }
+
+    /// Let time pass until the alarm fires, if it is armed. An alarm set to
+    /// the current time fires without time passing.
+    pub fn skip_to_alarm(&self) {
+        if self.armed.get() {
+            let until_alarm = self.alarm.get().wrapping_sub(self.now.get());
+            self.advance(until_alarm);
+        }
+    }
+}
+
+impl Time for HostedAlarm {
+    type Frequency = Freq16KHz;
+
+    fn disable(&self) {
+        self.armed.set(false);
+        self.interrupt.set(false);
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+}
+
+impl Alarm for HostedAlarm {
+    fn now(&self) -> u32 {
+        self.now.get()
+    }
+
+    fn set_alarm(&self, tics: u32) {
+        self.alarm.set(tics);
+        self.armed.set(true);
+    }
+
+    fn get_alarm(&self) -> u32 {
+        self.alarm.get()
+    }
+}
+
+impl Peripheral for HostedAlarm {
+    fn interrupt_pending(&self) -> bool {
+        self.interrupt.get()
+    }
+
+    fn handle_interrupt(&self) {
+        self.interrupt.set(false);
+        self.client.map(|client| client.fired());
+    }
+}
@@ -0,0 +1,250 @@
+//! Building and loading apps for simulated processes.
+//!
+//! A simulated process never runs its code, but the kernel still loads it
+//! from a TBF header, which sets its name, memory and permissions.
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::procs::{self, AppVerifier, FaultResponse, Process};
+use kernel::Kernel;
+use std::mem;
+use std::slice;
+
+/// Memory set aside for each app. Apps get the smallest power of two that
+/// fits what they ask for, so this must be at least that large.
+const APP_MEMORY_SIZE: usize = 64 * 1024;
+
+/// Bytes of code after the header of each app.
+const CODE_SIZE: usize = 4;
+
+/// An app to load as a simulated process.
+pub struct App {
+    name: String,
+    minimum_ram_size: u32,
+    /// `(driver_num, allowed_commands)` the app may use, if it is restricted.
+    permissions: Option<Vec<(u32, u64)>>,
+    /// `(format, data)` of the credentials in the app's footers.
+    credentials: Vec<(u32, Vec<u8>)>,
+}
+
+impl App {
+    /// An app named `name` that may use every driver.
+    pub fn new(name: &str) -> App {
+        App {
+            name: name.to_string(),
+            minimum_ram_size: 4096,
+            permissions: None,
+            credentials: Vec::new(),
+        }
+    }
+
+    /// Ask for at least `size` bytes of memory.
+    pub fn minimum_ram_size(mut self, size: u32) -> App {
+        self.minimum_ram_size = size;
+        self
+    }
+
+    /// Restrict the app to the drivers it is permitted, and permit driver
+    /// `driver_num` with the commands below 64 set in `allowed_commands`.
+    pub fn permit(mut self, driver_num: usize, allowed_commands: u64) -> App {
+        self.permissions
+            .get_or_insert_with(Vec::new)
+            .push((driver_num as u32, allowed_commands));
+        self
+    }
+
+    /// Add a credential in format `format` to the app's footers.
+    pub fn credential(mut self, format: u32, data: &[u8]) -> App {
+        self.credentials.push((format, data.to_vec()));
+        self
+    }
+
+    /// Add a credential in format `format` that `compute` derives from the
+    /// part of the app credentials cover, and that is `len` bytes long.
+    ///
+    /// Credentials added afterwards change the app's total size, and so the
+    /// part `compute` was given.
+    pub fn computed_credential<F>(mut self, format: u32, len: usize, compute: F) -> App
+    where
+        F: FnOnce(&[u8]) -> Vec<u8>,
+    {
+        self.credentials.push((format, vec![0; len]));
+        let data = compute(&self.binary());
+        assert_eq!(data.len(), len, "credential has the wrong length");
+        self.credentials.last_mut().unwrap().1 = data;
+        self
+    }
+
+    /// The part of the app that credentials cover: the header and the code.
+    pub fn binary(&self) -> Vec<u8> {
+        let mut image = self.image();
+        let binary_end = read_u32(&image[2..4]) as usize + CODE_SIZE;
+        image.truncate(binary_end);
+        image
+    }
+
+    /// The TBF image of the app: a version 2 header, a few bytes of code that
+    /// never run, and the footers.
+    pub fn image(&self) -> Vec<u8> {
+        let mut footers = Vec::new();
+        for &(format, ref data) in &self.credentials {
+            let mut credential = Vec::new();
+            push_u32(&mut credential, format);
+            credential.extend_from_slice(data);
+            push_tlv(&mut footers, 128, &credential);
+        }
+
+        let mut tlvs = Vec::new();
+
+        // Main: init function offset, protected size and minimum RAM size.
+        // They are filled in below.
+        push_tlv(&mut tlvs, 1, &[0; 12]);
+        push_tlv(&mut tlvs, 3, self.name.as_bytes());
+        if let Some(ref permissions) = self.permissions {
+            let mut entries = Vec::new();
+            for &(driver_num, allowed_commands) in permissions {
+                push_u32(&mut entries, driver_num);
+                push_u32(&mut entries, 0);
+                push_u32(&mut entries, allowed_commands as u32);
+                push_u32(&mut entries, (allowed_commands >> 32) as u32);
+            }
+            push_tlv(&mut tlvs, 8, &entries);
+        }
+        // Footer: the binary end offset, filled in below.
+        push_tlv(&mut tlvs, 9, &[0; 4]);
+
+        let header_size = 16 + tlvs.len();
+        let binary_end = header_size + CODE_SIZE;
+        let total_size = binary_end + footers.len();
+        let mut image = Vec::with_capacity(total_size);
+        push_u32(&mut image, 2 | (header_size as u32) << 16);
+        push_u32(&mut image, total_size as u32);
+        // Enabled.
+        push_u32(&mut image, 1);
+        // Checksum.
+        push_u32(&mut image, 0);
+        image.extend_from_slice(&tlvs);
+
+        // Both offsets count from the end of the header. The init function
+        // must be a Thumb address, so its offset is odd, and only the header
+        // is protected.
+        write_u32(&mut image, 20, 1);
+        write_u32(&mut image, 24, 0);
+        write_u32(&mut image, 28, self.minimum_ram_size);
+        write_u32(&mut image, header_size - 4, binary_end as u32);
+
+        let checksum = image
+            .chunks(4)
+            .enumerate()
+            .filter(|&(i, _)| i != 3)
+            .fold(0, |checksum, (_, word)| checksum ^ read_u32(word));
+        write_u32(&mut image, 12, checksum);
+
+        image.extend_from_slice(&[0; CODE_SIZE]);
+        image.extend_from_slice(&footers);
+        image
+    }
+}
+
+/// Load `apps` as processes of `kernel`, in order, and return the process
+/// array to hand to `procs::Simulator::new`. If `app_verifier` is given, the
+/// apps it does not accept leave their slot empty.
+///
+/// The flash and memory of the apps are never freed.
+pub fn load_apps(
+    kernel: &'static Kernel,
+    apps: &[App],
+    app_verifier: Option<&AppVerifier>,
+    capability: &ProcessManagementCapability,
+) -> &'static mut [Option<&'static mut Process<'static>>] {
+    let (flash, _) = app_flash(apps);
+    let memory = leak_aligned(&vec![0; apps.len() * APP_MEMORY_SIZE], 8);
+    let processes = empty_slots(apps.len());
+    unsafe {
+        procs::load_processes(
+            kernel,
+            flash.as_ptr(),
+            memory,
+            processes,
+            FaultResponse::Panic,
+            app_verifier,
+            capability,
+        );
+    }
+    processes
+}
+
+/// Write the images of `apps` to flash one after another, as a board's apps
+/// are, and return the flash with the address of each app in it.
+///
+/// The flash is never freed.
+pub fn app_flash(apps: &[App]) -> (&'static [u8], Vec<usize>) {
+    let mut flash = Vec::new();
+    let mut offsets = Vec::new();
+    for app in apps {
+        offsets.push(flash.len());
+        flash.extend_from_slice(&app.image());
+    }
+    // Zeroed flash after the last app ends the list of apps.
+    flash.extend_from_slice(&[0; 16]);
+
+    // The kernel reads the headers a word at a time.
+    let flash = leak_aligned(&flash, 8);
+    let start = flash.as_ptr() as usize;
+    (flash, offsets.iter().map(|offset| start + offset).collect())
+}
+
+/// `len` bytes of zeroed memory for apps, aligned to `len` so that apps are
+/// placed as they would be at the start of a board's app memory. `len` must
+/// be a power of two.
+///
+/// The memory is never freed.
+pub fn app_memory(len: usize) -> &'static mut [u8] {
+    leak_aligned(&vec![0; len], len)
+}
+
+/// A process array of `len` empty slots.
+pub fn empty_slots(len: usize) -> &'static mut [Option<&'static mut Process<'static>>] {
+    let processes: Vec<Option<&'static mut Process<'static>>> = (0..len).map(|_| None).collect();
+    Box::leak(processes.into_boxed_slice())
+}
+
+/// Copy `bytes` to memory that is aligned to `align` bytes and never freed.
+/// `align` must be a power of two of at least 8.
+fn leak_aligned(bytes: &[u8], align: usize) -> &'static mut [u8] {
+    // Allocate enough to start at the first aligned address in the
+    // allocation.
+    let words = vec![0u64; (bytes.len() + align) / 8];
+    let words = Box::leak(words.into_boxed_slice());
+    let start = words.as_mut_ptr() as usize;
+    let offset = (align - start % align) % align;
+    let aligned = unsafe { slice::from_raw_parts_mut((start + offset) as *mut u8, bytes.len()) };
+    aligned.copy_from_slice(bytes);
+    aligned
+}
+
+/// Append a TLV entry, padded to a multiple of four bytes.
+fn push_tlv(buf: &mut Vec<u8>, tipe: u16, data: &[u8]) {
+    push_u32(buf, tipe as u32 | (data.len() as u32) << 16);
+    buf.extend_from_slice(data);
+    while buf.len() % mem::size_of::<u32>() != 0 {
+        buf.push(0);
+    }
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        buf.push((value >> (8 * i)) as u8);
+    }
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    for i in 0..4 {
+        buf[offset + i] = (value >> (8 * i)) as u8;
+    }
+}
+
+fn read_u32(word: &[u8]) -> u32 {
+    word.iter()
+        .enumerate()
+        .fold(0, |value, (i, &byte)| value | (byte as u32) << (8 * i))
+}
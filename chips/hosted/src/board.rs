@@ -0,0 +1,71 @@
+//! The kernel, chip and platform that tests with processes share.
+
+use app::{empty_slots, load_apps, App};
+use chip::HostedChip;
+use kernel::capabilities::{
+    MainLoopCapability, MemoryAllocationCapability, ProcessManagementCapability,
+};
+use kernel::procs::{Process, Simulator};
+use kernel::{Grant, Kernel};
+use platform::HostedPlatform;
+
+/// Sets up a board whose apps are simulated.
+///
+/// Tests add their peripherals to `chip` and their drivers to `platform`,
+/// then call `simulate` to load the apps.
+pub struct BoardBuilder {
+    pub chip: HostedChip,
+    pub platform: HostedPlatform,
+    kernel: &'static Kernel,
+}
+
+impl BoardBuilder {
+    pub fn new() -> BoardBuilder {
+        BoardBuilder {
+            chip: HostedChip::new(),
+            platform: HostedPlatform::new(),
+            kernel: ::leak(Kernel::new()),
+        }
+    }
+
+    /// The kernel the apps are loaded on, for tests to configure.
+    pub fn kernel(&self) -> &'static Kernel {
+        self.kernel
+    }
+
+    /// Create a grant for a driver, which the apps only get room for if it is
+    /// created before `simulate`.
+    pub fn grant<T: Default>(&self) -> Grant<T> {
+        Grant::create(&create_capability!(MemoryAllocationCapability))
+    }
+
+    /// Load `apps` and return the chip with a simulator for their processes.
+    pub fn simulate(self, apps: &[App]) -> (HostedChip, Simulator<'static, HostedPlatform>) {
+        let processes = load_apps(
+            self.kernel,
+            apps,
+            None,
+            &create_capability!(ProcessManagementCapability),
+        );
+        self.simulate_processes(processes)
+    }
+
+    /// Return the chip with a simulator for `slots` processes that are loaded
+    /// later, for example with a `procs::ProcessLoader`.
+    pub fn simulate_slots(self, slots: usize) -> (HostedChip, Simulator<'static, HostedPlatform>) {
+        self.simulate_processes(empty_slots(slots))
+    }
+
+    fn simulate_processes(
+        self,
+        processes: &'static mut [Option<&'static mut Process<'static>>],
+    ) -> (HostedChip, Simulator<'static, HostedPlatform>) {
+        let simulator = Simulator::new(
+            self.kernel,
+            ::leak(self.platform),
+            processes,
+            &create_capability!(MainLoopCapability),
+        );
+        (self.chip, simulator)
+    }
+}
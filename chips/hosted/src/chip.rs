@@ -0,0 +1,149 @@
+//! Interrupt and deferred call dispatch for the hosted chip.
+
+use alarm::HostedAlarm;
+use deferred_call_tasks::Task;
+use flash::HostedFlash;
+use kernel::common::cells::OptionalCell;
+use kernel::common::deferred_call;
+use kernel::Chip;
+use mpu::HostedMpu;
+use std::cell::Cell;
+use systick::HostedSysTick;
+
+/// A peripheral that can interrupt the hosted chip.
+pub trait Peripheral {
+    /// Whether the peripheral has raised an interrupt that has not been
+    /// handled yet.
+    fn interrupt_pending(&self) -> bool;
+
+    /// Clear the interrupt and tell the peripheral's client what happened.
+    fn handle_interrupt(&self);
+}
+
+pub struct HostedChip {
+    pub mpu: HostedMpu,
+    pub systick: HostedSysTick,
+    /// Peripherals in the order their interrupts are serviced.
+    peripherals: Vec<&'static Peripheral>,
+    /// The alarm that wakes the chip from sleep.
+    alarm: OptionalCell<&'static HostedAlarm>,
+    /// The flash that completes its operations with deferred calls.
+    flash: OptionalCell<&'static HostedFlash>,
+    sleep_count: Cell<usize>,
+}
+
+impl HostedChip {
+    pub fn new() -> HostedChip {
+        // Deferred calls are kept in a global, so drop the ones an earlier
+        // chip left pending.
+        while deferred_call::has_tasks() {
+            deferred_call::DeferredCall::<Task>::next_pending();
+        }
+
+        HostedChip {
+            mpu: HostedMpu::new(),
+            systick: HostedSysTick::new(),
+            peripherals: Vec::new(),
+            alarm: OptionalCell::empty(),
+            flash: OptionalCell::empty(),
+            sleep_count: Cell::new(0),
+        }
+    }
+
+    /// Service the interrupts of `peripheral`, after those of the
+    /// peripherals added before it.
+    pub fn add_peripheral(&mut self, peripheral: &'static Peripheral) {
+        self.peripherals.push(peripheral);
+    }
+
+    /// Use `alarm` as the chip's alarm. Sleeping lets time pass until it
+    /// fires.
+    pub fn set_alarm(&mut self, alarm: &'static HostedAlarm) {
+        self.add_peripheral(alarm);
+        self.alarm.set(alarm);
+    }
+
+    /// Use `flash` as the chip's flash, which completes its operations with
+    /// deferred calls.
+    pub fn set_flash(&mut self, flash: &'static HostedFlash) {
+        self.flash.set(flash);
+    }
+
+    /// Service interrupts and deferred calls until none are left, as the main
+    /// loop does for a board without processes.
+    pub fn run_until_idle(&mut self) {
+        while self.has_pending_interrupts() {
+            self.service_pending_interrupts();
+        }
+    }
+
+    /// Sleep until the alarm fires, and service what that set off.
+    pub fn wait_for_alarm(&mut self) {
+        self.run_until_idle();
+        unsafe {
+            self.atomic(|| Chip::sleep(self));
+        }
+        self.run_until_idle();
+    }
+
+    /// How many times the chip has slept.
+    pub fn sleep_count(&self) -> usize {
+        self.sleep_count.get()
+    }
+}
+
+impl Chip for HostedChip {
+    type MPU = HostedMpu;
+    type SysTick = HostedSysTick;
+
+    fn service_pending_interrupts(&mut self) {
+        loop {
+            if let Some(task) = deferred_call::DeferredCall::next_pending() {
+                match task {
+                    Task::Flash => self.flash.map(|flash| flash.handle_deferred_call()),
+                };
+            } else if let Some(peripheral) = self
+                .peripherals
+                .iter()
+                .find(|peripheral| peripheral.interrupt_pending())
+            {
+                peripheral.handle_interrupt();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        deferred_call::has_tasks()
+            || self
+                .peripherals
+                .iter()
+                .any(|peripheral| peripheral.interrupt_pending())
+    }
+
+    fn mpu(&self) -> &HostedMpu {
+        &self.mpu
+    }
+
+    fn systick(&self) -> &HostedSysTick {
+        &self.systick
+    }
+
+    /// Nothing but the alarm interrupts the chip by itself, so sleeping lets
+    /// time pass until the alarm fires. Without an armed alarm the chip would
+    /// sleep forever, so it returns right away.
+    fn sleep(&self) {
+        self.sleep_count.set(self.sleep_count.get() + 1);
+        self.alarm.map(|alarm| alarm.skip_to_alarm());
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Interrupts are only handled when the kernel services them, so
+        // nothing can interrupt `f`.
+        f()
+    }
+}
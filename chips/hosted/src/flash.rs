@@ -0,0 +1,161 @@
+//! Simulated flash, kept in memory. Operations complete with a deferred call,
+//! as on the SAM4L.
+
+use deferred_call_tasks::Task;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::common::deferred_call::DeferredCall;
+use kernel::hil;
+use kernel::ReturnCode;
+use std::cell::{Cell, RefCell};
+use std::ops::{Index, IndexMut};
+
+/// Size of a flash page in bytes.
+pub const PAGE_SIZE: usize = 512;
+
+static DEFERRED_CALL: DeferredCall<Task> = unsafe { DeferredCall::new(Task::Flash) };
+
+pub struct HostedPage(pub [u8; PAGE_SIZE]);
+
+impl HostedPage {
+    pub fn new() -> HostedPage {
+        HostedPage([0; PAGE_SIZE])
+    }
+}
+
+impl Index<usize> for HostedPage {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for HostedPage {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for HostedPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operation {
+    Idle,
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct HostedFlash {
+    client: OptionalCell<&'static hil::flash::Client<HostedFlash>>,
+    /// The contents of the flash. Erased bytes read as `0xFF`.
+    memory: RefCell<Vec<u8>>,
+    operation: Cell<Operation>,
+    page_number: Cell<usize>,
+    buffer: TakeCell<'static, HostedPage>,
+}
+
+impl HostedFlash {
+    /// Flash of `num_pages` erased pages.
+    pub fn new(num_pages: usize) -> HostedFlash {
+        HostedFlash {
+            client: OptionalCell::empty(),
+            memory: RefCell::new(vec![0xFF; num_pages * PAGE_SIZE]),
+            operation: Cell::new(Operation::Idle),
+            page_number: Cell::new(0),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    /// Copy of the whole contents of the flash.
+    pub fn contents(&self) -> Vec<u8> {
+        self.memory.borrow().clone()
+    }
+
+    /// Write `data` at byte `address` directly, without an operation, for a
+    /// test to set up what the flash holds.
+    pub fn load(&self, address: usize, data: &[u8]) {
+        self.memory.borrow_mut()[address..address + data.len()].copy_from_slice(data);
+    }
+
+    fn start(&self, operation: Operation, page_number: usize) -> ReturnCode {
+        if self.operation.get() != Operation::Idle {
+            return ReturnCode::EBUSY;
+        }
+        if (page_number + 1) * PAGE_SIZE > self.memory.borrow().len() {
+            return ReturnCode::EINVAL;
+        }
+        self.operation.set(operation);
+        self.page_number.set(page_number);
+        DEFERRED_CALL.set();
+        ReturnCode::SUCCESS
+    }
+
+    /// Carry out the pending operation and tell the client it is done.
+    pub fn handle_deferred_call(&self) {
+        let operation = self.operation.replace(Operation::Idle);
+        let start = self.page_number.get() * PAGE_SIZE;
+        let page = start..start + PAGE_SIZE;
+        match operation {
+            Operation::Idle => {}
+            Operation::Read => {
+                self.buffer.take().map(|buffer| {
+                    buffer.0.copy_from_slice(&self.memory.borrow()[page]);
+                    self.client.map(move |client| {
+                        client.read_complete(buffer, hil::flash::Error::CommandComplete);
+                    });
+                });
+            }
+            Operation::Write => {
+                self.buffer.take().map(|buffer| {
+                    self.memory.borrow_mut()[page].copy_from_slice(&buffer.0);
+                    self.client.map(move |client| {
+                        client.write_complete(buffer, hil::flash::Error::CommandComplete);
+                    });
+                });
+            }
+            Operation::Erase => {
+                for byte in self.memory.borrow_mut()[page].iter_mut() {
+                    *byte = 0xFF;
+                }
+                self.client.map(|client| {
+                    client.erase_complete(hil::flash::Error::CommandComplete);
+                });
+            }
+        }
+    }
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for HostedFlash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for HostedFlash {
+    type Page = HostedPage;
+
+    fn read_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        let ret = self.start(Operation::Read, page_number);
+        if ret == ReturnCode::SUCCESS {
+            self.buffer.replace(buf);
+        }
+        ret
+    }
+
+    fn write_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        let ret = self.start(Operation::Write, page_number);
+        if ret == ReturnCode::SUCCESS {
+            self.buffer.replace(buf);
+        }
+        ret
+    }
+
+    fn erase_page(&self, page_number: usize) -> ReturnCode {
+        self.start(Operation::Erase, page_number)
+    }
+}
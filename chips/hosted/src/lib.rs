@@ -0,0 +1,114 @@
+//! A chip that runs on the host machine, for testing capsules with
+//! `cargo test`.
+//!
+//! The peripherals keep their state in memory. They finish operations by
+//! raising simulated interrupts or setting deferred calls, and the chip
+//! services those when the kernel asks it to, just as a real chip does. Time
+//! only passes when the test advances the alarm or the chip sleeps, so tests
+//! are deterministic.
+//!
+//! Processes cannot run on the host, so tests load apps built with `App` and
+//! make syscalls for them with the kernel's `procs::Simulator`, which
+//! `BoardBuilder` sets up:
+//!
+//! ```rust
+//! # extern crate capsules;
+//! # extern crate hosted;
+//! # extern crate kernel;
+//! use capsules::alarm::{AlarmDriver, DRIVER_NUM};
+//! use hosted::{App, BoardBuilder, HostedAlarm};
+//! use kernel::ReturnCode;
+//!
+//! # fn main() {
+//! let _lock = hosted::lock();
+//! let mut builder = BoardBuilder::new();
+//! let alarm = hosted::leak(HostedAlarm::new());
+//! let driver = hosted::leak(AlarmDriver::new(&*alarm, builder.grant()));
+//! alarm.set_client(driver);
+//! builder.chip.set_alarm(alarm);
+//! builder.platform.add_driver(DRIVER_NUM, driver);
+//!
+//! let (mut chip, simulator) = builder.simulate(&[App::new("app")]);
+//! assert_eq!(
+//!     simulator.command(0, DRIVER_NUM, 0, 0, 0),
+//!     ReturnCode::SuccessWithValue { value: 1 }
+//! );
+//! simulator.run_until_idle(&mut chip);
+//! # }
+//! ```
+//!
+//! Grants must be created before the apps are loaded, since each process
+//! makes room for the grants that exist when it is created.
+//!
+//! Tests of capsules that no process uses only need a `HostedChip`, and drive
+//! it with `run_until_idle` and `wait_for_alarm`.
+
+#![feature(const_fn, try_from)]
+
+#[macro_use(create_capability)]
+extern crate kernel;
+
+mod deferred_call_tasks;
+
+pub mod alarm;
+pub mod app;
+pub mod board;
+pub mod chip;
+pub mod flash;
+pub mod mpu;
+pub mod platform;
+pub mod systick;
+pub mod uart;
+
+pub use alarm::HostedAlarm;
+pub use app::{app_flash, app_memory, load_apps, App};
+pub use board::BoardBuilder;
+pub use chip::{HostedChip, Peripheral};
+pub use flash::{HostedFlash, HostedPage};
+pub use mpu::HostedMpu;
+pub use platform::HostedPlatform;
+pub use systick::HostedSysTick;
+pub use uart::HostedUart;
+
+use kernel::procs::Simulator;
+use kernel::{Platform, ReturnCode};
+use std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+
+static LOCK_INIT: Once = ONCE_INIT;
+static mut LOCK: Option<Mutex<()>> = None;
+
+/// Keep other tests from using the kernel until the returned guard is
+/// dropped.
+///
+/// The kernel keeps processes, grants and deferred calls in global state, so
+/// tests that run in parallel would otherwise see each other's processes.
+pub fn lock() -> MutexGuard<'static, ()> {
+    unsafe {
+        LOCK_INIT.call_once(|| LOCK = Some(Mutex::new(())));
+        let lock = LOCK.as_ref().unwrap();
+        // A test that panicked while holding the lock does not leave the
+        // kernel in a state the next test cares about, since every test sets
+        // up its own processes.
+        lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Move `value` to memory that is never freed, as `static_init!` does on a
+/// board.
+pub fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+}
+
+/// Grow the memory of the simulated app `app` by `data.len()` bytes, put
+/// `data` there, and return its address, for the app to share with a driver.
+pub fn put<P: Platform>(simulator: &Simulator<P>, app: usize, data: &[u8]) -> usize {
+    let address = match simulator.memop(app, 1, data.len()) {
+        ReturnCode::SuccessWithValue { value } => value,
+        rc => panic!("sbrk failed: {:?}", rc),
+    };
+    assert_eq!(
+        simulator.write_memory(app, address, data),
+        ReturnCode::SUCCESS
+    );
+    address
+}
@@ -0,0 +1,55 @@
+//! A memory protection unit that protects nothing.
+//!
+//! Processes never run on the host, so there is nothing to protect. The
+//! hosted MPU only accepts the regions a Cortex-M MPU could provide, so that
+//! the kernel asks for the same regions it would on a real chip, and
+//! remembers whether it is enabled.
+
+use kernel::mpu::{AccessPermission, ExecutePermission, Region, MPU};
+use std::cell::Cell;
+
+/// Number of regions the MPU has.
+const NUM_REGIONS: usize = 8;
+
+pub struct HostedMpu {
+    enabled: Cell<bool>,
+}
+
+impl HostedMpu {
+    pub fn new() -> HostedMpu {
+        HostedMpu {
+            enabled: Cell::new(false),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.get()
+    }
+}
+
+impl MPU for HostedMpu {
+    fn enable_mpu(&self) {
+        self.enabled.set(true);
+    }
+
+    fn disable_mpu(&self) {
+        self.enabled.set(false);
+    }
+
+    fn create_region(
+        region_num: usize,
+        start: usize,
+        len: usize,
+        _execute: ExecutePermission,
+        _access: AccessPermission,
+    ) -> Option<Region> {
+        // Regions are at least 32 bytes, a power of two in size, and start at
+        // a multiple of their size.
+        if region_num >= NUM_REGIONS || len < 32 || !len.is_power_of_two() || start % len != 0 {
+            return None;
+        }
+        Some(Region::empty(region_num))
+    }
+
+    fn set_mpu(&self, _region: Region) {}
+}
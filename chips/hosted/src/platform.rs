@@ -0,0 +1,34 @@
+//! A platform whose drivers are chosen by the test.
+
+use kernel::{Driver, Platform};
+
+pub struct HostedPlatform {
+    drivers: Vec<(usize, &'static Driver)>,
+}
+
+impl HostedPlatform {
+    pub fn new() -> HostedPlatform {
+        HostedPlatform {
+            drivers: Vec::new(),
+        }
+    }
+
+    /// Give processes `driver` as driver number `driver_num`.
+    pub fn add_driver(&mut self, driver_num: usize, driver: &'static Driver) {
+        self.drivers.push((driver_num, driver));
+    }
+}
+
+impl Platform for HostedPlatform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&Driver>) -> R,
+    {
+        let driver = self
+            .drivers
+            .iter()
+            .find(|&&(num, _)| num == driver_num)
+            .map(|&(_, driver)| driver);
+        f(driver)
+    }
+}
@@ -0,0 +1,72 @@
+//! A simulated system tick timer, which counts down only when the test moves
+//! it forward.
+
+use kernel::SysTick;
+use std::cell::Cell;
+
+pub struct HostedSysTick {
+    /// Microseconds the timer starts counting down from.
+    period: Cell<u32>,
+    /// Microseconds left before the timer next counts down to zero.
+    remaining: Cell<u32>,
+    enabled: Cell<bool>,
+    overflowed: Cell<bool>,
+}
+
+impl HostedSysTick {
+    pub fn new() -> HostedSysTick {
+        HostedSysTick {
+            period: Cell::new(0),
+            remaining: Cell::new(0),
+            enabled: Cell::new(false),
+            overflowed: Cell::new(false),
+        }
+    }
+
+    /// Let `us` microseconds pass, if the timer is enabled. When it counts
+    /// down to zero it starts over from its period.
+    pub fn advance(&self, us: u32) {
+        if !self.enabled.get() || self.period.get() == 0 {
+            return;
+        }
+        if us >= self.remaining.get() {
+            let past_zero = us - self.remaining.get();
+            self.remaining
+                .set(self.period.get() - past_zero % self.period.get());
+            self.overflowed.set(true);
+        } else {
+            self.remaining.set(self.remaining.get() - us);
+        }
+    }
+}
+
+impl SysTick for HostedSysTick {
+    fn set_timer(&self, us: u32) {
+        self.period.set(us);
+        self.remaining.set(us);
+        self.overflowed.set(false);
+    }
+
+    fn greater_than(&self, us: u32) -> bool {
+        self.remaining.get() > us
+    }
+
+    fn get_value(&self) -> u32 {
+        self.remaining.get()
+    }
+
+    fn overflowed(&self) -> bool {
+        self.overflowed.get()
+    }
+
+    fn reset(&self) {
+        self.period.set(0);
+        self.remaining.set(0);
+        self.enabled.set(false);
+        self.overflowed.set(false);
+    }
+
+    fn enable(&self, _with_interrupt: bool) {
+        self.enabled.set(true);
+    }
+}
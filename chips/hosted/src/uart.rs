@@ -0,0 +1,142 @@
+//! A simulated UART. What it transmits is kept for the test to check, and
+//! what it receives is given to it by the test.
+
+use chip::Peripheral;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::uart::{self, UARTParams};
+use std::cell::{Cell, RefCell};
+use std::cmp;
+
+pub struct HostedUart {
+    client: OptionalCell<&'static uart::Client>,
+    params: Cell<Option<UARTParams>>,
+    /// Everything transmitted so far.
+    output: RefCell<Vec<u8>>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_interrupt: Cell<bool>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    /// How many bytes of `rx_len` have been received.
+    rx_index: Cell<usize>,
+    rx_interrupt: Cell<bool>,
+}
+
+impl HostedUart {
+    pub fn new() -> HostedUart {
+        HostedUart {
+            client: OptionalCell::empty(),
+            params: Cell::new(None),
+            output: RefCell::new(Vec::new()),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_interrupt: Cell::new(false),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            rx_interrupt: Cell::new(false),
+        }
+    }
+
+    /// The parameters the UART was last initialized with.
+    pub fn params(&self) -> Option<UARTParams> {
+        self.params.get()
+    }
+
+    /// Take everything transmitted since the last call.
+    pub fn take_output(&self) -> Vec<u8> {
+        self.output.replace(Vec::new())
+    }
+
+    /// Whether a receive is in progress.
+    pub fn is_receiving(&self) -> bool {
+        self.rx_buffer.is_some() && !self.rx_interrupt.get()
+    }
+
+    /// Receive `bytes`, as if they arrived on the line. Bytes that arrive
+    /// while no receive is in progress, or after the receive has all it asked
+    /// for, are lost, as on real hardware. Returns how many were received.
+    pub fn receive_bytes(&self, bytes: &[u8]) -> usize {
+        if !self.is_receiving() {
+            return 0;
+        }
+        let index = self.rx_index.get();
+        let count = cmp::min(bytes.len(), self.rx_len.get() - index);
+        self.rx_buffer.map(|buffer| {
+            buffer[index..index + count].copy_from_slice(&bytes[..count]);
+        });
+        self.rx_index.set(index + count);
+        if self.rx_index.get() == self.rx_len.get() {
+            self.rx_interrupt.set(true);
+        }
+        count
+    }
+}
+
+impl uart::UART for HostedUart {
+    fn set_client(&self, client: &'static uart::Client) {
+        self.client.set(client);
+    }
+
+    fn init(&self, params: UARTParams) {
+        self.params.set(Some(params));
+    }
+
+    /// Transmitting takes no time: the data is sent right away and the
+    /// interrupt raised.
+    fn transmit(&self, tx_data: &'static mut [u8], tx_len: usize) {
+        assert!(
+            self.tx_buffer.is_none(),
+            "transmit while a transmission is in progress"
+        );
+        let len = cmp::min(tx_len, tx_data.len());
+        self.output.borrow_mut().extend_from_slice(&tx_data[..len]);
+        self.tx_len.set(len);
+        self.tx_buffer.replace(tx_data);
+        self.tx_interrupt.set(true);
+    }
+
+    fn receive(&self, rx_buffer: &'static mut [u8], rx_len: usize) {
+        assert!(
+            self.rx_buffer.is_none(),
+            "receive while a reception is in progress"
+        );
+        self.rx_len.set(cmp::min(rx_len, rx_buffer.len()));
+        self.rx_index.set(0);
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_interrupt.set(self.rx_len.get() == 0);
+    }
+
+    /// Ends the receive with the bytes received so far.
+    fn abort_receive(&self) {
+        if self.rx_buffer.is_some() {
+            self.rx_interrupt.set(true);
+        }
+    }
+}
+
+impl Peripheral for HostedUart {
+    fn interrupt_pending(&self) -> bool {
+        self.tx_interrupt.get() || self.rx_interrupt.get()
+    }
+
+    fn handle_interrupt(&self) {
+        if self.tx_interrupt.get() {
+            self.tx_interrupt.set(false);
+            self.tx_buffer.take().map(|buffer| {
+                self.client.map(move |client| {
+                    client.transmit_complete(buffer, uart::Error::CommandComplete)
+                });
+            });
+        }
+        if self.rx_interrupt.get() {
+            self.rx_interrupt.set(false);
+            self.rx_buffer.take().map(|buffer| {
+                let len = self.rx_index.get();
+                self.client.map(move |client| {
+                    client.receive_complete(buffer, len, uart::Error::CommandComplete)
+                });
+            });
+        }
+    }
+}
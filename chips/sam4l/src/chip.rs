@@ -12,6 +12,7 @@ use flashcalw;
 use gpio;
 use i2c;
 use kernel::common::deferred_call;
+use kernel::power::{self, SleepState};
 use kernel::Chip;
 use nvic;
 use pm;
@@ -43,6 +44,11 @@ impl Sam4l {
         dma::DMA_CHANNELS[6].initialize(&mut usart::USART3, dma::DMAWidth::Width8Bit);
         dma::DMA_CHANNELS[7].initialize(&mut usart::USART3, dma::DMAWidth::Width8Bit);
 
+        power::register(usart::USART0.sleep_vote());
+        power::register(usart::USART1.sleep_vote());
+        power::register(usart::USART2.sleep_vote());
+        power::register(usart::USART3.sleep_vote());
+
         spi::SPI.set_dma(&mut dma::DMA_CHANNELS[8], &mut dma::DMA_CHANNELS[9]);
         dma::DMA_CHANNELS[8].initialize(&mut spi::SPI, dma::DMAWidth::Width8Bit);
         dma::DMA_CHANNELS[9].initialize(&mut spi::SPI, dma::DMAWidth::Width8Bit);
@@ -162,7 +168,7 @@ impl Chip for Sam4l {
     }
 
     fn sleep(&self) {
-        if pm::deep_sleep_ready() {
+        if power::choose_sleep_state() == SleepState::DeepSleep && pm::deep_sleep_ready() {
             unsafe {
                 cortexm4::scb::set_sleepdeep();
             }
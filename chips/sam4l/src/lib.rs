@@ -29,6 +29,7 @@ pub mod gpio;
 pub mod i2c;
 pub mod nvic;
 pub mod pm;
+pub mod power_model;
 pub mod scif;
 pub mod spi;
 pub mod trng;
@@ -1055,7 +1055,9 @@ macro_rules! get_clock {
 ///
 ///   * HSB may only have clocks for the flash (and PicoCache), APBx bridges, and PDCA on.
 ///
-///   * PBA may only have I2C Slaves on as they can self-wake.
+///   * PBA may only have I2C Slaves on as they can self-wake, and the USARTs,
+///     which vote through `kernel::power` for the sleep state they tolerate
+///     instead.
 ///
 ///   * PBB may only have clocks for the flash, HRAMC1 (also flash related), and PDCA on.
 ///
@@ -1086,7 +1088,11 @@ pub fn deep_sleep_ready() -> bool {
     // PBA clocks that can be enabled and the core is permitted to enter deep sleep.
     let deep_sleep_pbamask: FieldValue<u32, ClockMaskPba::Register> =
         /* added by us */ ClockMaskPba::TWIS0::SET +
-        /* added by us */ ClockMaskPba::TWIS1::SET;
+        /* added by us */ ClockMaskPba::TWIS1::SET +
+        /* added by us */ ClockMaskPba::USART0::SET +
+        /* added by us */ ClockMaskPba::USART1::SET +
+        /* added by us */ ClockMaskPba::USART2::SET +
+        /* added by us */ ClockMaskPba::USART3::SET;
 
     // PBB clocks that can be enabled and the core is permitted to enter deep sleep.
     let deep_sleep_pbbmask: FieldValue<u32, ClockMaskPbb::Register> =
@@ -0,0 +1,95 @@
+//! Estimate of the power the SAM4L draws, from the clocks that are running.
+//!
+//! The current of the core and of each peripheral grows with the frequency of
+//! its clock, so the model adds, per MHz of the system clock, the current of
+//! the core and that of every peripheral whose clock mask is set. While a
+//! sleep vote or a busy peripheral keeps the chip out of deep sleep, the
+//! current the chip will draw sleeping instead of deep sleeping is added too,
+//! so that the process that started the peripheral pays for it.
+//!
+//! The figures are rough typical values for the core running from flash, in
+//! line with the SAM4L datasheet. They are good enough to compare processes
+//! with each other; a board that needs the energy in joules should measure
+//! its own.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let power_model = static_init!(
+//!     sam4l::power_model::ClockPowerModel,
+//!     sam4l::power_model::ClockPowerModel::new(3300)
+//! );
+//! board_kernel.set_power_model(power_model, &kernel_configuration_capability);
+//! ```
+
+use kernel::power::{self, PowerModel, SleepState};
+use kernel::ClockInterface;
+use pm::{self, Clock, HSBClock, PBAClock, PBBClock};
+
+/// Current of the core running, in µA per MHz.
+const CORE_UA_PER_MHZ: u32 = 90;
+
+/// Current of the chip in sleep mode, with the CPU stopped and the clocks
+/// running, in µA per MHz.
+const SLEEP_UA_PER_MHZ: u32 = 30;
+
+/// Current of the peripherals that draw a noticeable amount while their clock
+/// runs, in µA per MHz.
+const PERIPHERAL_UA_PER_MHZ: [(Clock, u32); 21] = [
+    (Clock::HSB(HSBClock::PDCA), 2),
+    (Clock::HSB(HSBClock::USBC), 8),
+    (Clock::HSB(HSBClock::CRCCU), 1),
+    (Clock::HSB(HSBClock::AESA), 6),
+    (Clock::PBA(PBAClock::IISC), 2),
+    (Clock::PBA(PBAClock::SPI), 3),
+    (Clock::PBA(PBAClock::TC0), 3),
+    (Clock::PBA(PBAClock::TC1), 3),
+    (Clock::PBA(PBAClock::TWIM0), 2),
+    (Clock::PBA(PBAClock::TWIM1), 2),
+    (Clock::PBA(PBAClock::TWIM2), 2),
+    (Clock::PBA(PBAClock::TWIM3), 2),
+    (Clock::PBA(PBAClock::USART0), 3),
+    (Clock::PBA(PBAClock::USART1), 3),
+    (Clock::PBA(PBAClock::USART2), 3),
+    (Clock::PBA(PBAClock::USART3), 3),
+    (Clock::PBA(PBAClock::ADCIFE), 4),
+    (Clock::PBA(PBAClock::DACC), 2),
+    (Clock::PBA(PBAClock::TRNG), 1),
+    (Clock::PBB(PBBClock::CRCCU), 1),
+    (Clock::PBB(PBBClock::USBC), 2),
+];
+
+pub struct ClockPowerModel {
+    supply_millivolts: u32,
+}
+
+impl ClockPowerModel {
+    /// A model for a chip powered with `supply_millivolts`. The power it
+    /// reports is in microwatts.
+    pub const fn new(supply_millivolts: u32) -> ClockPowerModel {
+        ClockPowerModel {
+            supply_millivolts: supply_millivolts,
+        }
+    }
+
+    /// The current the chip draws right now, in µA.
+    pub fn active_current(&self) -> u32 {
+        let mut ua_per_mhz = CORE_UA_PER_MHZ;
+        for &(clock, peripheral_ua_per_mhz) in PERIPHERAL_UA_PER_MHZ.iter() {
+            if clock.is_enabled() {
+                ua_per_mhz += peripheral_ua_per_mhz;
+            }
+        }
+        if power::deepest_sleep_state() != SleepState::DeepSleep || !pm::deep_sleep_ready() {
+            ua_per_mhz += SLEEP_UA_PER_MHZ;
+        }
+        ua_per_mhz * (pm::get_system_frequency() / 1_000_000)
+    }
+}
+
+impl PowerModel for ClockPowerModel {
+    fn active_power(&self) -> u32 {
+        self.active_current() * self.supply_millivolts / 1000
+    }
+}
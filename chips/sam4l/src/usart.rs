@@ -9,6 +9,7 @@ use kernel::common::cells::OptionalCell;
 use kernel::common::regs::{ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil;
+use kernel::power::{SleepState, SleepVote};
 use kernel::ReturnCode;
 
 use dma;
@@ -281,6 +282,7 @@ const USART_BASE_ADDRS: [StaticRef<UsartRegisters>; 4] = unsafe {
 };
 
 pub struct USARTRegManager<'a> {
+    usart: &'a USART,
     registers: &'a UsartRegisters,
     clock: pm::Clock,
     rx_dma: Option<&'static dma::DMAChannel>,
@@ -296,6 +298,7 @@ impl USARTRegManager<'a> {
         }
         let regs: &UsartRegisters = &*usart.registers;
         USARTRegManager {
+            usart: usart,
             registers: regs,
             clock: usart.clock,
             rx_dma: usart.rx_dma.get(),
@@ -338,6 +341,8 @@ impl Drop for USARTRegManager<'a> {
         if !(rx_active || tx_active || ints_active || is_panic) {
             pm::disable_clock(self.clock);
         }
+
+        self.usart.update_sleep_vote();
     }
 }
 
@@ -388,30 +393,37 @@ pub struct USART {
     client: OptionalCell<UsartClient<'static>>,
 
     spi_chip_select: OptionalCell<&'static hil::gpio::Pin>,
+
+    sleep_vote: SleepVote,
+    receive_sleep_state: Cell<SleepState>,
 }
 
 // USART hardware peripherals on SAM4L
 pub static mut USART0: USART = USART::new(
     USART_BASE_ADDRS[0],
     pm::PBAClock::USART0,
+    "usart0",
     dma::DMAPeripheral::USART0_RX,
     dma::DMAPeripheral::USART0_TX,
 );
 pub static mut USART1: USART = USART::new(
     USART_BASE_ADDRS[1],
     pm::PBAClock::USART1,
+    "usart1",
     dma::DMAPeripheral::USART1_RX,
     dma::DMAPeripheral::USART1_TX,
 );
 pub static mut USART2: USART = USART::new(
     USART_BASE_ADDRS[2],
     pm::PBAClock::USART2,
+    "usart2",
     dma::DMAPeripheral::USART2_RX,
     dma::DMAPeripheral::USART2_TX,
 );
 pub static mut USART3: USART = USART::new(
     USART_BASE_ADDRS[3],
     pm::PBAClock::USART3,
+    "usart3",
     dma::DMAPeripheral::USART3_RX,
     dma::DMAPeripheral::USART3_TX,
 );
@@ -420,6 +432,7 @@ impl USART {
     const fn new(
         base_addr: StaticRef<UsartRegisters>,
         clock: pm::PBAClock,
+        name: &'static str,
         rx_dma_peripheral: dma::DMAPeripheral,
         tx_dma_peripheral: dma::DMAPeripheral,
     ) -> USART {
@@ -445,9 +458,44 @@ impl USART {
 
             // This is only used if the USART is in SPI mode.
             spi_chip_select: OptionalCell::empty(),
+
+            sleep_vote: SleepVote::new(name),
+            receive_sleep_state: Cell::new(SleepState::Sleep),
         }
     }
 
+    /// The vote for how deeply the chip may sleep, which `chip.rs` registers.
+    pub fn sleep_vote(&self) -> &SleepVote {
+        &self.sleep_vote
+    }
+
+    /// How deeply the chip may sleep while this USART waits to receive.
+    ///
+    /// By default the chip only sleeps lightly, so that no byte is lost. In
+    /// deep sleep the USART has no clock: a console that mostly waits for
+    /// input can allow `SleepState::DeepSleep` to save energy, if losing the
+    /// bytes that arrive while the chip sleeps deeply is acceptable. Only opt
+    /// in on battery-powered boards, or where something else wakes the chip
+    /// before the bytes arrive.
+    pub fn set_receive_sleep_state(&self, state: SleepState) {
+        self.receive_sleep_state.set(state);
+        self.update_sleep_vote();
+    }
+
+    /// Vote for the deepest sleep state the current transfers tolerate. A
+    /// transmission must finish, so it always keeps the chip from sleeping
+    /// deeply.
+    fn update_sleep_vote(&self) {
+        let state = if self.usart_tx_state.get() != USARTStateTX::Idle {
+            SleepState::Sleep
+        } else if self.usart_rx_state.get() != USARTStateRX::Idle {
+            self.receive_sleep_state.get()
+        } else {
+            SleepState::DeepSleep
+        };
+        self.sleep_vote.set(state);
+    }
+
     pub fn set_dma(&self, rx_dma: &'static dma::DMAChannel, tx_dma: &'static dma::DMAChannel) {
         self.rx_dma.set(Some(rx_dma));
         self.tx_dma.set(Some(tx_dma));
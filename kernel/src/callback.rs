@@ -4,9 +4,15 @@ use core::ptr::NonNull;
 use process;
 
 /// Userspace app identifier.
+///
+/// Besides the index of the process, an `AppId` holds the generation of the
+/// process it was given to. After the process is restarted or unloaded the
+/// `AppId` is stale: callbacks scheduled with it are dropped, grants cannot be
+/// entered with it and `AppSlice`s shared under it are empty.
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub struct AppId {
     idx: usize,
+    generation: usize,
 }
 
 /// The kernel can masquerade as an app. IDs >= this value are the kernel.
@@ -14,12 +20,18 @@ pub struct AppId {
 const KERNEL_APPID_BOUNDARY: usize = 100;
 
 impl AppId {
-    pub(crate) fn new(idx: usize) -> AppId {
-        AppId { idx: idx }
+    pub(crate) fn new(idx: usize, generation: usize) -> AppId {
+        AppId {
+            idx: idx,
+            generation: generation,
+        }
     }
 
     pub(crate) const fn kernel_new(idx: usize) -> AppId {
-        AppId { idx: idx }
+        AppId {
+            idx: idx,
+            generation: 0,
+        }
     }
 
     pub const fn is_kernel(self) -> bool {
@@ -34,9 +46,29 @@ impl AppId {
         self.idx
     }
 
+    pub(crate) fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Whether the process this `AppId` was given to still runs. The kernel's
+    /// own `AppId`s are always valid.
+    pub fn is_valid(&self) -> bool {
+        self.is_kernel() || process::is_current(*self)
+    }
+
     pub fn get_editable_flash_range(&self) -> (usize, usize) {
         process::get_editable_flash_range(self.idx)
     }
+
+    /// Whether the TBF header of the app explicitly lists `driver_num` in a
+    /// Permissions TLV.
+    ///
+    /// Apps without a Permissions TLV may use every driver, so drivers that
+    /// only privileged apps should use check this instead, and return
+    /// `ENOPERM` to every other app.
+    pub fn has_explicit_driver_permission(&self, driver_num: usize) -> bool {
+        process::has_explicit_driver_permission(self.idx, driver_num)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
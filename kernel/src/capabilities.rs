@@ -0,0 +1,49 @@
+//! Capabilities for calling privileged kernel functions.
+//!
+//! Some kernel functions, such as loading processes or creating grants, must
+//! only be called by the board, since they would let a capsule break the
+//! isolation between processes or take memory from them. These functions
+//! require a reference to a value whose type implements one of the traits
+//! below.
+//!
+//! The traits are `unsafe` to implement, and capsules are compiled with
+//! `#![forbid(unsafe_code)]`, so only trusted code such as the board's
+//! `main.rs` can create a capability. It does so with `create_capability!`:
+//!
+//! ```rust
+//! let process_management_capability =
+//!     create_capability!(kernel::capabilities::ProcessManagementCapability);
+//! kernel::procs::load_processes(
+//!     board_kernel,
+//!     ..,
+//!     &process_management_capability,
+//! );
+//! ```
+//!
+//! A board can then hand the result of a privileged function, such as a
+//! `ProcessManager`, to the capsules it trusts with it.
+
+/// Allows creating, loading and controlling processes.
+pub unsafe trait ProcessManagementCapability {}
+
+/// Allows running the kernel's main loop.
+pub unsafe trait MainLoopCapability {}
+
+/// Allows creating grants, which take memory from processes.
+pub unsafe trait MemoryAllocationCapability {}
+
+/// Allows setting up kernel-wide services, such as the debug console.
+pub unsafe trait KernelConfigurationCapability {}
+
+/// Create a value that has the capability `$T`.
+///
+/// This expands to an `unsafe impl`, so it cannot be used in crates that
+/// forbid unsafe code.
+#[macro_export]
+macro_rules! create_capability {
+    ($T:ty) => {{
+        struct Capability;
+        unsafe impl $T for Capability {}
+        Capability
+    }};
+}
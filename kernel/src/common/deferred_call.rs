@@ -3,6 +3,9 @@
 //! This is a tool to allow chip peripherals to schedule "interrupts"
 //! in the chip scheduler if the hardware doesn't support interrupts where
 //! they are needed.
+//!
+//! The tasks are a chip-specific enum dispatched by the chip, so capsules
+//! cannot use this; they use `dynamic_deferred_call` instead.
 
 use core::cell::UnsafeCell;
 use core::convert::Into;
@@ -0,0 +1,130 @@
+//! Deferred calls that capsules can use.
+//!
+//! `deferred_call::DeferredCall` is keyed by a chip-specific enum and
+//! dispatched by the chip, so only chip peripherals can use it. A
+//! `DynamicDeferredCall` instead lets any component, including capsules,
+//! register itself at runtime and ask to be called back from the kernel's
+//! main loop, for example to issue a callback that must not happen from
+//! within the call that caused it.
+//!
+//! The board creates one `DynamicDeferredCall` with room for every client,
+//! gives it to the kernel, and registers the clients:
+//!
+//! ```rust
+//! static mut DYNAMIC_DEFERRED_CALL_CLIENTS: [DynamicDeferredCallClientState; 2] = [
+//!     DynamicDeferredCallClientState::new(),
+//!     DynamicDeferredCallClientState::new(),
+//! ];
+//!
+//! let dynamic_deferred_call = static_init!(
+//!     DynamicDeferredCall,
+//!     DynamicDeferredCall::new(&DYNAMIC_DEFERRED_CALL_CLIENTS)
+//! );
+//! board_kernel.set_dynamic_deferred_call(dynamic_deferred_call, &kernel_configuration_capability);
+//!
+//! let client = static_init!(Client, Client::new(dynamic_deferred_call));
+//! client.initialize_callback_handle(
+//!     dynamic_deferred_call.register(client).expect("no deferred call slot left"),
+//! );
+//! ```
+//!
+//! The client then calls `DynamicDeferredCall::set` with its handle, and the
+//! kernel calls `DynamicDeferredCallClient::call` before it runs a process or
+//! lets the chip sleep again.
+
+use core::cell::Cell;
+
+use common::cells::OptionalCell;
+
+/// Something that can be called back through a `DynamicDeferredCall`.
+pub trait DynamicDeferredCallClient {
+    /// The deferred call with `handle` was set and is now being serviced.
+    fn call(&self, handle: DeferredCallHandle);
+}
+
+/// Identifies a client registered with a `DynamicDeferredCall`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeferredCallHandle(usize);
+
+/// Storage for one client of a `DynamicDeferredCall`.
+pub struct DynamicDeferredCallClientState {
+    scheduled: Cell<bool>,
+    client: OptionalCell<&'static DynamicDeferredCallClient>,
+}
+
+impl DynamicDeferredCallClientState {
+    pub const fn new() -> DynamicDeferredCallClientState {
+        DynamicDeferredCallClientState {
+            scheduled: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+/// Deferred calls for a fixed number of dynamically registered clients.
+pub struct DynamicDeferredCall {
+    client_states: &'static [DynamicDeferredCallClientState],
+    /// How many clients are registered.
+    registered: Cell<usize>,
+    /// Whether any client is scheduled.
+    call_pending: Cell<bool>,
+}
+
+impl DynamicDeferredCall {
+    /// Create a deferred call with room for as many clients as
+    /// `client_states` holds.
+    pub fn new(client_states: &'static [DynamicDeferredCallClientState]) -> DynamicDeferredCall {
+        DynamicDeferredCall {
+            client_states: client_states,
+            registered: Cell::new(0),
+            call_pending: Cell::new(false),
+        }
+    }
+
+    /// Register `client`. Returns `None` if there is no room left for it.
+    pub fn register(
+        &self,
+        client: &'static DynamicDeferredCallClient,
+    ) -> Option<DeferredCallHandle> {
+        let index = self.registered.get();
+        self.client_states.get(index).map(|state| {
+            state.client.set(client);
+            self.registered.set(index + 1);
+            DeferredCallHandle(index)
+        })
+    }
+
+    /// Ask for the client with `handle` to be called from the main loop.
+    /// Returns whether it was scheduled already, or `None` if the handle is
+    /// not from this deferred call.
+    pub fn set(&self, handle: DeferredCallHandle) -> Option<bool> {
+        self.client_states.get(handle.0).map(|state| {
+            let scheduled = state.scheduled.get();
+            state.scheduled.set(true);
+            self.call_pending.set(true);
+            scheduled
+        })
+    }
+
+    /// Whether any client is waiting to be called.
+    pub fn has_pending(&self) -> bool {
+        self.call_pending.get()
+    }
+
+    /// Call every client that is scheduled. Clients scheduled again during
+    /// their call are called the next time.
+    pub(crate) fn call_pending(&self) {
+        if !self.call_pending.get() {
+            return;
+        }
+        self.call_pending.set(false);
+        for (index, state) in self.client_states.iter().enumerate() {
+            if state.scheduled.get() {
+                state.scheduled.set(false);
+                state.client.map(|client| {
+                    client.call(DeferredCallHandle(index));
+                });
+            }
+        }
+    }
+}
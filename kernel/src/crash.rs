@@ -0,0 +1,259 @@
+//! Keeping a record of a kernel panic across the reboot that follows it.
+//!
+//! On a device nobody watches, what `debug::panic` prints to the UART is
+//! lost. So before printing, the panic routine also saves a crash record in
+//! the `.noinit` section of RAM, which the startup code neither loads nor
+//! zeroes. The record holds the panic message, the name and registers of the
+//! process that faulted, if one did, and the tail of the debug buffer.
+//!
+//! Once the chip reboots, for example because the kernel's watchdog stopped
+//! being tickled while the panic routine blinked the LEDs, the record is still
+//! there. The kernel prints a summary of it with `debug!` when the main loop
+//! starts, and `crash_record` returns it, so that a driver can let an app
+//! upload it. `clear_crash_record` forgets it once it is no longer needed.
+//!
+//! The record only survives resets that keep power to the RAM.
+
+use core::cmp;
+use core::fmt::{self, Write};
+use core::mem;
+use core::panic::PanicInfo;
+use core::ptr;
+use core::str;
+
+use debug;
+use process::Process;
+
+/// Marks a valid crash record, along with the checksum.
+const MAGIC: usize = 0x4352_5348;
+
+const MESSAGE_LEN: usize = 128;
+const PROCESS_NAME_LEN: usize = 32;
+const DEBUG_TAIL_LEN: usize = 256;
+
+/// Names of the process registers in a crash record, in order.
+pub const REGISTER_NAMES: [&str; 9] = ["R0", "R1", "R2", "R3", "R12", "SP", "LR", "PC", "XPSR"];
+
+/// What the kernel knew when it panicked.
+///
+/// Every field is a `usize` or an array of bytes, so that any bits left in
+/// RAM form a valid value; the magic number and checksum tell whether they
+/// form a record.
+#[repr(C)]
+pub struct CrashRecord {
+    magic: usize,
+    message_len: usize,
+    message: [u8; MESSAGE_LEN],
+    /// `0` if no process had faulted.
+    has_process: usize,
+    process_name_len: usize,
+    process_name: [u8; PROCESS_NAME_LEN],
+    registers: [usize; 9],
+    debug_tail_len: usize,
+    debug_tail: [u8; DEBUG_TAIL_LEN],
+    /// Must stay last, since it covers every field before it but `magic`.
+    checksum: usize,
+}
+
+/// Whether `record_fault` saved the process whose fault is making the kernel
+/// panic during this boot.
+static mut PROCESS_RECORDED: bool = false;
+
+#[link_section = ".noinit"]
+static mut CRASH_RECORD: CrashRecord = CrashRecord {
+    magic: 0,
+    message_len: 0,
+    message: [0; MESSAGE_LEN],
+    has_process: 0,
+    process_name_len: 0,
+    process_name: [0; PROCESS_NAME_LEN],
+    registers: [0; 9],
+    debug_tail_len: 0,
+    debug_tail: [0; DEBUG_TAIL_LEN],
+    checksum: 0,
+};
+
+/// The crash record left by the last kernel panic, if there is one.
+pub fn crash_record() -> Option<&'static CrashRecord> {
+    unsafe {
+        let record = &CRASH_RECORD;
+        if ptr::read_volatile(&record.magic) == MAGIC
+            && ptr::read_volatile(&record.checksum) == record.compute_checksum()
+        {
+            Some(record)
+        } else {
+            None
+        }
+    }
+}
+
+/// Forget the crash record, for example once it has been uploaded.
+pub fn clear_crash_record() {
+    unsafe {
+        ptr::write_volatile(&mut CRASH_RECORD.magic, 0);
+    }
+}
+
+/// Save the name and registers of `process` in the crash record, since its
+/// fault is about to make the kernel panic.
+pub(crate) unsafe fn record_fault(process: &Process) {
+    let record = &mut CRASH_RECORD;
+    // The panic replaces whatever record was there.
+    record.magic = 0;
+
+    let process_name_len = {
+        let mut writer = BufferWriter::new(&mut record.process_name);
+        let _ = writer.write_str(process.package_name);
+        writer.len
+    };
+    record.has_process = 1;
+    record.process_name_len = process_name_len;
+    record.registers = [
+        process.r0(),
+        process.r1(),
+        process.r2(),
+        process.r3(),
+        process.r12(),
+        process.sp(),
+        process.lr(),
+        process.pc(),
+        process.xpsr(),
+    ];
+    PROCESS_RECORDED = true;
+}
+
+/// Save what is known about a kernel panic in the crash record.
+pub(crate) unsafe fn record_panic(panic_info: &PanicInfo) {
+    let record = &mut CRASH_RECORD;
+    record.magic = 0;
+
+    let message_len = {
+        let mut writer = BufferWriter::new(&mut record.message);
+        if let Some(location) = panic_info.location() {
+            let _ = writer.write_fmt(format_args!("{}:{}: ", location.file(), location.line()));
+        }
+        if let Some(message) = panic_info.message() {
+            let _ = writer.write_fmt(*message);
+        }
+        writer.len
+    };
+    record.message_len = message_len;
+
+    // Only a panic caused by a process fault has a process to blame.
+    if !PROCESS_RECORDED {
+        record.has_process = 0;
+        record.process_name_len = 0;
+        record.registers = [0; 9];
+    }
+
+    record.debug_tail_len = debug::copy_debug_tail(&mut record.debug_tail);
+
+    record.checksum = record.compute_checksum();
+    ptr::write_volatile(&mut record.magic, MAGIC);
+}
+
+/// Print a summary of the crash record with `debug!`, if there is one.
+pub(crate) fn report() {
+    if !debug::has_console_driver() {
+        return;
+    }
+    crash_record().map(|record| {
+        debug!("Kernel panicked before this boot: {}", record.message());
+        record.process_name().map(|process_name| {
+            debug!(" while process {} had faulted", process_name);
+        });
+    });
+}
+
+impl CrashRecord {
+    /// Where the kernel panicked, and the panic message, possibly truncated.
+    pub fn message(&self) -> &str {
+        utf8_prefix(&self.message[..cmp::min(self.message_len, MESSAGE_LEN)])
+    }
+
+    /// The name of the process that had faulted when the kernel panicked.
+    pub fn process_name(&self) -> Option<&str> {
+        if self.has_process == 0 {
+            return None;
+        }
+        let len = cmp::min(self.process_name_len, PROCESS_NAME_LEN);
+        Some(utf8_prefix(&self.process_name[..len]))
+    }
+
+    /// The registers of the process that had faulted, in the order of
+    /// `REGISTER_NAMES`.
+    pub fn process_registers(&self) -> Option<[usize; 9]> {
+        if self.has_process == 0 {
+            None
+        } else {
+            Some(self.registers)
+        }
+    }
+
+    /// The last bytes written to the debug buffer before the panic.
+    pub fn debug_tail(&self) -> &[u8] {
+        &self.debug_tail[..cmp::min(self.debug_tail_len, DEBUG_TAIL_LEN)]
+    }
+
+    /// Print the whole record as text to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> fmt::Result {
+        writer.write_fmt(format_args!("Kernel panic: {}\r\n", self.message()))?;
+        if let Some(process_name) = self.process_name() {
+            writer.write_fmt(format_args!("Faulted process: {}\r\n", process_name))?;
+        }
+        if let Some(registers) = self.process_registers() {
+            for (name, value) in REGISTER_NAMES.iter().zip(registers.iter()) {
+                writer.write_fmt(format_args!(" {}: {:#010X}\r\n", name, value))?;
+            }
+        }
+        writer.write_str("Debug buffer:\r\n")?;
+        writer.write_str(utf8_prefix(self.debug_tail()))
+    }
+
+    /// Checksum of the words between the magic number and the checksum, so
+    /// that a record the previous boot did not finish writing is not mistaken
+    /// for a valid one.
+    fn compute_checksum(&self) -> usize {
+        let words = (mem::size_of::<CrashRecord>() / mem::size_of::<usize>()) - 1;
+        let base = self as *const CrashRecord as *const usize;
+        (1..words).fold(MAGIC, |sum, i| unsafe {
+            sum.rotate_left(1) ^ ptr::read_volatile(base.offset(i as isize))
+        })
+    }
+}
+
+/// The longest prefix of `bytes` that is valid UTF-8.
+fn utf8_prefix(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => unsafe { str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+    }
+}
+
+/// Formats into a fixed buffer, dropping what does not fit.
+struct BufferWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl BufferWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> BufferWriter<'a> {
+        BufferWriter {
+            buffer: buffer,
+            len: 0,
+        }
+    }
+}
+
+impl Write for BufferWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == self.buffer.len() {
+                break;
+            }
+            self.buffer[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
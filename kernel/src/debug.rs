@@ -16,7 +16,7 @@
 //! let kc = static_init!(
 //!     capsules::console::App,
 //!     capsules::console::App::default());
-//! kernel::debug::assign_console_driver(Some(hail.console), kc);
+//! kernel::debug::assign_console_driver(Some(hail.console), kc, &kernel_configuration_capability);
 //! ```
 //!
 //! Example
@@ -26,24 +26,64 @@
 //! debug!("Yes the code gets here with value {}", i);
 //! debug_verbose!("got here"); // includes message count, file, and line
 //! debug_gpio!(0, toggle); // Toggles the first debug GPIO
+//! log_warn!("retrying, attempt {}", n); // includes level and module
 //! ```
 //!
 //! ```
 //! Yes the code gets here with value 42
 //! TOCK_DEBUG(0): /tock/capsules/src/sensys.rs:24: got here
+//! WARN: capsules::sensys: retrying, attempt 2
 //! ```
+//!
+//! Log levels
+//! ----------
+//!
+//! Every message has a level: `log_error!`, `log_warn!`, `log_info!` and
+//! `log_trace!` set it explicitly, and `debug!` and `debug_verbose!` print at
+//! `Level::Info`. Messages above `MAX_LEVEL` are compiled out. `MAX_LEVEL` is
+//! `Level::Trace` unless one of the kernel crate's `log_max_level_error`,
+//! `log_max_level_warn` or `log_max_level_info` features is enabled.
+//!
+//! At runtime, messages above `Level::Info` are dropped, unless the board
+//! changes the threshold, for all modules or only for some of them:
+//!
+//! ```rust
+//! kernel::debug::set_level(kernel::debug::Level::Warn, &kernel_configuration_capability);
+//! kernel::debug::set_module_level(
+//!     "capsules::net",
+//!     kernel::debug::Level::Trace,
+//!     &kernel_configuration_capability,
+//! );
+//! ```
+//!
+//! Sinks
+//! -----
+//!
+//! Messages go to the console driver assigned with `assign_console_driver`,
+//! and to every other `DebugSink` the board adds with `add_sink`, such as a
+//! Segger RTT channel. A `DebugRing` keeps the most recent messages in RAM,
+//! and the one assigned with `assign_ring` is saved in the crash record when
+//! the kernel panics. Sinks that have no room for a message drop it, and
+//! `dropped_messages` counts how many were dropped.
 
 use callback::{AppId, Callback};
+use capabilities::KernelConfigurationCapability;
+use common::cells::TakeCell;
+use core::cell::Cell;
 use core::cmp::min;
-use core::fmt::{write, Arguments, Result, Write};
+use core::fmt::{write, Arguments, Error, Result, Write};
 use core::panic::PanicInfo;
-use core::ptr::{read_volatile, write_volatile};
+use core::ptr::{self, read_volatile, write_volatile};
 use core::{slice, str};
+use crash;
 use driver::Driver;
 use hil;
 use mem::AppSlice;
 use process;
 use returncode::ReturnCode;
+use trace;
+
+pub use crash::{clear_crash_record, crash_record, CrashRecord, REGISTER_NAMES};
 
 ///////////////////////////////////////////////////////////////////
 // panic! support routines
@@ -58,6 +98,7 @@ pub unsafe fn panic<L: hil::led::Led, W: Write>(
     nop: &Fn(),
 ) -> ! {
     panic_begin(nop);
+    panic_crash_record(panic_info);
     panic_banner(writer, panic_info);
     // Flush debug buffer if needed
     flush(writer);
@@ -77,6 +118,12 @@ pub unsafe fn panic_begin(nop: &Fn()) {
     }
 }
 
+/// Save the current panic in the crash record, so that it can be reported
+/// after the chip reboots.
+pub unsafe fn panic_crash_record(panic_info: &PanicInfo) {
+    crash::record_panic(panic_info);
+}
+
 /// Lightweight prints about the current panic and kernel version.
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
@@ -118,6 +165,12 @@ pub unsafe fn panic_process_info<W: Write>(writer: &mut W) {
             process.statistics_str(writer);
         });
     }
+
+    // print the most recent syscalls, if they were traced
+    trace::syscall_trace().map(|syscall_trace| {
+        let _ = writer.write_fmt(format_args!("\r\n---| Syscall Trace |---\r\n"));
+        syscall_trace.write_to(writer);
+    });
 }
 
 /// Blinks a recognizable pattern forever.
@@ -181,6 +234,267 @@ macro_rules! debug_gpio {
     }};
 }
 
+///////////////////////////////////////////////////////////////////
+// log levels and filtering
+
+/// How important a debug message is, from most to least.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// The least important level that is compiled in.
+#[cfg(feature = "log_max_level_error")]
+pub const MAX_LEVEL: Level = Level::Error;
+#[cfg(all(feature = "log_max_level_warn", not(feature = "log_max_level_error")))]
+pub const MAX_LEVEL: Level = Level::Warn;
+#[cfg(all(
+    feature = "log_max_level_info",
+    not(any(feature = "log_max_level_error", feature = "log_max_level_warn"))
+))]
+pub const MAX_LEVEL: Level = Level::Info;
+#[cfg(not(any(
+    feature = "log_max_level_error",
+    feature = "log_max_level_warn",
+    feature = "log_max_level_info"
+)))]
+pub const MAX_LEVEL: Level = Level::Trace;
+
+/// How many modules can have their own level.
+const MODULE_LEVELS_LEN: usize = 8;
+
+/// The least important level printed for modules without their own.
+static mut LEVEL: Level = Level::Info;
+
+/// Modules with their own level, by module path prefix.
+static mut MODULE_LEVELS: [Option<(&'static str, Level)>; MODULE_LEVELS_LEN] =
+    [None; MODULE_LEVELS_LEN];
+
+/// Print messages up to `level` from modules without their own level.
+pub fn set_level(level: Level, _capability: &KernelConfigurationCapability) {
+    unsafe {
+        LEVEL = level;
+    }
+}
+
+/// Print messages up to `level` from `module` and the modules inside it, for
+/// example `"capsules::net"`. Returns `ENOMEM` if too many modules have their
+/// own level already.
+pub fn set_module_level(
+    module: &'static str,
+    level: Level,
+    _capability: &KernelConfigurationCapability,
+) -> ReturnCode {
+    unsafe {
+        let mut free = None;
+        for (i, entry) in MODULE_LEVELS.iter_mut().enumerate() {
+            match *entry {
+                Some((prefix, _)) if prefix == module => {
+                    *entry = Some((module, level));
+                    return ReturnCode::SUCCESS;
+                }
+                None if free.is_none() => free = Some(i),
+                _ => {}
+            }
+        }
+        match free {
+            Some(i) => {
+                MODULE_LEVELS[i] = Some((module, level));
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::ENOMEM,
+        }
+    }
+}
+
+/// Whether `module` is `prefix` or a module inside it.
+fn module_matches(module: &str, prefix: &str) -> bool {
+    module.starts_with(prefix)
+        && (module.len() == prefix.len() || module[prefix.len()..].starts_with("::"))
+}
+
+/// Whether a message at `level` from `module` is printed. The most specific
+/// module level that applies wins.
+pub fn enabled(level: Level, module: &str) -> bool {
+    if level > MAX_LEVEL {
+        return false;
+    }
+    unsafe {
+        let mut threshold = LEVEL;
+        let mut matched_len = 0;
+        for entry in MODULE_LEVELS.iter() {
+            if let Some((prefix, prefix_level)) = *entry {
+                if prefix.len() >= matched_len && module_matches(module, prefix) {
+                    threshold = prefix_level;
+                    matched_len = prefix.len();
+                }
+            }
+        }
+        level <= threshold
+    }
+}
+
+///////////////////////////////////////////////////////////////////
+// debug sinks
+
+/// Somewhere debug messages are printed, besides the console driver.
+pub trait DebugSink {
+    /// Print `message`, which ends with a newline. Returns `false` if there
+    /// was no room for it, in which case none of it may be printed.
+    fn write_message(&self, level: Level, message: Arguments) -> bool;
+}
+
+/// How many sinks can be added besides the console driver.
+const SINKS_LEN: usize = 4;
+
+static mut SINKS: [Option<&'static DebugSink>; SINKS_LEN] = [None; SINKS_LEN];
+
+/// How many messages sinks had no room for.
+static mut DROPPED_MESSAGES: usize = 0;
+
+/// The ring saved in the crash record, if the board assigned one.
+static mut DEBUG_RING: Option<&'static DebugRing> = None;
+
+/// Also print debug messages to `sink`. Returns `ENOMEM` if there are too
+/// many sinks already.
+pub fn add_sink(
+    sink: &'static DebugSink,
+    _capability: &KernelConfigurationCapability,
+) -> ReturnCode {
+    unsafe {
+        match SINKS.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(sink);
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::ENOMEM,
+        }
+    }
+}
+
+/// Keep the most recent debug messages in `ring`, and save them in the crash
+/// record if the kernel panics.
+pub fn assign_ring(
+    ring: &'static DebugRing,
+    capability: &KernelConfigurationCapability,
+) -> ReturnCode {
+    let res = add_sink(ring, capability);
+    if res == ReturnCode::SUCCESS {
+        unsafe {
+            DEBUG_RING = Some(ring);
+        }
+    }
+    res
+}
+
+/// How many debug messages were dropped because a sink had no room for them.
+pub fn dropped_messages() -> usize {
+    unsafe { read_volatile(&DROPPED_MESSAGES) }
+}
+
+/// Send a message to the console driver and to every sink.
+///
+/// Until a console driver is assigned, messages stay in the console's buffer,
+/// so that a panic can still flush them.
+fn write_message(level: Level, message: Arguments) {
+    unsafe {
+        let mut dropped = 0;
+        // Without a driver the buffer only keeps early messages for a panic,
+        // so running out of room there drops nothing that would be printed.
+        if !DEBUG_WRITER.write_message(message) && DEBUG_WRITER.driver.is_some() {
+            dropped += 1;
+        }
+        for sink in SINKS.iter() {
+            if let Some(sink) = *sink {
+                if !sink.write_message(level, message) {
+                    dropped += 1;
+                }
+            }
+        }
+        if dropped > 0 {
+            write_volatile(&mut DROPPED_MESSAGES, DROPPED_MESSAGES + dropped);
+        }
+    }
+}
+
+/// `DebugSink` that keeps the most recent messages in RAM, overwriting the
+/// oldest ones.
+pub struct DebugRing {
+    buffer: TakeCell<'static, [u8]>,
+    /// Where the next byte is written.
+    head: Cell<usize>,
+    /// How many of the bytes are valid.
+    len: Cell<usize>,
+}
+
+impl DebugRing {
+    pub fn new(buffer: &'static mut [u8]) -> DebugRing {
+        DebugRing {
+            buffer: TakeCell::new(buffer),
+            head: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+
+    /// Copy the most recent bytes in the ring to `buf`. Returns how many were
+    /// copied.
+    pub fn copy_tail(&self, buf: &mut [u8]) -> usize {
+        self.buffer.map_or(0, |buffer| {
+            let copied = min(self.len.get(), buf.len());
+            let start = self.head.get() + buffer.len() - copied;
+            for (i, dst) in buf[..copied].iter_mut().enumerate() {
+                *dst = buffer[(start + i) % buffer.len()];
+            }
+            copied
+        })
+    }
+}
+
+impl DebugRing {
+    fn write_bytes(&self, bytes: &[u8]) {
+        self.buffer.map(|buffer| {
+            if buffer.is_empty() {
+                return;
+            }
+            for &byte in bytes {
+                let head = self.head.get();
+                buffer[head] = byte;
+                self.head.set((head + 1) % buffer.len());
+            }
+            let len = min(self.len.get() + bytes.len(), buffer.len());
+            self.len.set(len);
+        });
+    }
+}
+
+impl DebugSink for DebugRing {
+    fn write_message(&self, _level: Level, message: Arguments) -> bool {
+        struct RingWriter<'a>(&'a DebugRing);
+        impl Write for RingWriter<'a> {
+            fn write_str(&mut self, s: &str) -> Result {
+                self.0.write_bytes(s.as_bytes());
+                Ok(())
+            }
+        }
+        let _ = write(&mut RingWriter(self), message);
+        true
+    }
+}
+
 ///////////////////////////////////////////////////////////////////
 // debug! and debug_verbose! support
 
@@ -207,17 +521,27 @@ static mut DEBUG_WRITER: DebugWriter = DebugWriter {
     count: 0,             // how many debug! calls
 };
 
-pub unsafe fn assign_console_driver<T>(driver: Option<&'static Driver>, grant: &mut T) {
+pub unsafe fn assign_console_driver<T>(
+    driver: Option<&'static Driver>,
+    grant: &mut T,
+    _capability: &KernelConfigurationCapability,
+) {
     let ptr: *mut u8 = grant as *mut T as *mut u8;
     DEBUG_WRITER.driver = driver;
     DEBUG_WRITER.grant = Some(ptr);
 }
 
+/// Whether `debug!` has somewhere to print to.
+pub(crate) fn has_console_driver() -> bool {
+    unsafe { DEBUG_WRITER.driver.is_some() }
+}
+
+/// The grant of the kernel's debug app, or null if the board has not assigned
+/// a console driver to it.
 pub unsafe fn get_grant<T>() -> *mut T {
-    match DEBUG_WRITER.grant {
-        Some(grant) => grant as *mut T,
-        None => panic!("Request for unallocated kernel grant"),
-    }
+    DEBUG_WRITER
+        .grant
+        .map_or(ptr::null_mut(), |grant| grant as *mut T)
 }
 
 impl DebugWriter {
@@ -300,6 +624,26 @@ impl DebugWriter {
             }
         }
     }
+    /// Add a whole message to the buffer and start printing it, if a console
+    /// driver is assigned. Returns `false`, and leaves the buffer as it was,
+    /// if the message does not fit.
+    ///
+    /// Messages written before the driver is assigned are printed along with
+    /// the first message after it, or flushed if the kernel panics first.
+    fn write_message(&mut self, message: Arguments) -> bool {
+        let head = unsafe { read_volatile(&self.output_head) };
+        if write(self, message).is_err() {
+            unsafe {
+                write_volatile(&mut self.output_head, head);
+            }
+            return false;
+        }
+        if self.driver.is_some() && unsafe { read_volatile(&self.output_head) } != head {
+            self.publish_str();
+        }
+        true
+    }
+
     fn callback(bytes_written: usize, _: usize, _: usize, _: usize) {
         let active = unsafe { read_volatile(&DEBUG_WRITER.output_active_len) };
         if active != bytes_written {
@@ -403,26 +747,12 @@ impl Write for DebugWriter {
             let start = head;
             let end = tail;
             if (tail == 0) && (head == len - 1) {
-                let active = unsafe { read_volatile(&DEBUG_WRITER.output_active_len) };
-                panic!(
-                    "Debug buffer full. Head {} tail {} len {} active {} remaining {}",
-                    head,
-                    tail,
-                    len,
-                    active,
-                    remaining_bytes.len()
-                );
+                // Buffer full
+                return Err(Error);
             }
             if remaining_bytes.len() > end - start {
-                let active = unsafe { read_volatile(&DEBUG_WRITER.output_active_len) };
-                panic!(
-                    "Debug buffer out of room. Head {} tail {} len {} active {} remaining {}",
-                    head,
-                    tail,
-                    len,
-                    active,
-                    remaining_bytes.len()
-                );
+                // Buffer out of room
+                return Err(Error);
             }
             DebugWriter::write_buffer(start, end, remaining_bytes);
             let written = min(end - start, remaining_bytes.len());
@@ -439,26 +769,38 @@ impl Write for DebugWriter {
     }
 }
 
-pub fn begin_debug_fmt(args: Arguments) {
-    unsafe {
-        let writer = &mut DEBUG_WRITER;
-        let _ = write(writer, args);
-        let _ = writer.write_str("\n");
-        writer.publish_str();
+pub fn begin_debug_fmt(level: Level, module: &'static str, args: Arguments) {
+    if enabled(level, module) {
+        write_message(level, format_args!("{}\n", args));
     }
 }
 
-pub fn begin_debug_verbose_fmt(args: Arguments, file_line: &(&'static str, u32)) {
-    unsafe {
-        let count = read_volatile(&DEBUG_WRITER.count);
-        write_volatile(&mut DEBUG_WRITER.count, count + 1);
-
-        let writer = &mut DEBUG_WRITER;
+pub fn begin_debug_verbose_fmt(
+    level: Level,
+    module: &'static str,
+    args: Arguments,
+    file_line: &(&'static str, u32),
+) {
+    if enabled(level, module) {
+        let count = unsafe {
+            let count = read_volatile(&DEBUG_WRITER.count);
+            write_volatile(&mut DEBUG_WRITER.count, count + 1);
+            count
+        };
         let (file, line) = *file_line;
-        let _ = writer.write_fmt(format_args!("TOCK_DEBUG({}): {}:{}: ", count, file, line));
-        let _ = write(writer, args);
-        let _ = writer.write_str("\n");
-        writer.publish_str();
+        write_message(
+            level,
+            format_args!("TOCK_DEBUG({}): {}:{}: {}\n", count, file, line, args),
+        );
+    }
+}
+
+pub fn begin_log_fmt(level: Level, module: &'static str, args: Arguments) {
+    if enabled(level, module) {
+        write_message(
+            level,
+            format_args!("{}: {}: {}\n", level.as_str(), module, args),
+        );
     }
 }
 
@@ -470,10 +812,22 @@ macro_rules! debug {
         debug!("")
     });
     ($msg:expr) => ({
-        $crate::debug::begin_debug_fmt(format_args!($msg))
+        if $crate::debug::Level::Info <= $crate::debug::MAX_LEVEL {
+            $crate::debug::begin_debug_fmt(
+                $crate::debug::Level::Info,
+                module_path!(),
+                format_args!($msg),
+            )
+        }
     });
     ($fmt:expr, $($arg:tt)+) => ({
-        $crate::debug::begin_debug_fmt(format_args!($fmt, $($arg)+))
+        if $crate::debug::Level::Info <= $crate::debug::MAX_LEVEL {
+            $crate::debug::begin_debug_fmt(
+                $crate::debug::Level::Info,
+                module_path!(),
+                format_args!($fmt, $($arg)+),
+            )
+        }
     });
 }
 
@@ -485,21 +839,72 @@ macro_rules! debug_verbose {
         debug_verbose!("")
     });
     ($msg:expr) => ({
-        $crate::debug::begin_debug_verbose_fmt(format_args!($msg), {
-            // TODO: Maybe make opposite choice of panic!, no `static`, more
-            // runtime code for less static data
-            static _FILE_LINE: (&'static str, u32) = (file!(), line!());
-            &_FILE_LINE
-        })
+        if $crate::debug::Level::Info <= $crate::debug::MAX_LEVEL {
+            $crate::debug::begin_debug_verbose_fmt(
+                $crate::debug::Level::Info,
+                module_path!(),
+                format_args!($msg),
+                {
+                    // TODO: Maybe make opposite choice of panic!, no `static`,
+                    // more runtime code for less static data
+                    static _FILE_LINE: (&'static str, u32) = (file!(), line!());
+                    &_FILE_LINE
+                },
+            )
+        }
     });
     ($fmt:expr, $($arg:tt)+) => ({
-        $crate::debug::begin_debug_verbose_fmt(format_args!($fmt, $($arg)+), {
-            static _FILE_LINE: (&'static str, u32) = (file!(), line!());
-            &_FILE_LINE
-        })
+        if $crate::debug::Level::Info <= $crate::debug::MAX_LEVEL {
+            $crate::debug::begin_debug_verbose_fmt(
+                $crate::debug::Level::Info,
+                module_path!(),
+                format_args!($fmt, $($arg)+),
+                {
+                    static _FILE_LINE: (&'static str, u32) = (file!(), line!());
+                    &_FILE_LINE
+                },
+            )
+        }
     });
 }
 
+/// Print a message at `level`, with the level and module.
+///
+/// Crates that import the kernel's macros by name must import `log` along
+/// with the `log_*` macros, since those expand to it.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => ({
+        if $level <= $crate::debug::MAX_LEVEL {
+            $crate::debug::begin_log_fmt($level, module_path!(), format_args!($($arg)+))
+        }
+    });
+}
+
+/// Print an error, with its level and module.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => (log!($crate::debug::Level::Error, $($arg)+));
+}
+
+/// Print a warning, with its level and module.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => (log!($crate::debug::Level::Warn, $($arg)+));
+}
+
+/// Print an informational message, with its level and module.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => (log!($crate::debug::Level::Info, $($arg)+));
+}
+
+/// Print a detailed trace message, with its level and module.
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => (log!($crate::debug::Level::Trace, $($arg)+));
+}
+
 pub trait Debug {
     fn write(&self, buf: &'static mut [u8], len: usize);
 }
@@ -514,6 +919,32 @@ impl Default for Debug {
     }
 }
 
+/// Copy the last bytes written to the debug ring, if there is one, or else to
+/// the console driver's buffer, whether or not they were printed yet, to
+/// `buf`. Returns how many were copied.
+pub(crate) unsafe fn copy_debug_tail(buf: &mut [u8]) -> usize {
+    if let Some(ring) = DEBUG_RING {
+        return ring.copy_tail(buf);
+    }
+
+    let head = read_volatile(&DEBUG_WRITER.output_head);
+    let buffer = &DEBUG_WRITER.output_buffer;
+    // Oldest bytes first. Bytes that were never written are still zero.
+    let written = || {
+        buffer[head..]
+            .iter()
+            .chain(buffer[..head].iter())
+            .filter(|byte| **byte != 0)
+    };
+    let skip = written().count().saturating_sub(buf.len());
+    let mut copied = 0;
+    for (dst, src) in buf.iter_mut().zip(written().skip(skip)) {
+        *dst = *src;
+        copied += 1;
+    }
+    copied
+}
+
 pub unsafe fn flush<W: Write>(writer: &mut W) {
     let debug_head = read_volatile(&DEBUG_WRITER.output_head);
     let mut debug_tail = read_volatile(&DEBUG_WRITER.output_tail);
@@ -539,4 +970,11 @@ pub unsafe fn flush<W: Write>(writer: &mut W) {
             let _ = writer.write_str(s);
         }
     }
+    let dropped = dropped_messages();
+    if dropped > 0 {
+        let _ = writer.write_fmt(format_args!(
+            "\r\n---| {} debug messages dropped\r\n",
+            dropped
+        ));
+    }
 }
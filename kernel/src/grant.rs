@@ -1,10 +1,11 @@
 //! Data structure to store a list of userspace applications.
 
 use callback::AppId;
+use capabilities::MemoryAllocationCapability;
 use core::marker::PhantomData;
 use core::mem::size_of;
 use core::ops::{Deref, DerefMut};
-use core::ptr::{read_volatile, write_volatile, Unique};
+use core::ptr::{read_volatile, write, write_volatile, Unique};
 use debug;
 use process::{self, Error};
 
@@ -16,7 +17,8 @@ pub struct Grant<T: Default> {
 }
 
 pub struct AppliedGrant<T> {
-    appid: usize,
+    appid: AppId,
+    grant_num: usize,
     grant: *mut T,
     _phantom: PhantomData<T>,
 }
@@ -25,7 +27,8 @@ pub struct AppliedGrant<T> {
 /// functions for getting a pointer to their grant region. Normal apps are
 /// stored in a processes array, and finding apps is a matter of iterating that
 /// array. Kernel "apps" currently (June 2018) have no such structure, so
-/// finding them is a bit more ad-hoc.
+/// finding them is a bit more ad-hoc. The pointer is null if the kernel app
+/// has no grant, as on a board without a console for debug output.
 pub unsafe fn kernel_grant_for<T>(app_id: usize) -> *mut T {
     match app_id {
         debug::APPID_IDX => debug::get_grant(),
@@ -39,17 +42,19 @@ impl<T> AppliedGrant<T> {
         F: FnOnce(&mut Owned<T>, &mut Allocator) -> R,
         R: Copy,
     {
-        if AppId::is_kernel_idx(self.appid) {
+        if self.appid.is_kernel() {
             let mut allocator = Allocator {
                 app: None,
                 app_id: self.appid,
+                grant_num: self.grant_num,
             };
             let mut root = unsafe { Owned::new(self.grant, self.appid) };
             fun(&mut root, &mut allocator)
         } else {
             let mut allocator = Allocator {
-                app: unsafe { process::PROCS[self.appid].as_mut() },
+                app: unsafe { process::PROCS[self.appid.idx()].as_mut() },
                 app_id: self.appid,
+                grant_num: self.grant_num,
             };
             let mut root = unsafe { Owned::new(self.grant, self.appid) };
             fun(&mut root, &mut allocator)
@@ -59,16 +64,18 @@ impl<T> AppliedGrant<T> {
 
 pub struct Allocator<'a> {
     app: Option<&'a mut &'a mut process::Process<'a>>,
-    app_id: usize,
+    app_id: AppId,
+    /// The grant memory is allocated for, so that it is accounted to it.
+    grant_num: usize,
 }
 
 pub struct Owned<T: ?Sized> {
     data: Unique<T>,
-    app_id: usize,
+    app_id: AppId,
 }
 
 impl<T: ?Sized> Owned<T> {
-    unsafe fn new(data: *mut T, app_id: usize) -> Owned<T> {
+    unsafe fn new(data: *mut T, app_id: AppId) -> Owned<T> {
         Owned {
             data: Unique::new_unchecked(data),
             app_id: app_id,
@@ -76,7 +83,7 @@ impl<T: ?Sized> Owned<T> {
     }
 
     pub fn appid(&self) -> AppId {
-        AppId::new(self.app_id)
+        self.app_id
     }
 }
 
@@ -85,11 +92,11 @@ impl<T: ?Sized> Drop for Owned<T> {
         unsafe {
             let app_id = self.app_id;
             let data = self.data.as_ptr() as *mut u8;
-            if AppId::is_kernel_idx(app_id) {
+            if app_id.is_kernel() {
                 /* kernel free is nop */
 ;
             } else {
-                match process::PROCS[app_id] {
+                match process::PROCS[app_id.idx()] {
                     None => {}
                     Some(ref mut app) => {
                         app.free(data);
@@ -117,17 +124,22 @@ impl Allocator<'a> {
     pub fn alloc<T>(&mut self, data: T) -> Result<Owned<T>, Error> {
         unsafe {
             let app_id = self.app_id;
+            let grant_num = self.grant_num;
             match self.app.as_mut() {
-                Some(app) => app
-                    .alloc(size_of::<T>())
-                    .map_or(Err(Error::OutOfMemory), |arr| {
-                        let mut owned = Owned::new(arr.as_mut_ptr() as *mut T, app_id);
-                        *owned = data;
-                        Ok(owned)
-                    }),
+                Some(app) => {
+                    let size = size_of::<T>();
+                    app.grant_alloc(grant_num, size)
+                        .map_or(Err(Error::OutOfMemory), |arr| {
+                            // The memory is not initialized, so there is no
+                            // old value to drop.
+                            let ptr = arr.as_mut_ptr() as *mut T;
+                            write(ptr, data);
+                            Ok(Owned::new(ptr, app_id))
+                        })
+                }
                 None => {
-                    if !AppId::is_kernel_idx(app_id) {
-                        panic!("No app for allocator for {}", app_id);
+                    if !app_id.is_kernel() {
+                        panic!("No app for allocator for {}", app_id.idx());
                     }
                     panic!("Request to allocate in kernel grant");
                 }
@@ -138,11 +150,11 @@ impl Allocator<'a> {
 
 pub struct Borrowed<'a, T: 'a + ?Sized> {
     data: &'a mut T,
-    app_id: usize,
+    app_id: AppId,
 }
 
 impl<T: 'a + ?Sized> Borrowed<'a, T> {
-    pub fn new(data: &'a mut T, app_id: usize) -> Borrowed<T> {
+    pub fn new(data: &'a mut T, app_id: AppId) -> Borrowed<T> {
         Borrowed {
             data: data,
             app_id: app_id,
@@ -150,7 +162,7 @@ impl<T: 'a + ?Sized> Borrowed<'a, T> {
     }
 
     pub fn appid(&self) -> AppId {
-        AppId::new(self.app_id)
+        self.app_id
     }
 }
 
@@ -168,12 +180,14 @@ impl<T: 'a + ?Sized> DerefMut for Borrowed<'a, T> {
 }
 
 impl<T: Default> Grant<T> {
-    pub unsafe fn create() -> Grant<T> {
-        let ctr = read_volatile(&CONTAINER_COUNTER);
-        write_volatile(&mut CONTAINER_COUNTER, ctr + 1);
-        Grant {
-            grant_num: ctr,
-            ptr: PhantomData,
+    pub fn create(_capability: &MemoryAllocationCapability) -> Grant<T> {
+        unsafe {
+            let ctr = read_volatile(&CONTAINER_COUNTER);
+            write_volatile(&mut CONTAINER_COUNTER, ctr + 1);
+            Grant {
+                grant_num: ctr,
+                ptr: PhantomData,
+            }
         }
     }
 
@@ -182,26 +196,32 @@ impl<T: Default> Grant<T> {
             let app_id = appid.idx();
             if AppId::is_kernel(appid) {
                 let cntr = kernel_grant_for::<T>(app_id);
-                Some(AppliedGrant {
-                    appid: app_id,
-                    grant: cntr,
-                    _phantom: PhantomData,
-                })
+                if cntr.is_null() {
+                    None
+                } else {
+                    Some(AppliedGrant {
+                        appid: appid,
+                        grant_num: self.grant_num,
+                        grant: cntr,
+                        _phantom: PhantomData,
+                    })
+                }
             } else {
                 match process::PROCS[app_id] {
-                    Some(ref mut app) => {
+                    Some(ref mut app) if app.appid(app_id) == appid => {
                         let cntr = app.grant_for::<T>(self.grant_num);
                         if cntr.is_null() {
                             None
                         } else {
                             Some(AppliedGrant {
-                                appid: app_id,
+                                appid: appid,
+                                grant_num: self.grant_num,
                                 grant: cntr,
                                 _phantom: PhantomData,
                             })
                         }
                     }
-                    None => None,
+                    _ => None,
                 }
             }
         }
@@ -216,28 +236,35 @@ impl<T: Default> Grant<T> {
             let app_id = appid.idx();
             if AppId::is_kernel(appid) {
                 let root_ptr = kernel_grant_for::<T>(app_id);
-                let mut root = Borrowed::new(&mut *root_ptr, app_id);
+                if root_ptr.is_null() {
+                    return Err(Error::NoSuchApp);
+                }
+                let mut root = Borrowed::new(&mut *root_ptr, appid);
                 let mut allocator = Allocator {
                     app: None,
-                    app_id: app_id,
+                    app_id: appid,
+                    grant_num: self.grant_num,
                 };
                 let res = fun(&mut root, &mut allocator);
                 Ok(res)
             } else {
+                // A stale `AppId` must not reach the grant of the process
+                // that now runs in its slot.
                 match process::PROCS[app_id] {
-                    Some(ref mut app) => app.grant_for_or_alloc::<T>(self.grant_num).map_or(
-                        Err(Error::OutOfMemory),
-                        move |root_ptr| {
-                            let mut root = Borrowed::new(&mut *root_ptr, app_id);
+                    Some(ref mut app) if app.appid(app_id) == appid => {
+                        let root_ptr = app.grant_for_or_alloc::<T>(self.grant_num);
+                        root_ptr.map_or(Err(Error::OutOfMemory), move |root_ptr| {
+                            let mut root = Borrowed::new(&mut *root_ptr, appid);
                             let mut allocator = Allocator {
                                 app: Some(app),
-                                app_id: app_id,
+                                app_id: appid,
+                                grant_num: self.grant_num,
                             };
                             let res = fun(&mut root, &mut allocator);
                             Ok(res)
-                        },
-                    ),
-                    None => Err(Error::NoSuchApp),
+                        })
+                    }
+                    _ => Err(Error::NoSuchApp),
                 }
             }
         }
@@ -248,18 +275,21 @@ impl<T: Default> Grant<T> {
         F: Fn(&mut Owned<T>),
     {
         unsafe {
-            let itr = process::PROCS.iter_mut().filter_map(|p| p.as_mut());
-            for (app_id, app) in itr.enumerate() {
+            for (app_idx, app) in process::PROCS.iter_mut().enumerate() {
+                let app = match app.as_mut() {
+                    Some(app) => app,
+                    None => continue,
+                };
                 let root_ptr = app.grant_for::<T>(self.grant_num);
                 if !root_ptr.is_null() {
-                    let mut root = Owned::new(root_ptr, app_id);
+                    let mut root = Owned::new(root_ptr, app.appid(app_idx));
                     fun(&mut root);
                 }
             }
             // After iterating all possible normal apps, try the debug app.
             let root_ptr = kernel_grant_for::<T>(debug::APPID_IDX);
             if !root_ptr.is_null() {
-                let mut root = Owned::new(root_ptr, debug::APPID_IDX);
+                let mut root = Owned::new(root_ptr, AppId::kernel_new(debug::APPID_IDX));
                 fun(&mut root);
             }
         }
@@ -289,7 +319,8 @@ impl<T: Default> Iterator for Iter<'a, T> {
         while self.index < self.len {
             let idx = self.index;
             self.index += 1;
-            let res = self.grant.grant(AppId::new(idx));
+            let appid = unsafe { process::PROCS[idx].as_ref().map(|app| app.appid(idx)) };
+            let res = appid.and_then(|appid| self.grant.grant(appid));
             if res.is_some() {
                 return res;
             }
@@ -298,7 +329,7 @@ impl<T: Default> Iterator for Iter<'a, T> {
         // the grant iterator in case it has state the capsule needs to process.
         if self.index == self.len {
             self.index += 1;
-            let res = self.grant.grant(AppId::new(debug::APPID_IDX));
+            let res = self.grant.grant(AppId::kernel_new(debug::APPID_IDX));
             if res.is_some() {
                 return res;
             }
@@ -1,32 +1,221 @@
 //! Inter-process communication mechanism for Tock.
 //!
 //! This is a special syscall driver that allows userspace applications to
-//! share memory.
+//! share memory and to send each other messages.
+//!
+//! Services register a name and a version with `allow(SERVICE_NAME, ..)` and
+//! `command(SERVICE_NAME, version, flags)`, and clients find them by that
+//! name. A service can restrict which clients may discover it, share buffers
+//! with it, notify it and send it messages: only the clients whose package
+//! names are in the list it allowed with `ALLOWED_CLIENTS`, and only clients
+//! whose TBF header explicitly lists this driver in a Permissions TLV if it
+//! registers with `REQUIRE_PERMISSION`. Processes that did not register are
+//! still found by their package name, without a version.
+//!
+//! Messages are copied by the kernel. A process sends up to `MAX_MESSAGE_LEN`
+//! bytes from the buffer it shared with another process, and the kernel
+//! queues a copy in the grant of the receiver. The receiver gets the oldest
+//! message in the buffer it allowed with `MESSAGES` and an upcall with the
+//! sender's ID and the length. Until it releases that message with
+//! `command(MESSAGES, ..)` the next one stays queued. Since the upcall names
+//! the sender, a service can reply to the client that sent a request.
 
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x00010000;
 
+/// Number used in place of a process ID to allow the buffer messages are
+/// received in, to subscribe to messages and to release a received message.
+pub const MESSAGES: usize = 0xFFFF;
+
+/// Number used in place of a process ID to allow the name of a service and
+/// to register it.
+pub const SERVICE_NAME: usize = 0xFFFE;
+
+/// Number used in place of a process ID to allow the list of clients that may
+/// use a service.
+pub const ALLOWED_CLIENTS: usize = 0xFFFD;
+
+/// Flag for registering a service that only clients with an explicit
+/// permission for this driver may use.
+pub const REQUIRE_PERMISSION: usize = 1;
+
+/// Longest message that can be sent, in bytes.
+pub const MAX_MESSAGE_LEN: usize = 32;
+
+/// How many messages can wait for a process to receive them.
+const MESSAGE_QUEUE_LEN: usize = 4;
+
 use callback::{AppId, Callback};
+use capabilities::MemoryAllocationCapability;
 use driver::Driver;
-use grant::Grant;
+use grant::{Allocator, Grant, Owned};
 use mem::{AppSlice, Shared};
 use process;
 use returncode::ReturnCode;
 
+/// A copy of a message sent to a process.
+#[derive(Clone, Copy)]
+struct Message {
+    sender: Option<AppId>,
+    len: usize,
+    data: [u8; MAX_MESSAGE_LEN],
+}
+
+impl Message {
+    const EMPTY: Message = Message {
+        sender: None,
+        len: 0,
+        data: [0; MAX_MESSAGE_LEN],
+    };
+}
+
+/// Messages waiting for a process, oldest first.
+struct MessageQueue {
+    messages: [Message; MESSAGE_QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl MessageQueue {
+    fn push(&mut self, message: Message) -> bool {
+        if self.len == MESSAGE_QUEUE_LEN {
+            return false;
+        }
+        self.messages[(self.head + self.len) % MESSAGE_QUEUE_LEN] = message;
+        self.len += 1;
+        true
+    }
+
+    fn peek(&self) -> Option<&Message> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(&self.messages[self.head])
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.len > 0 {
+            self.head = (self.head + 1) % MESSAGE_QUEUE_LEN;
+            self.len -= 1;
+        }
+    }
+}
+
+/// What a process keeps about another process it talks to. Peers are
+/// allocated in the grant of the process as needed, so the number of
+/// processes it can talk to is only limited by its memory.
+struct Peer {
+    /// The run of the other process this peer is for.
+    appid: AppId,
+    shared_memory: Option<AppSlice<Shared, u8>>,
+    /// The other process may only read the shared memory.
+    read_only: bool,
+    client_callback: Option<Callback>,
+    next: Option<Owned<Peer>>,
+}
+
+impl Peer {
+    /// The peer for any run of the process at `idx`.
+    fn find(&mut self, idx: usize) -> Option<&mut Peer> {
+        if self.appid.idx() == idx {
+            Some(self)
+        } else {
+            match self.next {
+                Some(ref mut next) => next.find(idx),
+                None => None,
+            }
+        }
+    }
+}
+
 struct IPCData {
-    shared_memory: [Option<AppSlice<Shared, u8>>; 8],
-    client_callbacks: [Option<Callback>; 8],
+    peers: Option<Owned<Peer>>,
     callback: Option<Callback>,
+    /// The name the process registers its service under.
+    service_name: Option<AppSlice<Shared, u8>>,
+    /// The version of the service, once it is registered.
+    service_version: Option<usize>,
+    /// Package names of the clients that may use the service, separated by
+    /// commas. Any client may if this is not set.
+    allowed_clients: Option<AppSlice<Shared, u8>>,
+    require_permission: bool,
+    /// The name of the service to discover with `command(0, version, ..)`.
+    discover_name: Option<AppSlice<Shared, u8>>,
+    /// Messages sent to this process that it has not received yet.
+    messages: MessageQueue,
+    /// Where received messages are copied to.
+    receive_buffer: Option<AppSlice<Shared, u8>>,
+    message_callback: Option<Callback>,
+    /// The receive buffer holds a message the process has not released.
+    message_held: bool,
 }
 
 impl Default for IPCData {
     fn default() -> IPCData {
         IPCData {
-            shared_memory: [None, None, None, None, None, None, None, None],
-            client_callbacks: [None, None, None, None, None, None, None, None],
+            peers: None,
             callback: None,
+            service_name: None,
+            service_version: None,
+            allowed_clients: None,
+            require_permission: false,
+            discover_name: None,
+            messages: MessageQueue {
+                messages: [Message::EMPTY; MESSAGE_QUEUE_LEN],
+                head: 0,
+                len: 0,
+            },
+            receive_buffer: None,
+            message_callback: None,
+            message_held: false,
+        }
+    }
+}
+
+impl IPCData {
+    /// The peer for `appid`. An earlier run of the same process is not
+    /// `appid`'s peer, so that a restarted process does not inherit what was
+    /// shared with it before.
+    fn peer(&mut self, appid: AppId) -> Option<&mut Peer> {
+        match self.peers {
+            Some(ref mut peer) => peer.find(appid.idx()).filter(|peer| peer.appid == appid),
+            None => None,
         }
     }
+
+    /// The peer for `appid`, allocated if there is none yet. The peer of an
+    /// earlier run of the process is cleared and reused.
+    fn peer_or_alloc(&mut self, appid: AppId, allocator: &mut Allocator) -> Option<&mut Peer> {
+        let found = match self.peers {
+            Some(ref mut peer) => peer
+                .find(appid.idx())
+                .map(|peer| {
+                    if peer.appid != appid {
+                        peer.appid = appid;
+                        peer.shared_memory = None;
+                        peer.read_only = false;
+                        peer.client_callback = None;
+                    }
+                })
+                .is_some(),
+            None => false,
+        };
+        if !found {
+            let mut peer = allocator
+                .alloc(Peer {
+                    appid: appid,
+                    shared_memory: None,
+                    read_only: false,
+                    client_callback: None,
+                    next: None,
+                })
+                .ok()?;
+            peer.next = self.peers.take();
+            self.peers = Some(peer);
+        }
+        self.peer(appid)
+    }
 }
 
 pub struct IPC {
@@ -34,9 +223,9 @@ pub struct IPC {
 }
 
 impl IPC {
-    pub unsafe fn new() -> IPC {
+    pub fn new(capability: &MemoryAllocationCapability) -> IPC {
         IPC {
-            data: Grant::create(),
+            data: Grant::create(capability),
         }
     }
 
@@ -51,28 +240,31 @@ impl IPC {
                 let callback = match cb_type {
                     process::IPCType::Service => mydata.callback,
                     process::IPCType::Client => {
-                        *mydata.client_callbacks.get(otherapp.idx()).unwrap_or(&None)
+                        mydata.peer(otherapp).and_then(|peer| peer.client_callback)
                     }
                 };
                 callback
                     .map(|mut callback| {
                         self.data
-                            .enter(otherapp, |otherdata, _| {
-                                if appid.idx() >= otherdata.shared_memory.len() {
-                                    return;
-                                }
-                                match otherdata.shared_memory[appid.idx()] {
-                                    Some(ref slice) => {
+                            .enter(otherapp, |otherdata, _| match otherdata.peer(appid) {
+                                Some(&mut Peer {
+                                    shared_memory: Some(ref slice),
+                                    read_only,
+                                    ..
+                                }) => {
+                                    if read_only {
+                                        slice.expose_read_only_to(appid);
+                                    } else {
                                         slice.expose_to(appid);
-                                        callback.schedule(
-                                            otherapp.idx() + 1,
-                                            slice.len(),
-                                            slice.ptr() as usize,
-                                        );
-                                    }
-                                    None => {
-                                        callback.schedule(otherapp.idx() + 1, 0, 0);
                                     }
+                                    callback.schedule(
+                                        otherapp.idx() + 1,
+                                        slice.len(),
+                                        slice.ptr() as usize,
+                                    );
+                                }
+                                _ => {
+                                    callback.schedule(otherapp.idx() + 1, 0, 0);
                                 }
                             })
                             .unwrap_or(());
@@ -81,6 +273,211 @@ impl IPC {
             })
             .unwrap_or(());
     }
+
+    /// Find the process, other than `caller`, that registered the service
+    /// `name`. Returns it and the version of the service.
+    fn find_service(&self, name: &[u8], caller: AppId) -> Option<(AppId, usize)> {
+        let procs = unsafe { &mut process::PROCS };
+        for (i, p) in procs.iter().enumerate() {
+            let service = match *p {
+                Some(ref p) => p.appid(i),
+                None => continue,
+            };
+            if service == caller {
+                continue;
+            }
+            let version = self.data.grant(service).and_then(|grant| {
+                grant.enter(|data, _| match (data.service_version, &data.service_name) {
+                    (Some(version), &Some(ref service_name)) if service_name.as_ref() == name => {
+                        Some(version)
+                    }
+                    _ => None,
+                })
+            });
+            if let Some(version) = version {
+                return Some((service, version));
+            }
+        }
+        None
+    }
+
+    /// Whether `client` may use `service`, if `service` is a registered
+    /// service that restricts its clients.
+    fn may_connect(&self, client: AppId, service: AppId) -> bool {
+        if client == service {
+            return true;
+        }
+        let procs = unsafe { &mut process::PROCS };
+        let client_name = match procs[client.idx()] {
+            Some(ref p) => p.package_name.as_bytes(),
+            None => return false,
+        };
+        let permitted = client.has_explicit_driver_permission(DRIVER_NUM);
+        self.data.grant(service).map_or(true, |grant| {
+            grant.enter(|data, _| {
+                if data.service_version.is_none() {
+                    return true;
+                }
+                if data.require_permission && !permitted {
+                    return false;
+                }
+                match data.allowed_clients {
+                    Some(ref clients) => clients
+                        .as_ref()
+                        .split(|&c| c == b',')
+                        .any(|allowed| allowed == client_name),
+                    None => true,
+                }
+            })
+        })
+    }
+
+    /// Find the service `name` for `appid`. Registered services come first,
+    /// and have to have `version` if one is given. Processes that did not
+    /// register are found by their package name.
+    fn discover(&self, appid: AppId, name: &[u8], version: Option<usize>) -> ReturnCode {
+        let service = match self.find_service(name, appid) {
+            Some((service, service_version)) => {
+                if version.map_or(false, |version| version != service_version) {
+                    return ReturnCode::ENOSUPPORT;
+                }
+                service
+            }
+            None => {
+                let procs = unsafe { &mut process::PROCS };
+                let found = procs.iter().enumerate().find(|&(_, p)| match *p {
+                    Some(ref p) => p.package_name.as_bytes() == name,
+                    None => false,
+                });
+                match found {
+                    // Unregistered services have no version.
+                    Some(_) if version.is_some() => return ReturnCode::ENOSUPPORT,
+                    Some((i, &Some(ref p))) => p.appid(i),
+                    _ => return ReturnCode::ENODEVICE,
+                }
+            }
+        };
+
+        if !self.may_connect(appid, service) {
+            return ReturnCode::ENOPERM;
+        }
+        ReturnCode::SuccessWithValue {
+            value: service.idx() + 1,
+        }
+    }
+
+    /// Register the name `appid` allowed with `SERVICE_NAME` as a service.
+    /// Fails if another process already registered that name.
+    fn register_service(&self, appid: AppId, version: usize, flags: usize) -> ReturnCode {
+        self.data
+            .enter(appid, |data, _| {
+                let data: &mut IPCData = data;
+                match data.service_name {
+                    Some(ref name) if name.len() > 0 => {
+                        if self.find_service(name.as_ref(), appid).is_some() {
+                            return ReturnCode::EBUSY;
+                        }
+                    }
+                    _ => return ReturnCode::ERESERVE,
+                }
+                data.service_version = Some(version);
+                data.require_permission = flags & REQUIRE_PERMISSION != 0;
+                ReturnCode::SUCCESS
+            })
+            .unwrap_or(ReturnCode::EBUSY)
+    }
+
+    /// Queue a copy of the first `len` bytes of the buffer `appid` shared with
+    /// `target` for that process.
+    fn send_message(&self, appid: AppId, target: AppId, len: usize) -> ReturnCode {
+        if len > MAX_MESSAGE_LEN {
+            return ReturnCode::ESIZE;
+        }
+        if target == appid {
+            return ReturnCode::EINVAL;
+        }
+
+        let mut message = Message::EMPTY;
+        message.sender = Some(appid);
+        message.len = len;
+        let copied = self
+            .data
+            .enter(appid, |data, _| {
+                match data.peer(target) {
+                    Some(&mut Peer {
+                        shared_memory: Some(ref slice),
+                        ..
+                    }) => {
+                        if slice.len() < len {
+                            return ReturnCode::ESIZE;
+                        }
+                        message.data[..len].copy_from_slice(&slice.as_ref()[..len]);
+                        ReturnCode::SUCCESS
+                    }
+                    _ => ReturnCode::EINVAL, /* Nothing shared with the target */
+                }
+            })
+            .unwrap_or(ReturnCode::EBUSY);
+        if copied != ReturnCode::SUCCESS {
+            return copied;
+        }
+
+        let queued = self
+            .data
+            .enter(target, |data, _| data.messages.push(message))
+            .unwrap_or(false);
+        if !queued {
+            return ReturnCode::ENOMEM;
+        }
+        self.deliver_message(target);
+        ReturnCode::SUCCESS
+    }
+
+    /// Copy the oldest message queued for `appid` to its receive buffer and
+    /// tell it who sent the message, unless the buffer still holds a message
+    /// it did not release.
+    fn deliver_message(&self, appid: AppId) {
+        self.data
+            .enter(appid, |data, _| {
+                let data: &mut IPCData = data;
+                if data.message_held {
+                    return;
+                }
+                let message = match data.messages.peek() {
+                    Some(message) => *message,
+                    None => return,
+                };
+                let sender = match message.sender {
+                    Some(sender) => sender,
+                    None => return,
+                };
+                match (data.receive_buffer.as_mut(), data.message_callback.as_mut()) {
+                    (Some(buffer), Some(callback)) => {
+                        // A message longer than the buffer is truncated, but
+                        // the upcall still reports its full length.
+                        let copy_len = ::core::cmp::min(message.len, buffer.len());
+                        buffer.as_mut()[..copy_len].copy_from_slice(&message.data[..copy_len]);
+                        callback.schedule(sender.idx() + 1, message.len, 0);
+                    }
+                    _ => return,
+                }
+                data.messages.pop();
+                data.message_held = true;
+            })
+            .unwrap_or(());
+    }
+
+    /// The `AppId` of the process with ID `target_id`, the number IPC uses
+    /// for processes.
+    fn target(&self, target_id: usize) -> Option<AppId> {
+        let procs = unsafe { &mut process::PROCS };
+        if target_id == 0 || target_id > procs.len() {
+            return None;
+        }
+        procs[target_id - 1]
+            .as_ref()
+            .map(|target| target.appid(target_id - 1))
+    }
 }
 
 impl Driver for IPC {
@@ -93,14 +490,32 @@ impl Driver for IPC {
         app_id: AppId,
     ) -> ReturnCode {
         match subscribe_num {
+            // subscribe(MESSAGES)
+            //
+            // Register the callback that is called when a message is copied
+            // to the receive buffer. Its arguments are the ID of the sender
+            // and the length of the message.
+            MESSAGES => {
+                let res = self
+                    .data
+                    .enter(app_id, |data, _| {
+                        data.message_callback = callback;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+                self.deliver_message(app_id);
+                res
+            }
+
             // subscribe(0)
             //
             // Subscribe with subscribe_num == 0 is how a process registers
-            // itself as an IPC service. Each process can only register as a
-            // single IPC service. The identifier for the IPC service is the
-            // application name stored in the TBF header of the application.
-            // The callback that is passed to subscribe is called when another
-            // process notifies the server process.
+            // the callback of its IPC service. Each process can only provide
+            // a single IPC service. The service is identified by the name it
+            // registers, or else by the application name stored in the TBF
+            // header of the application. The callback that is passed to
+            // subscribe is called when another process notifies the server
+            // process.
             0 => self
                 .data
                 .enter(app_id, |data, _| {
@@ -117,16 +532,21 @@ impl Driver for IPC {
             // Once subscribed, the client will receive callbacks when the
             // service process calls notify_client().
             svc_id => {
-                if svc_id - 1 >= 8 {
-                    ReturnCode::EINVAL /* Maximum of 8 IPC's exceeded */
-                } else {
-                    self.data
-                        .enter(app_id, |data, _| {
-                            data.client_callbacks[svc_id - 1] = callback;
-                            ReturnCode::SUCCESS
-                        })
-                        .unwrap_or(ReturnCode::EBUSY)
-                }
+                let service = match self.target(svc_id) {
+                    Some(service) => service,
+                    None => return ReturnCode::EINVAL, /* Service does not exist */
+                };
+                self.data
+                    .enter(app_id, |data, allocator| {
+                        match data.peer_or_alloc(service, allocator) {
+                            Some(peer) => {
+                                peer.client_callback = callback;
+                                ReturnCode::SUCCESS
+                            }
+                            None => ReturnCode::ENOMEM,
+                        }
+                    })
+                    .unwrap_or(ReturnCode::EBUSY)
             }
         }
     }
@@ -136,16 +556,90 @@ impl Driver for IPC {
     /// and notifying an IPC client is done by setting client_or_svc to 1.
     /// In either case, the target_id is the same number as provided in a notify
     /// callback or as returned by allow.
+    ///
+    /// Setting client_or_svc to 2 sends the first `arg` bytes of the buffer
+    /// shared with target_id as a message. This fails with ENOMEM if the
+    /// target already has too many messages waiting.
+    ///
+    /// Setting client_or_svc to 3 makes the buffer shared with target_id
+    /// read-only for target_id if `arg` is not 0, and readable and writable
+    /// if it is. This takes effect when target_id is next notified. Buffers
+    /// are readable and writable unless this is set.
+    ///
+    /// Calling command with target_id == 0 discovers the service named in the
+    /// buffer last allowed with 0, which must have the version given in
+    /// client_or_svc. It returns the ID of the service, ENODEVICE if there is
+    /// no such service, ENOSUPPORT if its version is different and ENOPERM
+    /// if it does not allow the application to use it.
+    ///
+    /// Calling command with target_id == SERVICE_NAME registers the name
+    /// allowed with SERVICE_NAME as a service with the version given in
+    /// client_or_svc. The flags in arg can be REQUIRE_PERMISSION. This fails
+    /// with EBUSY if another process registered the name.
+    ///
+    /// Calling command with target_id == MESSAGES releases the received
+    /// message, so that the next one can be copied to the receive buffer.
     fn command(
         &self,
         target_id: usize,
         client_or_svc: usize,
-        _: usize,
+        arg: usize,
         appid: AppId,
     ) -> ReturnCode {
-        let procs = unsafe { &mut process::PROCS };
-        if target_id == 0 || target_id > procs.len() {
-            return ReturnCode::EINVAL; /* Request to IPC to impossible process */
+        match target_id {
+            0 => {
+                let version = client_or_svc;
+                return self
+                    .data
+                    .enter(appid, |data, _| match data.discover_name {
+                        Some(ref name) => self.discover(appid, name.as_ref(), Some(version)),
+                        None => ReturnCode::EINVAL,
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+            }
+            SERVICE_NAME => return self.register_service(appid, client_or_svc, arg),
+            MESSAGES => {
+                let res = self
+                    .data
+                    .enter(appid, |data, _| {
+                        data.message_held = false;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+                self.deliver_message(appid);
+                return res;
+            }
+            _ => {}
+        }
+
+        let target = match self.target(target_id) {
+            Some(target) => target,
+            None => return ReturnCode::EINVAL, /* Request to IPC to unknown process */
+        };
+
+        // Services are protected from clients they do not allow. A service
+        // can always notify its clients.
+        if (client_or_svc == 0 || client_or_svc == 2) && !self.may_connect(appid, target) {
+            return ReturnCode::ENOPERM;
+        }
+
+        match client_or_svc {
+            2 => return self.send_message(appid, target, arg),
+            3 => {
+                return self
+                    .data
+                    .enter(appid, |data, allocator| {
+                        match data.peer_or_alloc(target, allocator) {
+                            Some(peer) => {
+                                peer.read_only = arg != 0;
+                                ReturnCode::SUCCESS
+                            }
+                            None => ReturnCode::ENOMEM,
+                        }
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+            }
+            _ => {}
         }
 
         let cb_type = if client_or_svc == 0 {
@@ -154,6 +648,7 @@ impl Driver for IPC {
             process::IPCType::Client
         };
 
+        let procs = unsafe { &mut process::PROCS };
         procs[target_id - 1]
             .as_mut()
             .map(|target| {
@@ -170,55 +665,92 @@ impl Driver for IPC {
     /// call. The contents of the slice should be the string name of the IPC
     /// service. If this mechanism can find that service, allow will return
     /// an ID that can be used to notify that service. Otherwise an error will
-    /// be returned.
+    /// be returned: ENODEVICE if there is no such service and ENOPERM if it
+    /// does not allow the application to use it. The slice is kept for
+    /// discovering a specific version with command.
     ///
     /// If allow is called with target_id >= 1, it is a share command where the
     /// application is explicitly sharing a slice with an IPC service (as
     /// specified by the target_id). allow() simply allows both processes to
     /// access the buffer, it does not signal the service.
+    ///
+    /// If allow is called with target_id == SERVICE_NAME, the slice is the
+    /// name to register the application's service under. Changing it
+    /// unregisters the service.
+    ///
+    /// If allow is called with target_id == ALLOWED_CLIENTS, the slice lists
+    /// the package names of the clients that may use the application's
+    /// service, separated by commas.
+    ///
+    /// If allow is called with target_id == MESSAGES, the slice is where
+    /// messages sent to the application are copied to.
     fn allow(
         &self,
         appid: AppId,
         target_id: usize,
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
-        if target_id == 0 {
-            match slice {
-                Some(slice_data) => {
-                    let procs = unsafe { &mut process::PROCS };
-                    for (i, process) in procs.iter().enumerate() {
-                        match process {
-                            Some(p) => {
-                                let s = p.package_name.as_bytes();
-                                // are slices equal?
-                                if s.len() == slice_data.len()
-                                    && s.iter().zip(slice_data.iter()).all(|(c1, c2)| c1 == c2)
-                                {
-                                    return ReturnCode::SuccessWithValue {
-                                        value: (i as usize) + 1,
-                                    };
-                                }
-                            }
-                            None => {}
-                        }
-                    }
-                }
-                None => {}
+        match target_id {
+            0 => {
+                let res = match slice {
+                    Some(ref name) => self.discover(appid, name.as_ref(), None),
+                    None => ReturnCode::EINVAL, /* AppSlice must have non-zero length */
+                };
+                self.data
+                    .enter(appid, |data, _| data.discover_name = slice)
+                    .unwrap_or(());
+                return res;
+            }
+            SERVICE_NAME => {
+                return self
+                    .data
+                    .enter(appid, |data, _| {
+                        data.service_name = slice;
+                        data.service_version = None;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+            }
+            ALLOWED_CLIENTS => {
+                return self
+                    .data
+                    .enter(appid, |data, _| {
+                        data.allowed_clients = slice;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+            }
+            MESSAGES => {
+                let res = self
+                    .data
+                    .enter(appid, |data, _| {
+                        data.receive_buffer = slice;
+                        ReturnCode::SUCCESS
+                    })
+                    .unwrap_or(ReturnCode::EBUSY);
+                self.deliver_message(appid);
+                return res;
             }
+            _ => {}
+        }
 
-            return ReturnCode::EINVAL; /* AppSlice must have non-zero length */
+        let target = match self.target(target_id) {
+            Some(target) => target,
+            None => return ReturnCode::EINVAL, /* Target process does not exist */
+        };
+        if !self.may_connect(appid, target) {
+            return ReturnCode::ENOPERM;
         }
-        return self
-            .data
-            .enter(appid, |data, _| {
-                data.shared_memory
-                    .get_mut(target_id - 1)
-                    .map(|smem| {
-                        *smem = slice;
+        self.data
+            .enter(appid, |data, allocator| {
+                match data.peer_or_alloc(target, allocator) {
+                    Some(peer) => {
+                        peer.shared_memory = slice;
                         ReturnCode::SUCCESS
-                    })
-                    .unwrap_or(ReturnCode::EINVAL) /* Target process does not exist */
+                    }
+                    None => ReturnCode::ENOMEM,
+                }
             })
-            .unwrap_or(ReturnCode::EBUSY);
+            .unwrap_or(ReturnCode::EBUSY)
     }
 }
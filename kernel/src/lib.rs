@@ -7,33 +7,46 @@
 //! Most `unsafe` code is in this kernel crate.
 
 #![feature(asm, core_intrinsics, unique, ptr_internals, const_fn)]
-#![feature(use_extern_macros, try_from, used)]
+#![feature(use_extern_macros, try_from, used, panic_info_message)]
 #![feature(in_band_lifetimes)]
 #![no_std]
 
 extern crate tock_cells;
 extern crate tock_regs;
+extern crate tock_tbf;
 
 pub use tock_regs::{register_bitfields, register_bitmasks};
 
+pub mod capabilities;
 #[macro_use]
 pub mod common;
 #[macro_use]
 pub mod debug;
 pub mod hil;
 pub mod ipc;
+pub mod power;
+pub mod scheduler;
 
 mod callback;
+mod crash;
 mod driver;
 mod grant;
+mod loader;
+mod manager;
 mod mem;
 mod memop;
 mod platform;
 mod process;
+mod restart;
 mod returncode;
 mod sched;
+#[cfg(feature = "simulator")]
+mod simulator;
 mod syscall;
 mod tbfheader;
+mod trace;
+mod verifier;
+mod watchdog;
 
 pub use callback::{AppId, Callback};
 pub use driver::Driver;
@@ -54,5 +67,18 @@ pub use process::SYSCALL_FIRED;
 // functions and types are used by board files to setup the platform and setup
 // processes.
 pub mod procs {
-    pub use process::{load_processes, FaultResponse, Process};
+    pub use loader::{LoadError, ProcessLoader};
+    pub use manager::{ManagementError, ProcessInfo, ProcessManager};
+    pub use process::{load_processes, FaultResponse, FunctionCall, Process, State};
+    pub use restart::{AlarmRestartTimer, RestartTimer};
+    #[cfg(feature = "simulator")]
+    pub use simulator::Simulator;
+    pub use syscall::Syscall;
+    pub use tbfheader::{DefaultPermissions, TbfParseError};
+    pub use trace::{SyscallRecord, SyscallTrace, TraceClock};
+    pub use verifier::{
+        AppVerifier, CredentialCheck, TbfFooterV2Credentials, TbfFooterV2CredentialsType,
+        VerificationError,
+    };
+    pub use watchdog::{last_running_process, KernelWatchdog};
 }
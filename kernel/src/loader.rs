@@ -0,0 +1,248 @@
+//! Loading and unloading processes while the kernel is running.
+//!
+//! `procs::load_processes` starts the apps that are in flash at boot. A
+//! `ProcessLoader` lets the kernel start an app that was written to flash
+//! later, for example by a field update, and stop and unload a process so
+//! that its flash can be rewritten and its RAM reused.
+//!
+//! The loader only keeps the bounds of the app flash and RAM regions. Which
+//! parts of them are in use is found from the processes in `PROCS`, so apps
+//! loaded at boot and apps loaded later share the same regions.
+//!
+//! ```rust
+//! let process_loader = static_init!(
+//!     kernel::procs::ProcessLoader,
+//!     kernel::procs::ProcessLoader::new(
+//!         board_kernel,
+//!         &_sapps as *const u8,
+//!         &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+//!         APP_MEMORY.as_mut_ptr(),
+//!         APP_MEMORY.len(),
+//!         FAULT_RESPONSE,
+//!         None,
+//!         &process_management_capability,
+//!     )
+//! );
+//! ```
+
+use core::slice;
+
+use callback::AppId;
+use capabilities::ProcessManagementCapability;
+use process::{self, FaultResponse, Process};
+use returncode::ReturnCode;
+use sched::Kernel;
+use tbfheader::{self, TbfParseError};
+use verifier::{self, AppVerifier, VerificationError};
+
+/// Reasons a process could not be loaded or unloaded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    /// The app is not inside the app flash region, or overlaps the flash of a
+    /// process that is already loaded.
+    InvalidAddress,
+    /// The TBF header of the app could not be parsed.
+    InvalidHeader(TbfParseError),
+    /// The TBF is padding or a disabled app.
+    NotAnApp,
+    /// The entry point of the app is not a Thumb address.
+    InvalidEntryPoint,
+    /// The board's verifier did not accept the app.
+    NotVerified(VerificationError),
+    /// Every slot in the process array is in use.
+    NoFreeSlot,
+    /// There is no free RAM large enough for the app.
+    OutOfMemory,
+    /// There is no process at the given index.
+    NoSuchProcess,
+}
+
+impl From<LoadError> for ReturnCode {
+    fn from(err: LoadError) -> ReturnCode {
+        match err {
+            LoadError::InvalidAddress => ReturnCode::EINVAL,
+            LoadError::InvalidHeader(_) => ReturnCode::EINVAL,
+            LoadError::NotAnApp => ReturnCode::EINVAL,
+            LoadError::InvalidEntryPoint => ReturnCode::EINVAL,
+            LoadError::NotVerified(_) => ReturnCode::FAIL,
+            LoadError::NoFreeSlot => ReturnCode::EBUSY,
+            LoadError::OutOfMemory => ReturnCode::ENOMEM,
+            LoadError::NoSuchProcess => ReturnCode::EINVAL,
+        }
+    }
+}
+
+/// The loader can only be created with a `ProcessManagementCapability`, so it
+/// has one to create processes with.
+struct LoaderCapability;
+unsafe impl ProcessManagementCapability for LoaderCapability {}
+
+/// Kernel interface for starting and stopping processes at runtime.
+pub struct ProcessLoader {
+    kernel: &'static Kernel,
+    flash_start: usize,
+    flash_end: usize,
+    memory_start: usize,
+    memory_end: usize,
+    fault_response: FaultResponse,
+    app_verifier: Option<&'static AppVerifier>,
+}
+
+impl ProcessLoader {
+    /// Create a loader for apps in the `flash_len` bytes of flash starting at
+    /// `flash_start`, giving them RAM from the `memory_len` bytes starting at
+    /// `memory_start`.
+    ///
+    /// This is unsafe because the loader hands out the RAM region to processes,
+    /// so it must be the same region passed to `load_processes` and must not
+    /// be used for anything else.
+    pub unsafe fn new(
+        kernel: &'static Kernel,
+        flash_start: *const u8,
+        flash_len: usize,
+        memory_start: *mut u8,
+        memory_len: usize,
+        fault_response: FaultResponse,
+        app_verifier: Option<&'static AppVerifier>,
+        _capability: &ProcessManagementCapability,
+    ) -> ProcessLoader {
+        ProcessLoader {
+            kernel: kernel,
+            flash_start: flash_start as usize,
+            flash_end: flash_start as usize + flash_len,
+            memory_start: memory_start as usize,
+            memory_end: memory_start as usize + memory_len,
+            fault_response: fault_response,
+            app_verifier: app_verifier,
+        }
+    }
+
+    /// Parse the TBF at `app_address` in flash, give it RAM and start it in a
+    /// free slot of the process array.
+    ///
+    /// Returns the `AppId` of the new process.
+    pub fn load(&self, app_address: usize) -> Result<AppId, LoadError> {
+        let procs = unsafe { &mut process::PROCS };
+
+        // Check that the whole app is in the app flash region before parsing
+        // its header, since the parser is given all of it.
+        if app_address < self.flash_start
+            || app_address >= self.flash_end
+            || self.flash_end - app_address < tbfheader::TBF_LENGTHS_SIZE
+        {
+            return Err(LoadError::InvalidAddress);
+        }
+        let total_size = unsafe { tbfheader::parse_tbf_total_size(app_address as *const u8) }
+            .map_err(LoadError::InvalidHeader)?;
+        let app_end = app_address + total_size as usize;
+        if app_end > self.flash_end {
+            return Err(LoadError::InvalidAddress);
+        }
+        let overlaps_process = procs.iter().any(|p| {
+            p.as_ref().map_or(false, |p| {
+                app_address < p.flash_end() as usize && app_end > p.flash_start() as usize
+            })
+        });
+        if overlaps_process {
+            return Err(LoadError::InvalidAddress);
+        }
+
+        let tbf_header =
+            unsafe { tbfheader::parse_and_validate_tbf_header(app_address as *const u8) }
+                .map_err(LoadError::InvalidHeader)?;
+        if !tbf_header.is_app() || !tbf_header.enabled() {
+            return Err(LoadError::NotAnApp);
+        }
+
+        // `Process::create` panics on an invalid entry point, which is
+        // reasonable for apps installed with the kernel but not for one loaded
+        // at runtime.
+        if (app_address + tbf_header.get_init_function_offset() as usize) & 0x1 != 1 {
+            return Err(LoadError::InvalidEntryPoint);
+        }
+
+        if let Some(app_verifier) = self.app_verifier {
+            let app =
+                unsafe { slice::from_raw_parts(app_address as *const u8, app_end - app_address) };
+            verifier::verify_app(app_verifier, &tbf_header, app).map_err(LoadError::NotVerified)?;
+        }
+
+        let slot = procs
+            .iter()
+            .position(|p| p.is_none())
+            .ok_or(LoadError::NoFreeSlot)?;
+
+        let ram_size = unsafe { Process::required_ram_size(&tbf_header) };
+        let ram_start = self
+            .find_free_memory(ram_size)
+            .ok_or(LoadError::OutOfMemory)?;
+
+        // The app was already verified, so do not verify it again.
+        let created = unsafe {
+            Process::create(
+                self.kernel,
+                app_address as *const u8,
+                ram_start as *mut u8,
+                ram_size,
+                self.fault_response,
+                None,
+                &LoaderCapability,
+            )
+        };
+        match created {
+            Ok((Some(process), _, _)) => {
+                let appid = process.appid(slot);
+                procs[slot] = Some(process);
+                Ok(appid)
+            }
+            Ok((None, _, _)) => Err(LoadError::NotAnApp),
+            Err(err) => Err(LoadError::InvalidHeader(err)),
+        }
+    }
+
+    /// Stop the process at index `app_idx` of the process array and release
+    /// its slot and its RAM.
+    ///
+    /// Must not be called for the process that is currently running, for
+    /// example from a system call the process itself made.
+    pub fn unload(&self, app_idx: usize) -> Result<(), LoadError> {
+        let procs = unsafe { &mut process::PROCS };
+        let (mem_start, mem_end) = match procs.get_mut(app_idx) {
+            Some(Some(process)) => {
+                process.terminate();
+                (process.mem_start(), process.mem_end())
+            }
+            _ => return Err(LoadError::NoSuchProcess),
+        };
+
+        // Other processes must not keep access to memory that may be given to
+        // the next process loaded.
+        for process in procs.iter() {
+            process
+                .as_ref()
+                .map(|process| process.revoke_mpu_regions(mem_start, mem_end));
+        }
+
+        procs[app_idx] = None;
+        Ok(())
+    }
+
+    /// Find `size` bytes of RAM in the app memory region that no process is
+    /// using, aligned to `size` so that the MPU can protect it.
+    fn find_free_memory(&self, size: usize) -> Option<usize> {
+        let procs = unsafe { &process::PROCS };
+        let align_up = |addr: usize| (addr + size - 1) / size * size;
+
+        let mut candidate = align_up(self.memory_start);
+        while candidate + size <= self.memory_end {
+            let overlapping = procs.iter().filter_map(|p| p.as_ref()).find(|p| {
+                candidate < p.mem_end() as usize && candidate + size > p.mem_start() as usize
+            });
+            match overlapping {
+                Some(process) => candidate = align_up(process.mem_end() as usize),
+                None => return Some(candidate),
+            }
+        }
+        None
+    }
+}
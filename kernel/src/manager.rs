@@ -0,0 +1,192 @@
+//! Inspecting and controlling processes while the kernel is running.
+//!
+//! A `ProcessManager` lets a capsule list the processes in `PROCS` and stop,
+//! start and restart them, for example on behalf of a supervisor app or an
+//! operator on a debug console.
+//!
+//! ```rust
+//! let process_manager = static_init!(
+//!     kernel::procs::ProcessManager,
+//!     kernel::procs::ProcessManager::new(&process_management_capability)
+//! );
+//! ```
+
+use core::fmt::Write;
+
+use capabilities::ProcessManagementCapability;
+use process::{self, Process, State};
+use returncode::ReturnCode;
+
+/// Reasons a process could not be controlled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ManagementError {
+    /// There is no process at the given index.
+    NoSuchProcess,
+    /// The process is already in the requested state.
+    AlreadyInState,
+}
+
+impl From<ManagementError> for ReturnCode {
+    fn from(err: ManagementError) -> ReturnCode {
+        match err {
+            ManagementError::NoSuchProcess => ReturnCode::EINVAL,
+            ManagementError::AlreadyInState => ReturnCode::EALREADY,
+        }
+    }
+}
+
+/// A snapshot of the state of one process.
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessInfo {
+    /// The package name from the TBF header.
+    pub name: &'static str,
+    pub state: State,
+    /// How many times the process was restarted.
+    pub restart_count: usize,
+    /// How many syscalls the process made since it last started.
+    pub syscall_count: usize,
+    /// How many bytes of RAM the process uses, including grants.
+    pub memory_used: usize,
+    /// How many bytes of RAM the process was given.
+    pub memory_size: usize,
+    /// How long the process has run since it last started, in microseconds.
+    pub cpu_time_us: u64,
+    /// How many times an interrupt took the CPU from the process before it
+    /// made a syscall or used up its timeslice, since it last started.
+    pub preemption_count: usize,
+    /// How many times the process used up its timeslice since it last
+    /// started.
+    pub timeslice_expiration_count: usize,
+    /// The energy the process used since it last started, as estimated by
+    /// the board's `PowerModel`, or `0` without one.
+    pub energy: u64,
+}
+
+/// Kernel interface for listing and controlling processes.
+pub struct ProcessManager {
+    _private: (),
+}
+
+impl ProcessManager {
+    /// Whoever holds the manager can stop any process, so boards must only
+    /// give it to trusted capsules.
+    pub fn new(_capability: &ProcessManagementCapability) -> ProcessManager {
+        ProcessManager { _private: () }
+    }
+
+    /// How many slots the process array has. Indices of processes are less
+    /// than this.
+    pub fn number_of_slots(&self) -> usize {
+        unsafe { process::PROCS.len() }
+    }
+
+    /// Information about the process at index `app_idx`, if there is one.
+    pub fn info(&self, app_idx: usize) -> Option<ProcessInfo> {
+        let info = self.with_process(app_idx, |process| ProcessInfo {
+            name: process.package_name,
+            state: process.current_state(),
+            restart_count: process.restart_count(),
+            syscall_count: process.syscall_count(),
+            memory_used: process.memory_used(),
+            memory_size: process.memory_size(),
+            cpu_time_us: process.cpu_time_us(),
+            preemption_count: process.preemption_count(),
+            timeslice_expiration_count: process.timeslice_expiration_count(),
+            energy: process.energy(),
+        });
+        info.ok()
+    }
+
+    /// The index of the process with the package name `name`.
+    pub fn find_by_name(&self, name: &str) -> Option<usize> {
+        let procs = unsafe { &process::PROCS };
+        procs.iter().position(|p| {
+            p.as_ref()
+                .map_or(false, |process| process.package_name == name)
+        })
+    }
+
+    /// Stop the process at index `app_idx`. It keeps its slot and memory and
+    /// can be started again with `start`.
+    ///
+    /// Must not be called for the process that is currently running.
+    pub fn stop(&self, app_idx: usize) -> Result<(), ManagementError> {
+        self.with_process(app_idx, |process| {
+            if process.current_state() == State::Stopped {
+                Err(ManagementError::AlreadyInState)
+            } else {
+                process.terminate();
+                Ok(())
+            }
+        })?
+    }
+
+    /// Start a stopped or faulted process at index `app_idx` over from its
+    /// entry point.
+    pub fn start(&self, app_idx: usize) -> Result<(), ManagementError> {
+        self.with_process(app_idx, |process| match process.current_state() {
+            State::Stopped | State::Fault => {
+                process.terminate();
+                unsafe { process.restart() };
+                Ok(())
+            }
+            State::Running | State::Yielded => Err(ManagementError::AlreadyInState),
+        })?
+    }
+
+    /// Stop the process at index `app_idx`, whatever its state, and start it
+    /// over from its entry point.
+    ///
+    /// Must not be called for the process that is currently running.
+    pub fn restart(&self, app_idx: usize) -> Result<(), ManagementError> {
+        self.with_process(app_idx, |process| {
+            process.terminate();
+            unsafe { process.restart() };
+        })
+    }
+
+    /// Make the process at index `app_idx` fault, as if it had accessed
+    /// memory it may not. The kernel then handles it with its fault response,
+    /// which may panic the kernel.
+    ///
+    /// Must not be called for the process that is currently running.
+    pub fn fault(&self, app_idx: usize) -> Result<(), ManagementError> {
+        self.with_process(app_idx, |process| match process.current_state() {
+            State::Running | State::Yielded => {
+                unsafe { process.fault_state() };
+                Ok(())
+            }
+            State::Stopped | State::Fault => Err(ManagementError::AlreadyInState),
+        })?
+    }
+
+    /// Print the state of the process at index `app_idx`, its memory layout
+    /// and its registers, as the kernel does for each process when it panics.
+    pub fn write_statistics<W: Write>(
+        &self,
+        app_idx: usize,
+        writer: &mut W,
+    ) -> Result<(), ManagementError> {
+        self.with_process(app_idx, |process| unsafe { process.statistics_str(writer) })
+    }
+
+    /// Print the cause of the last hardware fault, as the kernel does when it
+    /// panics.
+    pub fn write_fault_info<W: Write>(&self, writer: &mut W) {
+        let procs = unsafe { &mut process::PROCS };
+        for process in procs.iter_mut().filter_map(|p| p.as_mut()).take(1) {
+            unsafe { process.fault_str(writer) };
+        }
+    }
+
+    fn with_process<F, R>(&self, app_idx: usize, fun: F) -> Result<R, ManagementError>
+    where
+        F: FnOnce(&mut Process<'static>) -> R,
+    {
+        let procs = unsafe { &mut process::PROCS };
+        match procs.get_mut(app_idx) {
+            Some(Some(process)) => Ok(fun(process)),
+            _ => Err(ManagementError::NoSuchProcess),
+        }
+    }
+}
@@ -6,6 +6,7 @@ use core::ptr::Unique;
 use core::slice;
 
 use callback::AppId;
+use platform::mpu;
 use process;
 
 #[derive(Debug)]
@@ -47,7 +48,7 @@ impl<L, T> Drop for AppPtr<L, T> {
     fn drop(&mut self) {
         unsafe {
             let ps = &mut process::PROCS;
-            if ps.len() > self.process.idx() {
+            if self.process.is_valid() {
                 ps[self.process.idx()]
                     .as_mut()
                     .map(|process| process.free(self.ptr.as_mut()));
@@ -56,6 +57,10 @@ impl<L, T> Drop for AppPtr<L, T> {
     }
 }
 
+/// Memory of a process shared with the kernel.
+///
+/// Once the process is restarted or unloaded the memory may belong to another
+/// process, so the slice is then empty.
 pub struct AppSlice<L, T> {
     ptr: AppPtr<L, T>,
     len: usize,
@@ -72,19 +77,37 @@ impl<L, T> AppSlice<L, T> {
     }
 
     pub fn len(&self) -> usize {
-        self.len
+        if self.ptr.process.is_valid() {
+            self.len
+        } else {
+            0
+        }
     }
 
     pub fn ptr(&self) -> *const T {
         self.ptr.ptr.as_ptr()
     }
 
+    /// Let the process `appid` read and write this memory.
     pub unsafe fn expose_to(&self, appid: AppId) -> bool {
+        self.expose_with(appid, mpu::AccessPermission::ReadWrite)
+    }
+
+    /// Let the process `appid` read, but not write, this memory. The kernel
+    /// can still write it while the region is loaded.
+    pub unsafe fn expose_read_only_to(&self, appid: AppId) -> bool {
+        self.expose_with(appid, mpu::AccessPermission::UnprivilegedReadOnly)
+    }
+
+    unsafe fn expose_with(&self, appid: AppId, access: mpu::AccessPermission) -> bool {
         let ps = &mut process::PROCS;
-        if appid.idx() != self.ptr.process.idx() && ps.len() > appid.idx() {
+        if appid.idx() != self.ptr.process.idx() && appid.is_valid() && self.ptr.process.is_valid()
+        {
             ps[appid.idx()]
                 .as_ref()
-                .map(|process| process.add_mpu_region(self.ptr() as *const u8, self.len() as u32))
+                .map(|process| {
+                    process.add_mpu_region(self.ptr() as *const u8, self.len() as u32, access)
+                })
                 .unwrap_or(false)
         } else {
             false
@@ -110,12 +133,13 @@ impl<L, T> AppSlice<L, T> {
 
 impl<L, T> AsRef<[T]> for AppSlice<L, T> {
     fn as_ref(&self) -> &[T] {
-        unsafe { slice::from_raw_parts(self.ptr.ptr.as_ref(), self.len) }
+        unsafe { slice::from_raw_parts(self.ptr.ptr.as_ref(), self.len()) }
     }
 }
 
 impl<L, T> AsMut<[T]> for AppSlice<L, T> {
     fn as_mut(&mut self) -> &mut [T] {
-        unsafe { slice::from_raw_parts_mut(self.ptr.ptr.as_mut(), self.len) }
+        let len = self.len();
+        unsafe { slice::from_raw_parts_mut(self.ptr.ptr.as_mut(), len) }
     }
 }
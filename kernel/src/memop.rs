@@ -36,10 +36,16 @@ use returncode::ReturnCode;
 ///   where the app has put the start of its heap. This is not strictly
 ///   necessary for correct operation, but allows for better debugging if the
 ///   app crashes.
-pub fn memop(process: &mut Process) -> ReturnCode {
-    let op_type = process.r0();
-    let r1 = process.r1();
-
+/// - `12`: Get how many bytes of its stack the app has ever used. Returns
+///   FAIL if the app has not told the kernel where its stack starts with
+///   operation 10.
+/// - `13`: Get how many bytes of grant memory capsules have allocated for the
+///   app.
+/// - `14`: Get how many bytes of grant memory the grant numbered r1 has
+///   allocated for the app. Returns FAIL if there is no such grant.
+///
+/// The process passes `op_type` in r0 and its argument, `r1`, in r1.
+pub fn memop(process: &mut Process, op_type: usize, r1: usize) -> ReturnCode {
     match op_type {
         // Op Type 0: BRK
         0 /* BRK */ => {
@@ -111,6 +117,19 @@ pub fn memop(process: &mut Process) -> ReturnCode {
             ReturnCode::SUCCESS
         }
 
+        // Op Type 12: Stack high-water mark
+        12 => process.stack_high_water_mark()
+            .map(|used| ReturnCode::SuccessWithValue { value: used })
+            .unwrap_or(ReturnCode::FAIL),
+
+        // Op Type 13: Grant memory used by all grants
+        13 => ReturnCode::SuccessWithValue { value: process.total_grant_memory_used() },
+
+        // Op Type 14: Grant memory used by the grant numbered r1
+        14 => process.grant_memory_used(r1)
+            .map(|used| ReturnCode::SuccessWithValue { value: used })
+            .unwrap_or(ReturnCode::FAIL),
+
         _ => ReturnCode::ENOSUPPORT,
     }
 }
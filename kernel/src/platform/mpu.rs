@@ -1,6 +1,6 @@
 //! Interface for configuring the Memory Protection Unit.
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AccessPermission {
     //                                 Privileged  Unprivileged
     //                                 Access      Access
@@ -21,6 +21,13 @@ pub trait SysTick {
     /// Returns if there is at least `us` microseconds left
     fn greater_than(&self, us: u32) -> bool;
 
+    /// Returns how many microseconds are left before the timer next counts
+    /// down to zero.
+    ///
+    /// The kernel compares values before and after running a process to
+    /// measure how long it ran.
+    fn get_value(&self) -> u32;
+
     /// Returns true if the timer has expired
     fn overflowed(&self) -> bool;
 
@@ -56,4 +63,8 @@ impl SysTick for () {
     fn greater_than(&self, _: u32) -> bool {
         true
     }
+
+    fn get_value(&self) -> u32 {
+        0
+    }
 }
@@ -0,0 +1,146 @@
+//! Choosing how deeply the chip may sleep.
+//!
+//! Most chips have several sleep modes. The deeper ones save more energy, but
+//! stop clocks that some peripherals need to finish what they are doing. Only
+//! the peripheral, or the capsule using it, knows whether it can tolerate
+//! that, so each of them holds a `SleepVote` for the deepest `SleepState` it
+//! can tolerate at the moment, and registers it once:
+//!
+//! ```rust
+//! static mut RADIO_VOTE: SleepVote = SleepVote::new("radio");
+//!
+//! kernel::power::register(&RADIO_VOTE);
+//! // While a packet is in flight:
+//! RADIO_VOTE.set(SleepState::Sleep);
+//! ```
+//!
+//! Before sleeping, `Chip::sleep` calls `choose_sleep_state` and enters the
+//! deepest sleep mode every vote allows, if its own checks of the hardware
+//! allow it too. A vote that keeps the chip from sleeping deeply counts how
+//! often it did, so that a board can find out which peripheral costs it
+//! energy:
+//!
+//! ```rust
+//! for vote in kernel::power::votes() {
+//!     debug!("{}: blocked deep sleep {} times", vote.name(), vote.blocked_count());
+//! }
+//! ```
+//!
+//! Chips that have a single sleep mode ignore the votes.
+//!
+//! A board can also describe the power the chip draws with a `PowerModel`,
+//! which the kernel uses to estimate how much energy each process uses.
+
+use core::cell::Cell;
+use core::ptr;
+
+use common::list::{List, ListLink, ListNode};
+
+/// How deeply the chip sleeps, from the lightest to the deepest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepState {
+    /// Only the CPU stops; peripheral clocks keep running.
+    Sleep,
+    /// Peripheral clocks stop too, and only peripherals that can run without
+    /// them, or that can wake the chip, keep working.
+    DeepSleep,
+}
+
+/// The deepest sleep state one peripheral or capsule tolerates.
+pub struct SleepVote {
+    name: &'static str,
+    state: Cell<SleepState>,
+    /// How many times this vote kept the chip from sleeping deeply.
+    blocked: Cell<usize>,
+    next: ListLink<'static, SleepVote>,
+}
+
+impl ListNode<'static, SleepVote> for SleepVote {
+    fn next(&'static self) -> &'static ListLink<'static, SleepVote> {
+        &self.next
+    }
+}
+
+impl SleepVote {
+    /// A vote named `name` that allows the deepest sleep state.
+    pub const fn new(name: &'static str) -> SleepVote {
+        SleepVote {
+            name: name,
+            state: Cell::new(SleepState::DeepSleep),
+            blocked: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Allow the chip to sleep at most as deeply as `state`.
+    pub fn set(&self, state: SleepState) {
+        self.state.set(state);
+    }
+
+    pub fn state(&self) -> SleepState {
+        self.state.get()
+    }
+
+    /// How many times this vote kept the chip from sleeping deeply.
+    pub fn blocked_count(&self) -> usize {
+        self.blocked.get()
+    }
+}
+
+static mut VOTES: List<'static, SleepVote> = List::new();
+
+/// Take `vote` into account whenever the chip sleeps. Registering the same
+/// vote again has no effect.
+pub fn register(vote: &'static SleepVote) {
+    unsafe {
+        if !VOTES.iter().any(|registered| ptr::eq(registered, vote)) {
+            VOTES.push_head(vote);
+        }
+    }
+}
+
+/// Every registered vote.
+pub fn votes() -> impl Iterator<Item = &'static SleepVote> {
+    unsafe { VOTES.iter() }
+}
+
+/// The deepest sleep state every vote allows.
+pub fn deepest_sleep_state() -> SleepState {
+    votes()
+        .map(|vote| vote.state())
+        .min()
+        .unwrap_or(SleepState::DeepSleep)
+}
+
+/// The deepest sleep state every vote allows, for `Chip::sleep` to call
+/// right before the chip sleeps. Counts the sleep against every vote that
+/// keeps the chip from sleeping deeply.
+pub fn choose_sleep_state() -> SleepState {
+    let state = deepest_sleep_state();
+    for vote in votes() {
+        if vote.state() < SleepState::DeepSleep {
+            vote.blocked.set(vote.blocked.get() + 1);
+        }
+    }
+    state
+}
+
+/// Estimates the power the chip draws, so that the kernel can charge each
+/// process for the energy used while it runs.
+///
+/// A board can give the kernel one with `Kernel::set_power_model`. The kernel
+/// asks it for the power drawn right before and right after running a
+/// process, and adds the time the process ran, weighted by their average, to
+/// the energy of the process. A model that adds the power of the peripherals
+/// that are active, for example from the chip's clock masks, makes processes
+/// that keep peripherals busy pay for them. `sam4l::power_model` is such a
+/// model.
+pub trait PowerModel {
+    /// The power the chip draws right now, in a unit the board chooses, for
+    /// example microwatts.
+    fn active_power(&self) -> u32;
+}
@@ -1,20 +1,24 @@
 //! Support for creating and running userspace applications.
 
 use callback::AppId;
+use capabilities::ProcessManagementCapability;
 use common::{Queue, RingBuffer};
 
 use core::cell::Cell;
 use core::fmt::Write;
 use core::ptr::{read_volatile, write, write_volatile};
-use core::{mem, ptr, slice, str};
+use core::{cmp, mem, ptr, slice, str};
+use crash;
 use grant;
 
 use common::math;
 use platform::mpu;
 use returncode::ReturnCode;
 use sched::Kernel;
-use syscall::Syscall;
-use tbfheader;
+use scheduler;
+use syscall::{ExitType, Syscall};
+use tbfheader::{self, TbfHeader, TbfParseError};
+use verifier::{self, AppVerifier};
 
 /// This is used in the hardfault handler.
 #[no_mangle]
@@ -39,6 +43,28 @@ extern "C" {
 
 pub static mut PROCS: &'static mut [Option<&mut Process<'static>>] = &mut [];
 
+/// Generation given to the next process that is created or restarted.
+/// Generation `0` is left for the `AppId`s of the kernel.
+static mut NEXT_GENERATION: usize = 1;
+
+fn next_generation() -> usize {
+    unsafe {
+        let generation = NEXT_GENERATION;
+        NEXT_GENERATION = match generation.wrapping_add(1) {
+            0 => 1,
+            next => next,
+        };
+        generation
+    }
+}
+
+/// Number of tasks that can be queued for a process.
+const CALLBACK_LEN: usize = 10;
+
+/// Pattern written to unused process memory, so that the kernel can tell how
+/// deep the stack of a process has ever grown.
+const STACK_PAINT: u32 = 0xDEADC0DE;
+
 /// Helper function to load processes from flash into an array of active
 /// processes. This is the default template for loading processes, but a board
 /// is able to create its own `load_processes()` function and use that instead.
@@ -48,35 +74,49 @@ pub static mut PROCS: &'static mut [Option<&mut Process<'static>>] = &mut [];
 /// `app_memory` buffer until either the memory is exhausted or the allocated
 /// number of processes are created, with process structures placed in the
 /// provided array. How process faults are handled by the kernel is also
-/// selected.
+/// selected. If an `app_verifier` is given, only apps whose credentials it
+/// accepts are started.
 pub unsafe fn load_processes(
     kernel: &'static Kernel,
     start_of_flash: *const u8,
     app_memory: &mut [u8],
     procs: &mut [Option<&mut Process<'static>>],
     fault_response: FaultResponse,
+    app_verifier: Option<&AppVerifier>,
+    capability: &ProcessManagementCapability,
 ) {
     let mut apps_in_flash_ptr = start_of_flash;
     let mut app_memory_ptr = app_memory.as_mut_ptr();
     let mut app_memory_size = app_memory.len();
     for i in 0..procs.len() {
-        let (process, flash_offset, memory_offset) = Process::create(
+        let created = Process::create(
             kernel,
             apps_in_flash_ptr,
             app_memory_ptr,
             app_memory_size,
             fault_response,
+            app_verifier,
+            capability,
         );
-
-        if process.is_none() {
-            // We did not get a valid process, but we may have gotten a disabled
-            // process or padding. Therefore we want to skip this chunk of flash
-            // and see if there is a valid app there. However, if we cannot
-            // advance the flash pointer, then we are done.
-            if flash_offset == 0 && memory_offset == 0 {
+        let (process, flash_offset, memory_offset) = match created {
+            Ok(created) => created,
+            Err(err) => {
+                // Without a valid header we cannot know where the next app
+                // starts, so we are done. Erased or zeroed flash after the
+                // last app is the normal way for the list of apps to end, so
+                // only report other problems.
+                match err {
+                    TbfParseError::UnknownVersion(0) | TbfParseError::UnknownVersion(0xFFFF) => {}
+                    _ => debug!("Invalid TBF header at {:?}: {:?}", apps_in_flash_ptr, err),
+                }
                 break;
             }
-        } else {
+        };
+
+        // If we did not get a valid process, we may have gotten a disabled
+        // process or padding. Therefore we want to skip this chunk of flash and
+        // see if there is a valid app there.
+        if process.is_some() {
             procs[i] = process;
         }
 
@@ -94,8 +134,23 @@ pub fn schedule(callback: FunctionCall, appid: AppId) -> bool {
     }
 
     match procs[idx] {
+        Some(ref mut p) if p.generation == appid.generation() => p.schedule(callback),
+        _ => false,
+    }
+}
+
+/// Whether `appid` still refers to the process it was given to. It does not
+/// once that process has been restarted or unloaded, even if another process
+/// now has the same index.
+pub fn is_current(appid: AppId) -> bool {
+    let procs = unsafe { &mut PROCS };
+    if appid.idx() >= procs.len() {
+        return false;
+    }
+
+    match procs[appid.idx()] {
         None => false,
-        Some(ref mut p) => p.schedule(callback),
+        Some(ref p) => p.generation == appid.generation(),
     }
 }
 
@@ -119,6 +174,40 @@ pub fn get_editable_flash_range(app_idx: usize) -> (usize, usize) {
     }
 }
 
+/// Restart the processes waiting out a backoff delay whose restart time is
+/// not after `now`, in the ticks of the kernel's `RestartTimer`.
+///
+/// Returns when the next waiting process is due, if any are still waiting.
+pub(crate) fn restart_due_processes(now: u32) -> Option<u32> {
+    let procs = unsafe { &mut PROCS };
+    let mut next: Option<u32> = None;
+    for process in procs.iter_mut().filter_map(|p| p.as_mut()) {
+        process.restart_at.map(|restart_at| {
+            // The timer wraps, so compare how far in the future each time is.
+            if (restart_at.wrapping_sub(now) as i32) <= 0 {
+                unsafe { process.restart() };
+            } else if next.map_or(true, |n| restart_at.wrapping_sub(now) < n.wrapping_sub(now)) {
+                next = Some(restart_at);
+            }
+        });
+    }
+    next
+}
+
+/// Whether the app's TBF header explicitly lists `driver_num` in its
+/// permissions. See `AppId::has_explicit_driver_permission`.
+pub fn has_explicit_driver_permission(app_idx: usize, driver_num: usize) -> bool {
+    let procs = unsafe { &mut PROCS };
+    if app_idx >= procs.len() {
+        return false;
+    }
+
+    match procs[app_idx] {
+        None => false,
+        Some(ref p) => p.header.has_permissions() && p.has_driver_permission(driver_num, None),
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     NoSuchApp,
@@ -140,13 +229,37 @@ impl From<Error> for ReturnCode {
 pub enum State {
     Running,
     Yielded,
+    /// The process faulted and is waiting to be restarted.
     Fault,
+    /// The process has terminated and will not run again.
+    Stopped,
 }
 
+/// How the kernel handles a process that faults.
+///
+/// Every policy that restarts processes also honors the Max Restarts TLV in
+/// the process's TBF header. A process that has used up its restarts is
+/// stopped.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FaultResponse {
+    /// Panic the kernel, printing the state of the process.
     Panic,
+    /// Stop the process and drop everything queued for it.
+    Stop,
+    /// Restart the process immediately, every time it faults.
     Restart,
+    /// Restart the process immediately, at most `max_restarts` times.
+    RestartWithLimit { max_restarts: usize },
+    /// Restart the process after a delay that doubles with every restart,
+    /// starting at `initial_delay_ms` and capped at `max_delay_ms`.
+    ///
+    /// The board must give the kernel a `RestartTimer` with
+    /// `Kernel::set_restart_timer` to wait out the delay. Without one, a
+    /// process that faults is stopped, as with `Stop`.
+    RestartWithBackoff {
+        initial_delay_ms: u32,
+        max_delay_ms: u32,
+    },
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -211,6 +324,31 @@ struct ProcessDebug {
     /// How many times this process has entered into a fault condition and the
     /// kernel has restarted it.
     restart_count: Cell<usize>,
+
+    /// The completion code the process last passed to the `EXIT` syscall.
+    completion_code: Cell<Option<usize>>,
+
+    /// How many times a capsule could not allocate grant memory for this
+    /// process.
+    grant_alloc_failures: Cell<usize>,
+
+    /// How many times the process could not grow its own memory with `brk`
+    /// or `sbrk`.
+    app_alloc_failures: Cell<usize>,
+
+    /// How long the process has run since it started, in microseconds.
+    cpu_time_us: Cell<u64>,
+
+    /// The time the process ran weighted by the power the board's
+    /// `PowerModel` estimated, in its unit of power times microseconds.
+    energy: Cell<u64>,
+
+    /// How many times an interrupt took the CPU from the process before it
+    /// made a syscall or used up its timeslice.
+    preemption_count: Cell<usize>,
+
+    /// How many times the process used up its timeslice.
+    timeslice_expiration_count: Cell<usize>,
 }
 
 pub struct Process<'a> {
@@ -264,7 +402,7 @@ pub struct Process<'a> {
     flash: &'static [u8],
 
     /// Collection of pointers to the TBF header in flash.
-    header: tbfheader::TbfHeader,
+    header: TbfHeader<'static>,
 
     /// Saved each time the app switches to the kernel.
     stored_regs: StoredRegs,
@@ -281,7 +419,28 @@ pub struct Process<'a> {
     /// How to deal with Faults occurring in the process
     fault_response: FaultResponse,
 
-    /// MPU regions are saved as a pointer-size pair.
+    /// Scheduling priority from the TBF header. Larger is higher priority.
+    priority: u32,
+
+    /// How long the process may run before being pre-empted, in microseconds.
+    timeslice_us: u32,
+
+    /// How many times the kernel may restart the process after a fault, or
+    /// `None` if the TBF header does not limit it.
+    max_restarts: Option<usize>,
+
+    /// When a process waiting out a restart backoff should be restarted, in
+    /// ticks of the kernel's `RestartTimer`.
+    restart_at: Option<u32>,
+
+    /// Which run of a process this is. It changes whenever the process is
+    /// restarted, and is never the same for two processes, so `AppId`s from
+    /// before a restart or from an unloaded process are not mistaken for ones
+    /// of the process running now.
+    generation: usize,
+
+    /// MPU regions are saved as a pointer-size pair, with the access the
+    /// process has to the region.
     ///
     /// size is encoded as X where
     /// SIZE = 2<sup>(X + 1)</sup> and X >= 4.
@@ -292,7 +451,7 @@ pub struct Process<'a> {
     ///
     /// The pointer must be aligned to the size. E.g. if the size is 32 bytes, the pointer must be
     /// 32-byte aligned.
-    mpu_regions: [Cell<(*const u8, math::PowerOfTwo)>; 5],
+    mpu_regions: [Cell<(*const u8, math::PowerOfTwo, mpu::AccessPermission)>; 5],
 
     /// Essentially a list of callbacks that want to call functions in the
     /// process.
@@ -307,9 +466,9 @@ pub struct Process<'a> {
 
 impl Process<'a> {
     pub fn schedule(&mut self, callback: FunctionCall) -> bool {
-        // If this app is in the `Fault` state then we shouldn't schedule
-        // any work for it.
-        if self.current_state() == State::Fault {
+        // If this app is in the `Fault` or `Stopped` state then we shouldn't
+        // schedule any work for it.
+        if self.current_state() == State::Fault || self.current_state() == State::Stopped {
             return false;
         }
 
@@ -329,6 +488,10 @@ impl Process<'a> {
     }
 
     pub fn schedule_ipc(&mut self, from: AppId, cb_type: IPCType) {
+        if self.current_state() == State::Fault || self.current_state() == State::Stopped {
+            return;
+        }
+
         self.kernel.increment_work();
         let ret = self.tasks.enqueue(Task::IPC((from, cb_type)));
 
@@ -345,6 +508,52 @@ impl Process<'a> {
         self.state
     }
 
+    /// Whether the process has work to do: it is either in the middle of
+    /// running or it has yielded and has tasks waiting.
+    pub fn ready(&self) -> bool {
+        match self.state {
+            State::Running => true,
+            State::Yielded => self.tasks.has_elements(),
+            State::Fault => false,
+            State::Stopped => false,
+        }
+    }
+
+    /// The scheduling priority of the process as set in its TBF header.
+    /// Larger values are higher priority.
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// How long the process may run before being pre-empted, in microseconds.
+    pub fn timeslice_us(&self) -> u32 {
+        self.timeslice_us
+    }
+
+    /// Whether the process's TBF header permits it to use the driver
+    /// `driver_num` and, for commands, `command_num` on that driver. Without
+    /// a Permissions entry, the kernel's default permissions decide.
+    pub fn has_driver_permission(&self, driver_num: usize, command_num: Option<usize>) -> bool {
+        self.header
+            .driver_permitted(driver_num, command_num, self.kernel.default_permissions())
+    }
+
+    /// Drop everything the process had queued, remove it from the kernel's
+    /// count of outstanding work and mark it stopped. Used when the process
+    /// will not continue from where it is.
+    pub(crate) fn terminate(&mut self) {
+        for _ in 0..self.tasks.len() {
+            self.kernel.decrement_work();
+        }
+        self.tasks.empty();
+
+        if self.state == State::Running {
+            self.kernel.decrement_work();
+        }
+        self.state = State::Stopped;
+        self.restart_at = None;
+    }
+
     pub fn yield_state(&mut self) {
         if self.state == State::Running {
             self.state = State::Yielded;
@@ -354,68 +563,137 @@ impl Process<'a> {
 
     pub unsafe fn fault_state(&mut self) {
         write_volatile(&mut APP_FAULT, 0);
-        self.state = State::Fault;
 
-        match self.fault_response {
-            FaultResponse::Panic => {
-                // process faulted. Panic and print status
-                panic!("Process {} had a fault", self.package_name);
+        if self.fault_response == FaultResponse::Panic {
+            // process faulted. Panic and print status
+            self.state = State::Fault;
+            crash::record_fault(self);
+            panic!("Process {} had a fault", self.package_name);
+        }
+
+        // The process will not continue from where it faulted, so remove it
+        // and its tasks from the amount of work queue.
+        self.terminate();
+
+        let restart_count = self.debug.restart_count.get();
+        let (max_restarts, delay_ms) = match self.fault_response {
+            FaultResponse::Panic | FaultResponse::Stop => return,
+            FaultResponse::Restart => (self.max_restarts, 0),
+            FaultResponse::RestartWithLimit { max_restarts } => (
+                Some(
+                    self.max_restarts
+                        .map_or(max_restarts, |max| cmp::min(max, max_restarts)),
+                ),
+                0,
+            ),
+            FaultResponse::RestartWithBackoff {
+                initial_delay_ms,
+                max_delay_ms,
+            } => {
+                let backoff = 1u32 << cmp::min(restart_count, 31);
+                let delay_ms = cmp::min(initial_delay_ms.saturating_mul(backoff), max_delay_ms);
+                (self.max_restarts, delay_ms)
             }
-            FaultResponse::Restart => {
-                // Remove the tasks that were scheduled for the app from the
-                // amount of work queue.
-                for _ in 0..self.tasks.len() {
-                    self.kernel.decrement_work();
-                }
+        };
+
+        // If the process has used up its restarts, leave it stopped so it is
+        // never scheduled again.
+        if max_restarts.map_or(false, |max| restart_count >= max) {
+            return;
+        }
 
-                // And remove those tasks
-                self.tasks.empty();
-
-                // Mark that we restarted this process.
-                self.debug
-                    .restart_count
-                    .set(self.debug.restart_count.get() + 1);
-
-                // Reset some state for the process.
-                self.debug.syscall_count.set(0);
-                self.debug.last_syscall.set(None);
-                self.debug.dropped_callback_count.set(0);
-
-                // We are going to start this process over again, so need
-                // the init_fn location.
-                let app_flash_address = self.flash_start();
-                let init_fn = app_flash_address
-                    .offset(self.header.get_init_function_offset() as isize)
-                    as usize;
-                self.yield_pc = init_fn;
-                self.psr = 0x01000000;
-                self.state = State::Yielded;
-
-                // Need to reset the grant region.
-                self.grant_ptrs_reset();
-                self.kernel_memory_break = self.original_kernel_memory_break;
-
-                // Reset other memory pointers.
-                self.app_break = self.original_app_break;
-                self.current_stack_pointer = self.original_stack_pointer;
-
-                // And queue up this app to be restarted.
-                let flash_protected_size = self.header.get_protected_size() as usize;
-                let flash_app_start = app_flash_address as usize + flash_protected_size;
-
-                self.tasks.enqueue(Task::FunctionCall(FunctionCall {
-                    pc: init_fn,
-                    r0: flash_app_start,
-                    r1: self.memory.as_ptr() as usize,
-                    r2: self.memory.len() as usize,
-                    r3: self.app_break as usize,
-                }));
-
-                self.kernel.increment_work();
+        if delay_ms == 0 {
+            self.state = State::Fault;
+            self.restart();
+            return;
+        }
+        match self.kernel.restart_timer() {
+            Some(timer) => {
+                self.state = State::Fault;
+                let restart_at = timer.now().wrapping_add(timer.ticks_from_ms(delay_ms));
+                self.restart_at = Some(restart_at);
+                timer.set_wakeup(restart_at);
             }
+            None => {
+                // Restarting right away would let a process that faults on
+                // every start keep the chip busy, so leave it stopped.
+                debug!(
+                    "Not restarting {}: no restart timer for the backoff delay",
+                    self.package_name
+                );
+            }
+        }
+    }
+
+    /// End the process at its own request with the `EXIT` syscall, recording
+    /// `completion_code`. A restarted process counts as restarted, but is not
+    /// limited by the fault response.
+    pub unsafe fn exit(&mut self, exit_type: ExitType, completion_code: usize) {
+        self.debug.completion_code.set(Some(completion_code));
+        self.terminate();
+        if exit_type == ExitType::Restart {
+            self.restart();
         }
     }
 
+    /// Start the process over from its entry point with fresh memory. The
+    /// process must have been terminated first.
+    pub(crate) unsafe fn restart(&mut self) {
+        self.restart_at = None;
+        self.generation = next_generation();
+
+        // Mark that we restarted this process.
+        self.debug
+            .restart_count
+            .set(self.debug.restart_count.get() + 1);
+
+        // Reset some state for the process.
+        self.debug.syscall_count.set(0);
+        self.debug.last_syscall.set(None);
+        self.debug.dropped_callback_count.set(0);
+        self.debug.grant_alloc_failures.set(0);
+        self.debug.app_alloc_failures.set(0);
+        self.debug.cpu_time_us.set(0);
+        self.debug.energy.set(0);
+        self.debug.preemption_count.set(0);
+        self.debug.timeslice_expiration_count.set(0);
+
+        // We are going to start this process over again, so need
+        // the init_fn location.
+        let app_flash_address = self.flash_start();
+        let init_fn =
+            app_flash_address.offset(self.header.get_init_function_offset() as isize) as usize;
+        self.yield_pc = init_fn;
+        self.psr = 0x01000000;
+        self.state = State::Yielded;
+
+        // Need to reset the grant region.
+        self.grant_ptrs_reset();
+        self.kernel_memory_break = self.original_kernel_memory_break;
+
+        // Reset other memory pointers.
+        self.app_break = self.original_app_break;
+        self.current_stack_pointer = self.original_stack_pointer;
+
+        // Repaint the memory the app will use, so that the stack high-water
+        // mark is measured for this run only.
+        self.paint_memory(self.mem_start(), self.kernel_memory_break);
+
+        // And queue up this app to be restarted.
+        let flash_protected_size = self.header.get_protected_size() as usize;
+        let flash_app_start = app_flash_address as usize + flash_protected_size;
+
+        self.tasks.enqueue(Task::FunctionCall(FunctionCall {
+            pc: init_fn,
+            r0: flash_app_start,
+            r1: self.memory.as_ptr() as usize,
+            r2: self.memory.len() as usize,
+            r3: self.app_break as usize,
+        }));
+
+        self.kernel.increment_work();
+    }
+
     pub fn dequeue_task(&mut self) -> Option<Task> {
         self.tasks.dequeue().map(|cb| {
             self.kernel.decrement_work();
@@ -447,6 +725,87 @@ impl Process<'a> {
         self.kernel_memory_break
     }
 
+    pub fn app_break(&self) -> *const u8 {
+        self.app_break
+    }
+
+    /// How many bytes of its RAM the process is using, counting both the
+    /// memory below its app break and the grants above the kernel memory
+    /// break.
+    pub fn memory_used(&self) -> usize {
+        (self.app_break as usize - self.mem_start() as usize)
+            + (self.mem_end() as usize - self.kernel_memory_break as usize)
+    }
+
+    /// How many bytes of RAM the process was given.
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// The `AppId` of this run of the process at index `app_idx`.
+    pub(crate) fn appid(&self, app_idx: usize) -> AppId {
+        AppId::new(app_idx, self.generation)
+    }
+
+    pub fn restart_count(&self) -> usize {
+        self.debug.restart_count.get()
+    }
+
+    pub fn syscall_count(&self) -> usize {
+        self.debug.syscall_count.get()
+    }
+
+    pub fn dropped_callback_count(&self) -> usize {
+        self.debug.dropped_callback_count.get()
+    }
+
+    /// How long the process has run since it started, in microseconds.
+    pub fn cpu_time_us(&self) -> u64 {
+        self.debug.cpu_time_us.get()
+    }
+
+    /// The energy the process used since it started, as estimated by the
+    /// board's `PowerModel`, or `0` without one.
+    pub fn energy(&self) -> u64 {
+        self.debug.energy.get()
+    }
+
+    pub fn preemption_count(&self) -> usize {
+        self.debug.preemption_count.get()
+    }
+
+    pub fn timeslice_expiration_count(&self) -> usize {
+        self.debug.timeslice_expiration_count.get()
+    }
+
+    /// Charge the process for running `us` microseconds while the chip drew
+    /// `power`.
+    pub(crate) fn charge_cpu_time(&self, us: u32, power: u32) {
+        self.debug
+            .cpu_time_us
+            .set(self.debug.cpu_time_us.get() + us as u64);
+        self.debug
+            .energy
+            .set(self.debug.energy.get() + us as u64 * power as u64);
+    }
+
+    pub(crate) fn incr_preemption_count(&self) {
+        self.debug
+            .preemption_count
+            .set(self.debug.preemption_count.get() + 1);
+    }
+
+    pub(crate) fn incr_timeslice_expiration_count(&self) {
+        self.debug
+            .timeslice_expiration_count
+            .set(self.debug.timeslice_expiration_count.get() + 1);
+    }
+
+    /// The completion code the process last passed to the `EXIT` syscall.
+    pub fn completion_code(&self) -> Option<usize> {
+        self.debug.completion_code.get()
+    }
+
     pub fn number_writeable_flash_regions(&self) -> usize {
         self.header.number_writeable_flash_regions()
     }
@@ -462,6 +821,44 @@ impl Process<'a> {
             // We also reset the minimum stack pointer because whatever value
             // we had could be entirely wrong by now.
             self.debug.min_stack_pointer = stack_pointer;
+
+            // The stack starts at the bottom of the app's memory, and the app
+            // ran on a temporary stack there before it moved its stack. Paint
+            // over that, up to the current stack pointer, since everything
+            // below it is unused.
+            if self.current_stack_pointer > self.mem_start()
+                && self.current_stack_pointer <= stack_pointer
+            {
+                unsafe { self.paint_memory(self.mem_start(), self.current_stack_pointer) };
+            }
+        }
+    }
+
+    /// How many bytes of its stack the process has ever used, if it told the
+    /// kernel where its stack starts.
+    ///
+    /// The stack grows down towards the start of the app's memory, which the
+    /// kernel paints with a pattern when the app starts. The high-water mark
+    /// is the deepest word that no longer holds the pattern.
+    pub fn stack_high_water_mark(&self) -> Option<usize> {
+        self.debug.app_stack_start_pointer.map(|stack_start| {
+            let start = self.mem_start() as usize;
+            let mut word = start;
+            while word + 4 <= stack_start as usize
+                && unsafe { read_volatile(word as *const u32) } == STACK_PAINT
+            {
+                word += 4;
+            }
+            stack_start as usize - cmp::min(word, stack_start as usize)
+        })
+    }
+
+    /// Fill the app memory from `start` up to `end` with `STACK_PAINT`.
+    unsafe fn paint_memory(&self, start: *const u8, end: *const u8) {
+        let mut word = (start as usize + 3) & !3;
+        while word + 4 <= end as usize {
+            write_volatile(word as *mut u32, STACK_PAINT);
+            word += 4;
         }
     }
 
@@ -546,7 +943,7 @@ impl Process<'a> {
                 region.get().0 as usize,
                 region.get().1.as_num::<u32>() as usize,
                 mpu::ExecutePermission::ExecutionPermitted,
-                mpu::AccessPermission::ReadWrite,
+                region.get().2,
             ) {
                 None => panic!(
                     "Unexpected: Infeasible MPU allocation: Num: {}, \
@@ -560,17 +957,38 @@ impl Process<'a> {
         }
     }
 
-    pub fn add_mpu_region(&self, base: *const u8, size: u32) -> bool {
+    /// Remove any regions that other processes shared with this process
+    /// through IPC and that start in `[start, end)`.
+    pub(crate) fn revoke_mpu_regions(&self, start: *const u8, end: *const u8) {
+        for region in self.mpu_regions.iter() {
+            let base = region.get().0;
+            if base >= start && base < end {
+                region.set((
+                    ptr::null(),
+                    math::PowerOfTwo::zero(),
+                    mpu::AccessPermission::NoAccess,
+                ));
+            }
+        }
+    }
+
+    /// Give the process `access` to memory of another process. Sharing the
+    /// same memory again changes the access.
+    pub fn add_mpu_region(
+        &self,
+        base: *const u8,
+        size: u32,
+        access: mpu::AccessPermission,
+    ) -> bool {
         if size >= 16 && size.count_ones() == 1 && (base as u32) % size == 0 {
             let mpu_size = math::PowerOfTwo::floor(size);
             for region in self.mpu_regions.iter() {
                 if region.get().0 == ptr::null() {
-                    region.set((base, mpu_size));
+                    region.set((base, mpu_size, access));
                     return true;
                 } else if region.get().0 == base {
-                    if region.get().1 < mpu_size {
-                        region.set((base, mpu_size));
-                    }
+                    let (_, old_size, _) = region.get();
+                    region.set((base, cmp::max(old_size, mpu_size), access));
                     return true;
                 }
             }
@@ -584,168 +1002,230 @@ impl Process<'a> {
         remaining_app_memory: *mut u8,
         remaining_app_memory_size: usize,
         fault_response: FaultResponse,
-    ) -> (Option<&'static mut Process<'a>>, usize, usize) {
-        if let Some(tbf_header) = tbfheader::parse_and_validate_tbf_header(app_flash_address) {
-            let app_flash_size = tbf_header.get_total_size() as usize;
-
-            // If this isn't an app (i.e. it is padding) or it is an app but it
-            // isn't enabled, then we can skip it but increment past its flash.
-            if !tbf_header.is_app() || !tbf_header.enabled() {
-                return (None, app_flash_size, 0);
-            }
+        app_verifier: Option<&AppVerifier>,
+        _capability: &ProcessManagementCapability,
+    ) -> Result<(Option<&'static mut Process<'a>>, usize, usize), TbfParseError> {
+        let tbf_header = tbfheader::parse_and_validate_tbf_header(app_flash_address)?;
+        let app_flash_size = tbf_header.get_total_size() as usize;
+
+        // If this isn't an app (i.e. it is padding) or it is an app but it
+        // isn't enabled, then we can skip it but increment past its flash.
+        if !tbf_header.is_app() || !tbf_header.enabled() {
+            return Ok((None, app_flash_size, 0));
+        }
 
-            // Otherwise, actually load the app.
-            let mut min_app_ram_size = tbf_header.get_minimum_app_ram_size();
-            let package_name = tbf_header.get_package_name(app_flash_address);
-            let init_fn =
-                app_flash_address.offset(tbf_header.get_init_function_offset() as isize) as usize;
-
-            // Set the initial process stack and memory to 128 bytes.
-            let initial_stack_pointer = remaining_app_memory.offset(128);
-            let initial_sbrk_pointer = remaining_app_memory.offset(128);
-
-            // First determine how much space we need in the application's
-            // memory space just for kernel and grant state. We need to make
-            // sure we allocate enough memory just for that.
-
-            // Make room for grant pointers.
-            let grant_ptr_size = mem::size_of::<*const usize>();
-            let grant_ptrs_num = read_volatile(&grant::CONTAINER_COUNTER);
-            let grant_ptrs_offset = grant_ptrs_num * grant_ptr_size;
-
-            // Allocate memory for callback ring buffer.
-            let callback_size = mem::size_of::<Task>();
-            let callback_len = 10;
-            let callbacks_offset = callback_len * callback_size;
-
-            // Make room to store this process's metadata.
-            let process_struct_offset = mem::size_of::<Process>();
-
-            // Need to make sure that the amount of memory we allocate for
-            // this process at least covers this state.
-            if min_app_ram_size
-                < (grant_ptrs_offset + callbacks_offset + process_struct_offset) as u32
-            {
-                min_app_ram_size =
-                    (grant_ptrs_offset + callbacks_offset + process_struct_offset) as u32;
+        // If the board only runs trusted apps, check the app's credentials
+        // before giving it any memory. Apps that fail are skipped like disabled
+        // apps.
+        if let Some(app_verifier) = app_verifier {
+            let app = slice::from_raw_parts(app_flash_address, app_flash_size);
+            if let Err(err) = verifier::verify_app(app_verifier, &tbf_header, app) {
+                debug!(
+                    "Not starting app {} at {:?}: {:?}",
+                    tbf_header.get_package_name(),
+                    app_flash_address,
+                    err
+                );
+                return Ok((None, app_flash_size, 0));
             }
+        }
 
-            // TODO round app_ram_size up to a closer MPU unit.
-            // This is a very conservative approach that rounds up to power of
-            // two. We should be able to make this closer to what we actually need.
-            let app_ram_size = math::closest_power_of_two(min_app_ram_size) as usize;
+        // Otherwise, actually load the app.
+        let package_name = tbf_header.get_package_name();
+        let init_fn =
+            app_flash_address.offset(tbf_header.get_init_function_offset() as isize) as usize;
 
-            // Check that we can actually give this app this much memory.
-            if app_ram_size > remaining_app_memory_size {
-                panic!(
-                    "{:?} failed to load. Insufficient memory. Requested {} have {}",
-                    package_name, app_ram_size, remaining_app_memory_size
-                );
-            }
+        // Set the initial process stack and memory to 128 bytes.
+        let initial_stack_pointer = remaining_app_memory.offset(128);
+        let initial_sbrk_pointer = remaining_app_memory.offset(128);
 
-            let app_memory = slice::from_raw_parts_mut(remaining_app_memory, app_ram_size);
+        // First determine how much space we need in the application's
+        // memory space just for kernel and grant state. We need to make
+        // sure we allocate enough memory just for that.
 
-            // Set up initial grant region.
-            let mut kernel_memory_break = app_memory.as_mut_ptr().offset(app_memory.len() as isize);
+        // Make room for grant pointers, and for how much memory each grant
+        // has allocated.
+        let grant_ptr_size = mem::size_of::<*const usize>();
+        let grant_ptrs_num = read_volatile(&grant::CONTAINER_COUNTER);
+        let grant_ptrs_offset = grant_ptrs_num * (grant_ptr_size + mem::size_of::<usize>());
 
-            // Now that we know we have the space we can setup the grant
-            // pointers.
-            kernel_memory_break = kernel_memory_break.offset(-(grant_ptrs_offset as isize));
+        // Allocate memory for callback ring buffer.
+        let callback_size = mem::size_of::<Task>();
+        let callback_len = CALLBACK_LEN;
+        let callbacks_offset = callback_len * callback_size;
 
-            // Set all pointers to null.
-            let opts =
-                slice::from_raw_parts_mut(kernel_memory_break as *mut *const usize, grant_ptrs_num);
-            for opt in opts.iter_mut() {
-                *opt = ptr::null()
-            }
+        // Make room to store this process's metadata.
+        let process_struct_offset = mem::size_of::<Process>();
 
-            // Now that we know we have the space we can setup the memory
-            // for the callbacks.
-            kernel_memory_break = kernel_memory_break.offset(-(callbacks_offset as isize));
-
-            // Set up ring buffer.
-            let callback_buf =
-                slice::from_raw_parts_mut(kernel_memory_break as *mut Task, callback_len);
-            let tasks = RingBuffer::new(callback_buf);
-
-            // Last thing is the process struct.
-            kernel_memory_break = kernel_memory_break.offset(-(process_struct_offset as isize));
-            let process_struct_memory_location = kernel_memory_break;
-
-            // Determine the debug information to the best of our
-            // understanding. If the app is doing all of the PIC fixup and
-            // memory management we don't know much.
-            let mut app_heap_start_pointer = None;
-            let mut app_stack_start_pointer = None;
-
-            // Create the Process struct in the app grant region.
-            let mut process: &mut Process =
-                &mut *(process_struct_memory_location as *mut Process<'static>);
-
-            process.kernel = kernel;
-            process.memory = app_memory;
-            process.header = tbf_header;
-            process.kernel_memory_break = kernel_memory_break;
-            process.original_kernel_memory_break = kernel_memory_break;
-            process.app_break = initial_sbrk_pointer;
-            process.original_app_break = initial_sbrk_pointer;
-            process.current_stack_pointer = initial_stack_pointer;
-            process.original_stack_pointer = initial_stack_pointer;
-
-            process.flash = slice::from_raw_parts(app_flash_address, app_flash_size);
-
-            process.stored_regs = Default::default();
-            process.yield_pc = init_fn;
-            // Set the Thumb bit and clear everything else
-            process.psr = 0x01000000;
-
-            process.state = State::Yielded;
-            process.fault_response = fault_response;
-
-            process.mpu_regions = [
-                Cell::new((ptr::null(), math::PowerOfTwo::zero())),
-                Cell::new((ptr::null(), math::PowerOfTwo::zero())),
-                Cell::new((ptr::null(), math::PowerOfTwo::zero())),
-                Cell::new((ptr::null(), math::PowerOfTwo::zero())),
-                Cell::new((ptr::null(), math::PowerOfTwo::zero())),
-            ];
-            process.tasks = tasks;
-            process.package_name = package_name;
-
-            process.debug = ProcessDebug {
-                app_heap_start_pointer: app_heap_start_pointer,
-                app_stack_start_pointer: app_stack_start_pointer,
-                min_stack_pointer: initial_stack_pointer,
-                syscall_count: Cell::new(0),
-                last_syscall: Cell::new(None),
-                dropped_callback_count: Cell::new(0),
-                restart_count: Cell::new(0),
-            };
-
-            if (init_fn & 0x1) != 1 {
-                panic!(
-                    "{:?} process image invalid. \
-                     init_fn address must end in 1 to be Thumb, got {:#X}",
-                    package_name, init_fn
-                );
-            }
+        let app_ram_size = Process::required_ram_size(&tbf_header);
 
-            let flash_protected_size = process.header.get_protected_size() as usize;
-            let flash_app_start = app_flash_address as usize + flash_protected_size;
+        // Check that we can actually give this app this much memory.
+        if app_ram_size > remaining_app_memory_size {
+            panic!(
+                "{:?} failed to load. Insufficient memory. Requested {} have {}",
+                package_name, app_ram_size, remaining_app_memory_size
+            );
+        }
+
+        let app_memory = slice::from_raw_parts_mut(remaining_app_memory, app_ram_size);
+
+        // Set up initial grant region.
+        let mut kernel_memory_break = app_memory.as_mut_ptr().offset(app_memory.len() as isize);
+
+        // Now that we know we have the space we can setup the grant
+        // pointers.
+        kernel_memory_break = kernel_memory_break.offset(-(grant_ptrs_offset as isize));
+
+        // Set all pointers to null.
+        let opts = slice::from_raw_parts_mut(
+            kernel_memory_break.offset((grant_ptrs_num * mem::size_of::<usize>()) as isize)
+                as *mut *const usize,
+            grant_ptrs_num,
+        );
+        for opt in opts.iter_mut() {
+            *opt = ptr::null()
+        }
 
-            process.tasks.enqueue(Task::FunctionCall(FunctionCall {
-                pc: init_fn,
-                r0: flash_app_start,
-                r1: process.memory.as_ptr() as usize,
-                r2: process.memory.len() as usize,
-                r3: process.app_break as usize,
-            }));
+        // Nothing is allocated for any grant yet.
+        let grant_sizes =
+            slice::from_raw_parts_mut(kernel_memory_break as *mut usize, grant_ptrs_num);
+        for grant_size in grant_sizes.iter_mut() {
+            *grant_size = 0;
+        }
 
-            kernel.increment_work();
+        // Now that we know we have the space we can setup the memory
+        // for the callbacks.
+        kernel_memory_break = kernel_memory_break.offset(-(callbacks_offset as isize));
+
+        // Set up ring buffer.
+        let callback_buf =
+            slice::from_raw_parts_mut(kernel_memory_break as *mut Task, callback_len);
+        let tasks = RingBuffer::new(callback_buf);
+
+        // Last thing is the process struct.
+        kernel_memory_break = kernel_memory_break.offset(-(process_struct_offset as isize));
+        let process_struct_memory_location = kernel_memory_break;
+
+        // Determine the debug information to the best of our
+        // understanding. If the app is doing all of the PIC fixup and
+        // memory management we don't know much.
+        let app_heap_start_pointer = None;
+        let app_stack_start_pointer = None;
+
+        // Create the Process struct in the app grant region.
+        let process: &mut Process =
+            &mut *(process_struct_memory_location as *mut Process<'static>);
+
+        process.kernel = kernel;
+        process.memory = app_memory;
+        process.header = tbf_header;
+        process.kernel_memory_break = kernel_memory_break;
+        process.original_kernel_memory_break = kernel_memory_break;
+        process.app_break = initial_sbrk_pointer;
+        process.original_app_break = initial_sbrk_pointer;
+        process.current_stack_pointer = initial_stack_pointer;
+        process.original_stack_pointer = initial_stack_pointer;
+        process.paint_memory(process.mem_start(), process.kernel_memory_break);
+
+        process.flash = slice::from_raw_parts(app_flash_address, app_flash_size);
+
+        process.stored_regs = Default::default();
+        process.yield_pc = init_fn;
+        // Set the Thumb bit and clear everything else
+        process.psr = 0x01000000;
+
+        process.state = State::Yielded;
+        process.fault_response = fault_response;
+
+        process.priority = process.header.get_priority();
+        let timeslice_us = process
+            .header
+            .get_timeslice_us()
+            .unwrap_or(scheduler::DEFAULT_TIMESLICE_US);
+        process.timeslice_us = cmp::min(
+            cmp::max(timeslice_us, scheduler::MIN_TIMESLICE_US),
+            scheduler::MAX_TIMESLICE_US,
+        );
+        process.max_restarts = process.header.get_max_restarts().map(|max| max as usize);
+        process.restart_at = None;
+        process.generation = next_generation();
+
+        let no_region = (
+            ptr::null(),
+            math::PowerOfTwo::zero(),
+            mpu::AccessPermission::NoAccess,
+        );
+        process.mpu_regions = [
+            Cell::new(no_region),
+            Cell::new(no_region),
+            Cell::new(no_region),
+            Cell::new(no_region),
+            Cell::new(no_region),
+        ];
+        process.tasks = tasks;
+        process.package_name = package_name;
+
+        process.debug = ProcessDebug {
+            app_heap_start_pointer: app_heap_start_pointer,
+            app_stack_start_pointer: app_stack_start_pointer,
+            min_stack_pointer: initial_stack_pointer,
+            syscall_count: Cell::new(0),
+            last_syscall: Cell::new(None),
+            dropped_callback_count: Cell::new(0),
+            restart_count: Cell::new(0),
+            completion_code: Cell::new(None),
+            grant_alloc_failures: Cell::new(0),
+            app_alloc_failures: Cell::new(0),
+            cpu_time_us: Cell::new(0),
+            energy: Cell::new(0),
+            preemption_count: Cell::new(0),
+            timeslice_expiration_count: Cell::new(0),
+        };
 
-            return (Some(process), app_flash_size, app_ram_size);
+        if (init_fn & 0x1) != 1 {
+            panic!(
+                "{:?} process image invalid. \
+                 init_fn address must end in 1 to be Thumb, got {:#X}",
+                package_name, init_fn
+            );
         }
-        (None, 0, 0)
+
+        let flash_protected_size = process.header.get_protected_size() as usize;
+        let flash_app_start = app_flash_address as usize + flash_protected_size;
+
+        process.tasks.enqueue(Task::FunctionCall(FunctionCall {
+            pc: init_fn,
+            r0: flash_app_start,
+            r1: process.memory.as_ptr() as usize,
+            r2: process.memory.len() as usize,
+            r3: process.app_break as usize,
+        }));
+
+        kernel.increment_work();
+
+        Ok((Some(process), app_flash_size, app_ram_size))
+    }
+
+    /// Amount of RAM a process for the app described by `tbf_header` is
+    /// given: what the app asks for, but at least enough for the grant
+    /// pointers, callback queue and process struct the kernel keeps in it.
+    pub(crate) unsafe fn required_ram_size(tbf_header: &TbfHeader) -> usize {
+        let grant_ptrs_offset = read_volatile(&grant::CONTAINER_COUNTER)
+            * (mem::size_of::<*const usize>() + mem::size_of::<usize>());
+        let callbacks_offset = CALLBACK_LEN * mem::size_of::<Task>();
+        let process_struct_offset = mem::size_of::<Process>();
+
+        // Need to make sure that the amount of memory we allocate for
+        // this process at least covers this state.
+        let min_app_ram_size = cmp::max(
+            tbf_header.get_minimum_app_ram_size(),
+            (grant_ptrs_offset + callbacks_offset + process_struct_offset) as u32,
+        );
+
+        // TODO round app_ram_size up to a closer MPU unit.
+        // This is a very conservative approach that rounds up to power of
+        // two. We should be able to make this closer to what we actually need.
+        math::closest_power_of_two(min_app_ram_size) as usize
     }
 
     pub fn sbrk(&mut self, increment: isize) -> Result<*const u8, Error> {
@@ -757,6 +1237,9 @@ impl Process<'a> {
         if new_break < self.mem_start() || new_break >= self.mem_end() {
             Err(Error::AddressOutOfBounds)
         } else if new_break > self.kernel_memory_break {
+            self.debug
+                .app_alloc_failures
+                .set(self.debug.app_alloc_failures.get() + 1);
             Err(Error::OutOfMemory)
         } else {
             let old_break = self.app_break;
@@ -788,16 +1271,69 @@ impl Process<'a> {
         (self.mem_end() as *mut *mut T).offset(-(grant_num + 1))
     }
 
-    /// Reset all `grant_ptr`s to NULL.
+    /// Where the number of bytes allocated for grant `grant_num` is kept,
+    /// below the grant pointers.
+    unsafe fn grant_size_ptr(&self, grant_num: usize) -> *mut usize {
+        let grant_ptrs_num = read_volatile(&grant::CONTAINER_COUNTER) as isize;
+        let grant_sizes_end = (self.mem_end() as *mut *mut u8).offset(-grant_ptrs_num);
+        (grant_sizes_end as *mut usize).offset(-(grant_num as isize + 1))
+    }
+
+    /// Reset all `grant_ptr`s to NULL, and the memory allocated for each
+    /// grant to zero.
     unsafe fn grant_ptrs_reset(&self) {
         let grant_ptrs_num = read_volatile(&grant::CONTAINER_COUNTER);
         for grant_num in 0..grant_ptrs_num {
+            write_volatile(self.grant_size_ptr(grant_num), 0);
             let grant_num = grant_num as isize;
             let ctr_ptr = (self.mem_end() as *mut *mut usize).offset(-(grant_num + 1));
             write_volatile(ctr_ptr, ptr::null_mut());
         }
     }
 
+    /// Allocate `size` bytes of grant memory on behalf of grant `grant_num`,
+    /// keeping track of how much each grant uses.
+    pub unsafe fn grant_alloc(&mut self, grant_num: usize, size: usize) -> Option<&mut [u8]> {
+        if self.kernel_memory_break.offset(-(size as isize)) < self.app_break {
+            self.debug
+                .grant_alloc_failures
+                .set(self.debug.grant_alloc_failures.get() + 1);
+            return None;
+        }
+        let size_ptr = self.grant_size_ptr(grant_num);
+        write_volatile(size_ptr, read_volatile(size_ptr) + size);
+        self.alloc(size)
+    }
+
+    /// How many bytes of grant memory grant `grant_num` has allocated for
+    /// this process, or `None` if there is no such grant.
+    pub fn grant_memory_used(&self, grant_num: usize) -> Option<usize> {
+        unsafe {
+            if grant_num < read_volatile(&grant::CONTAINER_COUNTER) {
+                Some(read_volatile(self.grant_size_ptr(grant_num)))
+            } else {
+                None
+            }
+        }
+    }
+
+    /// How many bytes of grant memory all grants have allocated for this
+    /// process.
+    pub fn total_grant_memory_used(&self) -> usize {
+        let grant_ptrs_num = unsafe { read_volatile(&grant::CONTAINER_COUNTER) };
+        (0..grant_ptrs_num)
+            .filter_map(|grant_num| self.grant_memory_used(grant_num))
+            .sum()
+    }
+
+    pub fn grant_alloc_failures(&self) -> usize {
+        self.debug.grant_alloc_failures.get()
+    }
+
+    pub fn app_alloc_failures(&self) -> usize {
+        self.debug.app_alloc_failures.get()
+    }
+
     pub unsafe fn grant_for<T>(&mut self, grant_num: usize) -> *mut T {
         *self.grant_ptr(grant_num)
     }
@@ -805,7 +1341,8 @@ impl Process<'a> {
     pub unsafe fn grant_for_or_alloc<T: Default>(&mut self, grant_num: usize) -> Option<*mut T> {
         let ctr_ptr = self.grant_ptr::<T>(grant_num);
         if (*ctr_ptr).is_null() {
-            self.alloc(mem::size_of::<T>()).map(|root_arr| {
+            let root_size = mem::size_of::<T>();
+            self.grant_alloc(grant_num, root_size).map(|root_arr| {
                 let root_ptr = root_arr.as_mut_ptr() as *mut T;
                 // Initialize the grant contents using ptr::write, to
                 // ensure that we don't try to drop the contents of
@@ -892,16 +1429,23 @@ impl Process<'a> {
                 2 => Some(Syscall::COMMAND),
                 3 => Some(Syscall::ALLOW),
                 4 => Some(Syscall::MEMOP),
+                5 => Some(Syscall::EXIT),
                 _ => None,
             }
         }
     }
 
     pub fn incr_syscall_count(&self) {
+        self.count_syscall(self.svc_number());
+    }
+
+    /// Count a syscall the process made, for when it was not made by trapping
+    /// into the kernel.
+    pub(crate) fn count_syscall(&self, syscall: Option<Syscall>) {
         self.debug
             .syscall_count
             .set(self.debug.syscall_count.get() + 1);
-        self.debug.last_syscall.set(self.svc_number());
+        self.debug.last_syscall.set(syscall);
     }
 
     pub fn sp(&self) -> usize {
@@ -1123,6 +1667,13 @@ impl Process<'a> {
             ));
         }
 
+        if let Some(used) = self.stack_high_water_mark() {
+            let _ = writer.write_fmt(format_args!(
+                "Stack High-Water Mark:              {} bytes\r\n",
+                used
+            ));
+        }
+
         if cfsr == 0 && hfsr == 0 {
             let _ = writer.write_fmt(format_args!("No faults detected.\r\n"));
         } else {
@@ -1185,6 +1736,7 @@ impl Process<'a> {
         let last_syscall = self.debug.last_syscall.get();
         let dropped_callback_count = self.debug.dropped_callback_count.get();
         let restart_count = self.debug.restart_count.get();
+        let completion_code = self.debug.completion_code.get();
 
         // register values
         let (r0, r1, r2, r3, r12, sp, lr, pc, xpsr) = (
@@ -1217,6 +1769,44 @@ impl Process<'a> {
             None => writer.write_fmt(format_args!(" Last Syscall: None")),
         };
 
+        let _ = match completion_code {
+            Some(code) => writer.write_fmt(format_args!("   Completion Code: {}", code)),
+            None => writer.write_fmt(format_args!("   Completion Code: None")),
+        };
+
+        let _ = match self.stack_high_water_mark() {
+            Some(used) => writer.write_fmt(format_args!(
+                "\r\n Stack High-Water Mark: {} | {} bytes",
+                used, sram_stack_allocated
+            )),
+            None => writer.write_fmt(format_args!("\r\n Stack High-Water Mark: Unknown")),
+        };
+
+        let _ = writer.write_fmt(format_args!(
+            "\r\n CPU Time: {} us   Preemptions: {}   Timeslice Expirations: {}   Energy: {}",
+            self.debug.cpu_time_us.get(),
+            self.debug.preemption_count.get(),
+            self.debug.timeslice_expiration_count.get(),
+            self.debug.energy.get(),
+        ));
+
+        let _ = writer.write_fmt(format_args!(
+            "\r\n Grant Memory: {} bytes   Grant Alloc Failures: {}   App Alloc Failures: {}",
+            self.total_grant_memory_used(),
+            self.debug.grant_alloc_failures.get(),
+            self.debug.app_alloc_failures.get(),
+        ));
+        let grant_ptrs_num = read_volatile(&grant::CONTAINER_COUNTER);
+        for grant_num in 0..grant_ptrs_num {
+            let used = self.grant_memory_used(grant_num).unwrap_or(0);
+            if used > 0 {
+                let _ = writer.write_fmt(format_args!(
+                    "\r\n   Grant {:2}: {:6} bytes",
+                    grant_num, used
+                ));
+            }
+        }
+
         let _ = writer.write_fmt(format_args!("\
 \r\n\
 \r\n ╔═══════════╤══════════════════════════════════════════╗\
@@ -0,0 +1,85 @@
+//! Delaying the restart of processes that fault.
+//!
+//! With `FaultResponse::RestartWithBackoff` a process that faults is not
+//! restarted right away, so that an app that crashes on every start does not
+//! keep the chip busy. The kernel needs a timer to know when the delay is
+//! over. Boards that use the backoff policy give it one with
+//! `Kernel::set_restart_timer`, usually an `AlarmRestartTimer` on top of a
+//! virtual alarm:
+//!
+//! ```rust
+//! let restart_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let restart_timer = static_init!(
+//!     kernel::procs::AlarmRestartTimer<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+//!     kernel::procs::AlarmRestartTimer::new(restart_alarm)
+//! );
+//! restart_alarm.set_client(restart_timer);
+//! board_kernel.set_restart_timer(restart_timer, &kernel_configuration_capability);
+//! ```
+
+use hil::time::{self, Alarm, Frequency};
+use process;
+
+/// Source of time for restarting processes after a delay.
+pub trait RestartTimer {
+    /// The current time, in ticks.
+    fn now(&self) -> u32;
+
+    /// Convert a delay in milliseconds to ticks.
+    fn ticks_from_ms(&self, ms: u32) -> u32;
+
+    /// Make sure the timer wakes the kernel up no later than `at` to restart
+    /// the processes that are due.
+    fn set_wakeup(&self, at: u32);
+}
+
+/// `RestartTimer` that uses a hardware alarm.
+pub struct AlarmRestartTimer<'a, A: Alarm + 'a> {
+    alarm: &'a A,
+}
+
+impl<A: Alarm> AlarmRestartTimer<'a, A> {
+    pub const fn new(alarm: &'a A) -> AlarmRestartTimer<'a, A> {
+        AlarmRestartTimer { alarm: alarm }
+    }
+}
+
+impl<A: Alarm> RestartTimer for AlarmRestartTimer<'a, A> {
+    fn now(&self) -> u32 {
+        self.alarm.now()
+    }
+
+    fn ticks_from_ms(&self, ms: u32) -> u32 {
+        let freq = <A::Frequency>::frequency() as u64;
+        let ticks = ms as u64 * freq / 1000;
+        if ticks > u32::max_value() as u64 / 2 {
+            // Keep the wakeup time comparable with the current time.
+            u32::max_value() / 2
+        } else {
+            ticks as u32
+        }
+    }
+
+    fn set_wakeup(&self, at: u32) {
+        // Only move an armed alarm earlier, since it will restart every
+        // process that is due when it fires and re-arm for the rest.
+        let now = self.alarm.now();
+        let armed_at = self.alarm.get_alarm();
+        if !self.alarm.is_armed() || at.wrapping_sub(now) < armed_at.wrapping_sub(now) {
+            self.alarm.set_alarm(at);
+        }
+    }
+}
+
+impl<A: Alarm> time::Client for AlarmRestartTimer<'a, A> {
+    fn fired(&self) {
+        let now = self.alarm.now();
+        match process::restart_due_processes(now) {
+            Some(next) => self.alarm.set_alarm(next),
+            None => self.alarm.disable(),
+        }
+    }
+}
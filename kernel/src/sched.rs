@@ -4,35 +4,160 @@ use core::cell::Cell;
 use core::ptr;
 use core::ptr::NonNull;
 
-use callback;
 use callback::{AppId, Callback};
-use common::cells::NumericCellExt;
+use capabilities::{KernelConfigurationCapability, MainLoopCapability};
+use common::cells::{NumericCellExt, OptionalCell};
+use common::dynamic_deferred_call::DynamicDeferredCall;
+use crash;
 use ipc;
 use mem::AppSlice;
 use memop;
 use platform::mpu::MPU;
 use platform::systick::SysTick;
 use platform::{Chip, Platform};
+use power::PowerModel;
 use process;
 use process::{Process, Task};
+use restart::RestartTimer;
 use returncode::ReturnCode;
-use syscall::Syscall;
+use scheduler::Scheduler;
+use syscall::{ExitType, Syscall};
+use tbfheader::DefaultPermissions;
+use trace::{self, SyscallTrace};
+use watchdog::KernelWatchdog;
 
-/// The time a process is permitted to run before being pre-empted
-const KERNEL_TICK_DURATION_US: u32 = 10000;
 /// Skip re-scheduling a process if its quanta is nearly exhausted
 const MIN_QUANTA_THRESHOLD_US: u32 = 500;
 
+/// Period of the SysTick when a process runs without a timeslice, so that the
+/// time it runs can still be measured. The SysTick cannot tell how often it
+/// wrapped, so a process that runs longer than this without a syscall is only
+/// charged for part of it.
+const ACCOUNTING_PERIOD_US: u32 = 100_000;
+
 /// Main object for the kernel. Each board will need to create one.
 pub struct Kernel {
     /// How many "to-do" items exist at any given time. These include
     /// outstanding callbacks and processes in the Running state.
     work: Cell<usize>,
+
+    /// Timer used to delay restarting faulted processes, if the board
+    /// provides one.
+    restart_timer: OptionalCell<&'static RestartTimer>,
+
+    /// Watchdog that resets the chip if the kernel stops making progress, if
+    /// the board provides one.
+    watchdog: OptionalCell<&'static KernelWatchdog>,
+
+    /// Deferred calls that capsules can set, if the board provides them.
+    dynamic_deferred_call: OptionalCell<&'static DynamicDeferredCall>,
+
+    /// Estimates the power drawn while processes run, to charge them for
+    /// energy, if the board provides one.
+    power_model: OptionalCell<&'static PowerModel>,
+
+    /// Which drivers processes without a Permissions entry in their TBF
+    /// header may use.
+    default_permissions: Cell<DefaultPermissions>,
 }
 
 impl Kernel {
     pub fn new() -> Kernel {
-        Kernel { work: Cell::new(0) }
+        Kernel {
+            work: Cell::new(0),
+            restart_timer: OptionalCell::empty(),
+            watchdog: OptionalCell::empty(),
+            dynamic_deferred_call: OptionalCell::empty(),
+            power_model: OptionalCell::empty(),
+            default_permissions: Cell::new(DefaultPermissions::AllowAll),
+        }
+    }
+
+    /// Give the kernel a timer to delay restarting processes that fault.
+    /// Without one, `FaultResponse::RestartWithBackoff` restarts processes
+    /// immediately.
+    pub fn set_restart_timer(
+        &self,
+        restart_timer: &'static RestartTimer,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        self.restart_timer.set(restart_timer);
+    }
+
+    pub(crate) fn restart_timer(&self) -> Option<&'static RestartTimer> {
+        self.restart_timer.map(|restart_timer| *restart_timer)
+    }
+
+    /// Record the syscalls processes make in `syscall_trace`.
+    pub fn set_syscall_trace(
+        &self,
+        syscall_trace: &'static SyscallTrace,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        trace::set_syscall_trace(syscall_trace);
+    }
+
+    /// Have `watchdog` reset the chip if the main loop stops making progress.
+    pub fn set_watchdog(
+        &self,
+        watchdog: &'static KernelWatchdog,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        watchdog.take_record();
+        self.watchdog.set(watchdog);
+    }
+
+    /// Service the deferred calls set in `dynamic_deferred_call` from the
+    /// main loop.
+    pub fn set_dynamic_deferred_call(
+        &self,
+        dynamic_deferred_call: &'static DynamicDeferredCall,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        self.dynamic_deferred_call.set(dynamic_deferred_call);
+    }
+
+    /// Charge processes for the energy `power_model` estimates they use.
+    pub fn set_power_model(
+        &self,
+        power_model: &'static PowerModel,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        self.power_model.set(power_model);
+    }
+
+    /// Choose which drivers processes without a Permissions entry in their TBF
+    /// header may use. They may use every driver unless the board passes
+    /// `DefaultPermissions::DenyAll`.
+    pub fn set_default_permissions(
+        &self,
+        default_permissions: DefaultPermissions,
+        _capability: &KernelConfigurationCapability,
+    ) {
+        self.default_permissions.set(default_permissions);
+    }
+
+    pub(crate) fn default_permissions(&self) -> DefaultPermissions {
+        self.default_permissions.get()
+    }
+
+    /// Whether a deferred call is waiting to be serviced.
+    fn deferred_calls_pending(&self) -> bool {
+        self.dynamic_deferred_call
+            .map_or(false, |deferred_call| deferred_call.has_pending())
+    }
+
+    /// Whether an interrupt or a deferred call is waiting to be serviced.
+    pub(crate) fn work_pending<C: Chip>(&self, chip: &C) -> bool {
+        chip.has_pending_interrupts() || self.deferred_calls_pending()
+    }
+
+    /// Service the pending interrupts, then the pending deferred calls, as
+    /// the main loop does before it runs processes.
+    pub(crate) unsafe fn service_pending_work<C: Chip>(&self, chip: &mut C) {
+        chip.service_pending_interrupts();
+        self.dynamic_deferred_call
+            .map(|deferred_call| deferred_call.call_pending());
     }
 
     /// Something was scheduled for a process, so there is more work to do.
@@ -53,40 +178,101 @@ impl Kernel {
     }
 
     /// Main loop.
-    pub fn kernel_loop<P: Platform, C: Chip>(
+    ///
+    /// The `scheduler` decides which process runs next, and for how long,
+    /// whenever there are no interrupts left to service.
+    pub fn kernel_loop<P: Platform, C: Chip, S: Scheduler>(
         &self,
         platform: &P,
         chip: &mut C,
         processes: &'static mut [Option<&mut process::Process<'static>>],
         ipc: Option<&ipc::IPC>,
+        scheduler: &S,
+        _capability: &MainLoopCapability,
     ) {
         let processes = unsafe {
             process::PROCS = processes;
             &mut process::PROCS
         };
 
+        crash::report();
+        self.watchdog.map(|watchdog| watchdog.start());
+
         loop {
             unsafe {
-                chip.service_pending_interrupts();
-
-                for (i, p) in processes.iter_mut().enumerate() {
-                    p.as_mut().map(|process| {
-                        self.do_process(platform, chip, process, callback::AppId::new(i), ipc);
-                    });
-                    if chip.has_pending_interrupts() {
-                        break;
+                self.service_pending_work(chip);
+                self.watchdog.map(|watchdog| watchdog.tickle());
+
+                while !self.work_pending(chip) {
+                    match scheduler.next(processes) {
+                        Some((i, timeslice)) => {
+                            processes[i].as_mut().map(|process| {
+                                let appid = process.appid(i);
+                                self.watchdog
+                                    .map(|watchdog| watchdog.set_running_process(Some(i)));
+                                self.do_process(platform, chip, process, appid, ipc, timeslice);
+                                self.watchdog.map(|watchdog| {
+                                    watchdog.set_running_process(None);
+                                    watchdog.tickle();
+                                });
+                            });
+                        }
+                        None => break,
                     }
                 }
 
                 chip.atomic(|| {
-                    if !chip.has_pending_interrupts() && self.processes_blocked() {
+                    if !self.work_pending(chip) && self.processes_blocked() {
+                        // Nothing runs while the chip sleeps, so the watchdog
+                        // only stops for as long as the sleep lasts.
+                        self.watchdog.map(|watchdog| watchdog.suspend());
                         chip.sleep();
+                        self.watchdog.map(|watchdog| watchdog.resume());
                     }
                 });
             };
         }
     }
 
+    /// Run `process` until it makes a syscall or an interrupt takes the CPU
+    /// back, and charge it for the time it ran.
+    unsafe fn run_process<C: Chip>(&self, chip: &C, process: &mut Process, timeslice: Option<u32>) {
+        let systick = chip.systick();
+        process.setup_mpu(chip.mpu());
+        chip.mpu().enable_mpu();
+        if timeslice.is_some() {
+            systick.enable(true);
+        }
+        let power_before = self.power_model.map_or(0, |model| model.active_power());
+        let remaining_before = systick.get_value();
+        process.switch_to();
+        let remaining_after = systick.get_value();
+        systick.enable(false);
+        let power_after = self.power_model.map_or(0, |model| model.active_power());
+        chip.mpu().disable_mpu();
+
+        // The SysTick starts over from its period when it counts down to
+        // zero. With a timeslice that ends the timeslice, so it wraps at most
+        // once while the process runs.
+        let period = timeslice.unwrap_or(ACCOUNTING_PERIOD_US);
+        let wrapped = remaining_after > remaining_before;
+        let ran_us = if wrapped {
+            remaining_before + period.saturating_sub(remaining_after)
+        } else {
+            remaining_before - remaining_after
+        };
+        let power = ((power_before as u64 + power_after as u64) / 2) as u32;
+        process.charge_cpu_time(ran_us, power);
+
+        if !process.syscall_fired() {
+            if wrapped && timeslice.is_some() {
+                process.incr_timeslice_expiration_count();
+            } else {
+                process.incr_preemption_count();
+            }
+        }
+    }
+
     unsafe fn do_process<P: Platform, C: Chip>(
         &self,
         platform: &P,
@@ -94,28 +280,36 @@ impl Kernel {
         process: &mut Process,
         appid: AppId,
         ipc: Option<&::ipc::IPC>,
+        timeslice: Option<u32>,
     ) {
         let systick = chip.systick();
         systick.reset();
-        systick.set_timer(KERNEL_TICK_DURATION_US);
-        systick.enable(true);
+        match timeslice {
+            Some(us) => {
+                systick.set_timer(us);
+                systick.enable(true);
+            }
+            None => {
+                systick.set_timer(ACCOUNTING_PERIOD_US);
+                systick.enable(false);
+            }
+        }
 
         loop {
-            if chip.has_pending_interrupts()
-                || systick.overflowed()
-                || !systick.greater_than(MIN_QUANTA_THRESHOLD_US)
+            if self.work_pending(chip) {
+                break;
+            }
+            if timeslice.is_some()
+                && (systick.overflowed() || !systick.greater_than(MIN_QUANTA_THRESHOLD_US))
             {
+                // `run_process` counted the expiry when the process was
+                // interrupted.
                 break;
             }
 
             match process.current_state() {
                 process::State::Running => {
-                    process.setup_mpu(chip.mpu());
-                    chip.mpu().enable_mpu();
-                    systick.enable(true);
-                    process.switch_to();
-                    systick.enable(false);
-                    chip.mpu().disable_mpu();
+                    self.run_process(chip, process, timeslice);
                 }
                 process::State::Yielded => match process.dequeue_task() {
                     None => break,
@@ -141,9 +335,11 @@ impl Kernel {
                         continue;
                     }
                 },
-                process::State::Fault => {
-                    // we should never be scheduling a process in fault
-                    panic!("Attempted to schedule a faulty process");
+                process::State::Fault | process::State::Stopped => {
+                    // The process faulted or was stopped during this
+                    // timeslice and has nothing left to run until it is
+                    // restarted.
+                    break;
                 }
             }
 
@@ -160,67 +356,103 @@ impl Kernel {
 
             // process had a system call, count it
             process.incr_syscall_count();
-            match process.svc_number() {
-                Some(Syscall::MEMOP) => {
-                    let res = memop::memop(process);
-                    process.set_return_code(res);
-                }
-                Some(Syscall::YIELD) => {
+
+            let syscall = match process.svc_number() {
+                Some(syscall) => syscall,
+                None => continue,
+            };
+            // Keep the arguments for the syscall trace, since the return code
+            // overwrites r0.
+            let args = [process.r0(), process.r1(), process.r2(), process.r3()];
+
+            let res = match syscall {
+                Syscall::MEMOP => memop::memop(process, args[0], args[1]),
+                Syscall::EXIT => match ExitType::from_usize(process.r0()) {
+                    Some(exit_type) => {
+                        trace::record(appid.idx(), syscall, args, None);
+                        let completion_code = process.r1();
+                        process.exit(exit_type, completion_code);
+                        continue;
+                    }
+                    None => ReturnCode::EINVAL,
+                },
+                Syscall::YIELD => {
+                    trace::record(appid.idx(), syscall, args, None);
                     process.yield_state();
                     process.pop_syscall_stack();
 
                     // There might be already enqueued callbacks
                     continue;
                 }
-                Some(Syscall::SUBSCRIBE) => {
-                    let driver_num = process.r0();
-                    let subdriver_num = process.r1();
-                    let callback_ptr_raw = process.r2() as *mut ();
-                    let appdata = process.r3();
-
-                    let callback_ptr = NonNull::new(callback_ptr_raw);
-                    let callback =
-                        callback_ptr.map(|ptr| Callback::new(appid, appdata, ptr.cast()));
-
-                    let res = platform.with_driver(driver_num, |driver| match driver {
-                        Some(d) => d.subscribe(subdriver_num, callback, appid),
-                        None => ReturnCode::ENODEVICE,
-                    });
-                    process.set_return_code(res);
-                }
-                Some(Syscall::COMMAND) => {
-                    let res = platform.with_driver(process.r0(), |driver| match driver {
-                        Some(d) => d.command(process.r1(), process.r2(), process.r3(), appid),
-                        None => ReturnCode::ENODEVICE,
-                    });
-                    process.set_return_code(res);
+                Syscall::SUBSCRIBE | Syscall::COMMAND | Syscall::ALLOW => {
+                    driver_syscall(platform, process, appid, syscall, args)
                 }
-                Some(Syscall::ALLOW) => {
-                    let res = platform.with_driver(process.r0(), |driver| {
-                        match driver {
-                            Some(d) => {
-                                let start_addr = process.r2() as *mut u8;
-                                if start_addr != ptr::null_mut() {
-                                    let size = process.r3();
-                                    if process.in_exposed_bounds(start_addr, size) {
-                                        let slice =
-                                            AppSlice::new(start_addr as *mut u8, size, appid);
-                                        d.allow(appid, process.r1(), Some(slice))
-                                    } else {
-                                        ReturnCode::EINVAL /* memory not allocated to process */
-                                    }
-                                } else {
-                                    d.allow(appid, process.r1(), None)
-                                }
-                            }
-                            None => ReturnCode::ENODEVICE,
+            };
+            process.set_return_code(res);
+            trace::record(appid.idx(), syscall, args, Some(res));
+        }
+        systick.reset();
+    }
+}
+
+/// Pass a `SUBSCRIBE`, `COMMAND` or `ALLOW` syscall with arguments `args`,
+/// which `process` made, to the driver it names.
+///
+/// Processes may be restricted to a subset of the drivers the platform
+/// provides, so this checks that the process may use the driver first.
+pub(crate) unsafe fn driver_syscall<P: Platform>(
+    platform: &P,
+    process: &Process,
+    appid: AppId,
+    syscall: Syscall,
+    args: [usize; 4],
+) -> ReturnCode {
+    let [driver_num, subdriver_num, arg1, arg2] = args;
+    let permitted = match syscall {
+        Syscall::SUBSCRIBE | Syscall::ALLOW => process.has_driver_permission(driver_num, None),
+        Syscall::COMMAND => process.has_driver_permission(driver_num, Some(subdriver_num)),
+        _ => false,
+    };
+    if !permitted {
+        return ReturnCode::ENOPERM;
+    }
+
+    match syscall {
+        Syscall::SUBSCRIBE => {
+            let callback_ptr_raw = arg1 as *mut ();
+            let appdata = arg2;
+
+            let callback_ptr = NonNull::new(callback_ptr_raw);
+            let callback = callback_ptr.map(|ptr| Callback::new(appid, appdata, ptr.cast()));
+
+            platform.with_driver(driver_num, |driver| match driver {
+                Some(d) => d.subscribe(subdriver_num, callback, appid),
+                None => ReturnCode::ENODEVICE,
+            })
+        }
+        Syscall::COMMAND => platform.with_driver(driver_num, |driver| match driver {
+            Some(d) => d.command(subdriver_num, arg1, arg2, appid),
+            None => ReturnCode::ENODEVICE,
+        }),
+        Syscall::ALLOW => platform.with_driver(driver_num, |driver| {
+            match driver {
+                Some(d) => {
+                    let start_addr = arg1 as *mut u8;
+                    if start_addr != ptr::null_mut() {
+                        let size = arg2;
+                        if process.in_exposed_bounds(start_addr, size) {
+                            let slice = AppSlice::new(start_addr as *mut u8, size, appid);
+                            d.allow(appid, subdriver_num, Some(slice))
+                        } else {
+                            ReturnCode::EINVAL /* memory not allocated to process */
                         }
-                    });
-                    process.set_return_code(res);
+                    } else {
+                        d.allow(appid, subdriver_num, None)
+                    }
                 }
-                _ => {}
+                None => ReturnCode::ENODEVICE,
             }
-        }
-        systick.reset();
+        }),
+        _ => ReturnCode::ENOSUPPORT,
     }
 }
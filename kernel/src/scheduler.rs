@@ -0,0 +1,165 @@
+//! Scheduling policies for choosing which process the kernel runs next.
+//!
+//! A board selects a policy by passing an object implementing `Scheduler` to
+//! `Kernel::kernel_loop`. The kernel asks the scheduler for the next process
+//! to run whenever it has finished servicing interrupts or a process has
+//! yielded or exhausted its timeslice.
+//!
+//! ```rust
+//! let scheduler = kernel::scheduler::RoundRobin::new();
+//! board_kernel.kernel_loop(
+//!     &hail,
+//!     &mut chip,
+//!     &mut PROCESSES,
+//!     Some(&hail.ipc),
+//!     &scheduler,
+//!     &main_loop_capability,
+//! );
+//! ```
+
+use core::cell::Cell;
+
+use process::{Process, State};
+
+/// The time a process is permitted to run before being pre-empted if its TBF
+/// header does not request a different timeslice.
+pub const DEFAULT_TIMESLICE_US: u32 = 10000;
+
+/// Bounds on the timeslice a process can request in its TBF header. Shorter
+/// timeslices would be consumed by the scheduler's own overhead. The Cortex-M
+/// SysTick counts down from a 24-bit reload value, which lasts about 262 ms at
+/// 64 MHz, so longer timeslices would not fit.
+pub const MIN_TIMESLICE_US: u32 = 1000;
+pub const MAX_TIMESLICE_US: u32 = 250000;
+
+/// Interface for a kernel scheduling policy.
+pub trait Scheduler {
+    /// Choose the next process to run.
+    ///
+    /// Returns the index of the process in `processes` together with the
+    /// timeslice in microseconds it should be given. A timeslice of `None`
+    /// lets the process run until it yields. Returns `None` if no process is
+    /// ready to run, in which case the kernel will try to sleep.
+    ///
+    /// Implementations must only return processes for which
+    /// `Process::ready()` is true.
+    fn next(&self, processes: &[Option<&mut Process<'static>>]) -> Option<(usize, Option<u32>)>;
+}
+
+/// Find the first ready process at or after `start`, wrapping around the end
+/// of the process array.
+fn next_ready_from(processes: &[Option<&mut Process<'static>>], start: usize) -> Option<usize> {
+    let len = processes.len();
+    (0..len).map(|i| (start + i) % len).find(|&idx| {
+        processes[idx]
+            .as_ref()
+            .map_or(false, |process| process.ready())
+    })
+}
+
+/// Round robin scheduler.
+///
+/// Ready processes are run in index order, each for its timeslice. This is the
+/// policy the kernel has always used.
+pub struct RoundRobin {
+    next_index: Cell<usize>,
+}
+
+impl RoundRobin {
+    pub fn new() -> RoundRobin {
+        RoundRobin {
+            next_index: Cell::new(0),
+        }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn next(&self, processes: &[Option<&mut Process<'static>>]) -> Option<(usize, Option<u32>)> {
+        next_ready_from(processes, self.next_index.get()).and_then(|idx| {
+            self.next_index.set(idx + 1);
+            processes[idx]
+                .as_ref()
+                .map(|process| (idx, Some(process.timeslice_us())))
+        })
+    }
+}
+
+/// Fixed priority scheduler.
+///
+/// The ready process with the highest priority, as set in its TBF header,
+/// always runs. Processes without a priority in their header have priority
+/// `0`, the lowest. Processes with equal priority are run round robin so that
+/// they cannot starve each other.
+pub struct FixedPriority {
+    last_index: Cell<usize>,
+}
+
+impl FixedPriority {
+    pub fn new() -> FixedPriority {
+        FixedPriority {
+            last_index: Cell::new(0),
+        }
+    }
+}
+
+impl Scheduler for FixedPriority {
+    fn next(&self, processes: &[Option<&mut Process<'static>>]) -> Option<(usize, Option<u32>)> {
+        let len = processes.len();
+        let mut best: Option<(usize, u32, u32)> = None;
+
+        // Start looking just after the most recently run process so that
+        // processes with the same priority take turns.
+        for i in 0..len {
+            let idx = (self.last_index.get() + 1 + i) % len;
+            processes[idx].as_ref().map(|process| {
+                if process.ready() {
+                    let priority = process.priority();
+                    if best.map_or(true, |(_, best_priority, _)| priority > best_priority) {
+                        best = Some((idx, priority, process.timeslice_us()));
+                    }
+                }
+            });
+        }
+
+        best.map(|(idx, _, timeslice_us)| {
+            self.last_index.set(idx);
+            (idx, Some(timeslice_us))
+        })
+    }
+}
+
+/// Cooperative scheduler.
+///
+/// Ready processes are run in index order, but are never pre-empted by the
+/// kernel. A process runs until it yields, so a process that never yields
+/// will starve all others. If the kernel is interrupted while a process is
+/// running, that same process is resumed after the interrupt is serviced.
+pub struct Cooperative {
+    last_index: Cell<usize>,
+}
+
+impl Cooperative {
+    pub fn new() -> Cooperative {
+        Cooperative {
+            last_index: Cell::new(0),
+        }
+    }
+}
+
+impl Scheduler for Cooperative {
+    fn next(&self, processes: &[Option<&mut Process<'static>>]) -> Option<(usize, Option<u32>)> {
+        let last = self.last_index.get();
+        let still_running = processes.get(last).map_or(false, |p| {
+            p.as_ref()
+                .map_or(false, |process| process.current_state() == State::Running)
+        });
+        if still_running {
+            return Some((last, None));
+        }
+
+        next_ready_from(processes, last + 1).map(|idx| {
+            self.last_index.set(idx);
+            (idx, None)
+        })
+    }
+}
@@ -0,0 +1,231 @@
+//! Simulating processes at the syscall layer, to test capsules on a host.
+//!
+//! A host machine cannot run the code of a process or trap into the kernel
+//! from it. But a capsule only sees a process through the syscalls it makes,
+//! the memory it shares and the callbacks it is sent, so a test can play the
+//! part of the process instead. A `Simulator` makes syscalls for processes
+//! that were loaded as usual, and passes them through the same permission
+//! checks to the same drivers as the main loop does. It also runs the part of
+//! the main loop that services the chip, so that the test decides in which
+//! order everything happens:
+//!
+//! ```rust
+//! let simulator = Simulator::new(kernel, &platform, processes, &main_loop_capability);
+//! simulator.subscribe(0, ALARM_DRIVER_NUM, 0, CALLBACK_PC, 0);
+//! simulator.command(0, ALARM_DRIVER_NUM, 5, 1000, 0);
+//! simulator.sleep(&mut chip);
+//! let callback = simulator.next_callback(0).unwrap();
+//! ```
+//!
+//! A simulated process starts as if it had already run its init function, and
+//! waits for callbacks. It never runs the callbacks it is sent; the test takes
+//! them with `next_callback` and decides what the process does next.
+//!
+//! The simulator can swap the kernel's processes and read and write their
+//! memory, so it is only built with the kernel's `simulator` feature, which
+//! the hosted test chip enables and boards must not.
+
+use core::ptr;
+
+use callback::AppId;
+use capabilities::MainLoopCapability;
+use memop;
+use platform::{Chip, Platform};
+use process::{self, FunctionCall, Process, Task};
+use returncode::ReturnCode;
+use sched::{self, Kernel};
+use syscall::Syscall;
+use trace;
+
+/// Makes syscalls on behalf of processes and runs the main loop step by step.
+pub struct Simulator<'a, P: Platform + 'a> {
+    kernel: &'static Kernel,
+    platform: &'a P,
+}
+
+impl<P: Platform> Simulator<'a, P> {
+    /// Simulate `processes`, which make their syscalls to the drivers of
+    /// `platform`. This takes the place of `Kernel::kernel_loop`.
+    pub fn new(
+        kernel: &'static Kernel,
+        platform: &'a P,
+        processes: &'static mut [Option<&mut Process<'static>>],
+        _capability: &MainLoopCapability,
+    ) -> Simulator<'a, P> {
+        let processes = unsafe {
+            process::PROCS = processes;
+            &mut process::PROCS
+        };
+        for process in processes.iter_mut().filter_map(|p| p.as_mut()) {
+            // There is no init function to run, so drop the call to it.
+            process.dequeue_task();
+        }
+
+        Simulator {
+            kernel: kernel,
+            platform: platform,
+        }
+    }
+
+    /// The `AppId` of the process at index `app_idx`, if there is one.
+    pub fn appid(&self, app_idx: usize) -> Option<AppId> {
+        self.with_process(app_idx, |process| process.appid(app_idx))
+    }
+
+    /// Have the process at index `app_idx` subscribe the function at
+    /// `callback_pc` to the upcall `subscribe_num` of driver `driver_num`.
+    /// A `callback_pc` of `0` unsubscribes.
+    pub fn subscribe(
+        &self,
+        app_idx: usize,
+        driver_num: usize,
+        subscribe_num: usize,
+        callback_pc: usize,
+        appdata: usize,
+    ) -> ReturnCode {
+        let args = [driver_num, subscribe_num, callback_pc, appdata];
+        self.driver_syscall(app_idx, Syscall::SUBSCRIBE, args)
+    }
+
+    /// Have the process at index `app_idx` issue command `command_num` to
+    /// driver `driver_num`.
+    pub fn command(
+        &self,
+        app_idx: usize,
+        driver_num: usize,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+    ) -> ReturnCode {
+        let args = [driver_num, command_num, arg1, arg2];
+        self.driver_syscall(app_idx, Syscall::COMMAND, args)
+    }
+
+    /// Have the process at index `app_idx` share the `size` bytes of its
+    /// memory at `address` with driver `driver_num` as buffer `allow_num`. An
+    /// `address` of `0` takes the buffer back.
+    pub fn allow(
+        &self,
+        app_idx: usize,
+        driver_num: usize,
+        allow_num: usize,
+        address: usize,
+        size: usize,
+    ) -> ReturnCode {
+        let args = [driver_num, allow_num, address, size];
+        self.driver_syscall(app_idx, Syscall::ALLOW, args)
+    }
+
+    /// Have the process at index `app_idx` make the memory operation
+    /// `op_type`, for example `1` to grow its memory by `arg` bytes.
+    pub fn memop(&self, app_idx: usize, op_type: usize, arg: usize) -> ReturnCode {
+        let args = [op_type, arg, 0, 0];
+        self.syscall(app_idx, Syscall::MEMOP, args, |process| {
+            memop::memop(process, op_type, arg)
+        })
+    }
+
+    /// Copy the memory of the process at index `app_idx` at `address` into
+    /// `buf`. Only memory below the app break can be read.
+    pub fn read_memory(&self, app_idx: usize, address: usize, buf: &mut [u8]) -> ReturnCode {
+        self.with_process(app_idx, |process| {
+            if owns(process, address, buf.len()) {
+                unsafe {
+                    ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len());
+                }
+                ReturnCode::SUCCESS
+            } else {
+                ReturnCode::EINVAL
+            }
+        }).unwrap_or(ReturnCode::EINVAL)
+    }
+
+    /// Copy `data` into the memory of the process at index `app_idx` at
+    /// `address`, as the process would write it. Only memory below the app
+    /// break can be written.
+    pub fn write_memory(&self, app_idx: usize, address: usize, data: &[u8]) -> ReturnCode {
+        self.with_process(app_idx, |process| {
+            if owns(process, address, data.len()) {
+                unsafe {
+                    ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len());
+                }
+                ReturnCode::SUCCESS
+            } else {
+                ReturnCode::EINVAL
+            }
+        }).unwrap_or(ReturnCode::EINVAL)
+    }
+
+    /// Take the next callback queued for the process at index `app_idx`, as
+    /// the process would when it yields. IPC notifications are dropped, since
+    /// the simulator does not run IPC.
+    pub fn next_callback(&self, app_idx: usize) -> Option<FunctionCall> {
+        self.with_process(app_idx, |process| {
+            while let Some(task) = process.dequeue_task() {
+                match task {
+                    Task::FunctionCall(call) => return Some(call),
+                    Task::IPC(_) => {}
+                }
+            }
+            None
+        }).unwrap_or(None)
+    }
+
+    /// Service interrupts and deferred calls until none are pending, as the
+    /// main loop does before it runs processes.
+    pub fn run_until_idle<C: Chip>(&self, chip: &mut C) {
+        while self.kernel.work_pending(chip) {
+            unsafe { self.kernel.service_pending_work(chip) };
+        }
+    }
+
+    /// Put `chip` to sleep, as the main loop does when there is nothing to
+    /// do, and service what woke it up.
+    pub fn sleep<C: Chip>(&self, chip: &mut C) {
+        self.run_until_idle(chip);
+        unsafe {
+            chip.atomic(|| chip.sleep());
+        }
+        self.run_until_idle(chip);
+    }
+
+    fn driver_syscall(&self, app_idx: usize, syscall: Syscall, args: [usize; 4]) -> ReturnCode {
+        let platform = self.platform;
+        self.syscall(app_idx, syscall, args, |process| unsafe {
+            sched::driver_syscall(platform, process, process.appid(app_idx), syscall, args)
+        })
+    }
+
+    /// Count and trace a syscall the process at index `app_idx` makes, which
+    /// `fun` carries out.
+    fn syscall<F>(&self, app_idx: usize, syscall: Syscall, args: [usize; 4], fun: F) -> ReturnCode
+    where
+        F: FnOnce(&mut Process<'static>) -> ReturnCode,
+    {
+        self.with_process(app_idx, |process| {
+            process.count_syscall(Some(syscall));
+            let res = fun(process);
+            trace::record(app_idx, syscall, args, Some(res));
+            res
+        }).unwrap_or(ReturnCode::EINVAL)
+    }
+
+    fn with_process<F, R>(&self, app_idx: usize, fun: F) -> Option<R>
+    where
+        F: FnOnce(&mut Process<'static>) -> R,
+    {
+        let procs = unsafe { &mut process::PROCS };
+        match procs.get_mut(app_idx) {
+            Some(Some(process)) => Some(fun(process)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the `size` bytes at `address` are below the app break of `process`.
+fn owns(process: &Process, address: usize, size: usize) -> bool {
+    address >= process.mem_start() as usize
+        && address
+            .checked_add(size)
+            .map_or(false, |end| end <= process.app_break() as usize)
+}
@@ -18,4 +18,28 @@ pub enum Syscall {
 
     /// Various memory operations.
     MEMOP = 4,
+
+    /// End the process, either for good or to start it over, with a
+    /// completion code.
+    EXIT = 5,
+}
+
+/// What the process asks for with the `EXIT` syscall, passed in `r0`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExitType {
+    /// Stop the process. It will not run again.
+    Terminate = 0,
+
+    /// Start the process over from its entry point.
+    Restart = 1,
+}
+
+impl ExitType {
+    pub fn from_usize(exit_type: usize) -> Option<ExitType> {
+        match exit_type {
+            0 => Some(ExitType::Terminate),
+            1 => Some(ExitType::Restart),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,238 @@
+//! Tracing the syscalls processes make.
+//!
+//! A `SyscallTrace` keeps the most recent syscalls in a ring, overwriting the
+//! oldest ones when it is full. Each record holds the process, the syscall,
+//! its arguments, what it returned and when it was made. This helps to find
+//! out, for example, why an app stopped getting callbacks: whether it ever
+//! subscribed, and what the driver returned when it did.
+//!
+//! Tracing is optional. A board that wants it gives the kernel a trace with
+//! `Kernel::set_syscall_trace`:
+//!
+//! ```rust
+//! static mut SYSCALL_TRACE_BUF: [kernel::procs::SyscallRecord; 64] =
+//!     [kernel::procs::SyscallRecord::EMPTY; 64];
+//!
+//! let syscall_trace = static_init!(
+//!     kernel::procs::SyscallTrace,
+//!     kernel::procs::SyscallTrace::new(&mut SYSCALL_TRACE_BUF, Some(virtual_alarm))
+//! );
+//! board_kernel.set_syscall_trace(syscall_trace, &kernel_configuration_capability);
+//! ```
+//!
+//! The trace is printed after the process status when the kernel panics, and
+//! can be printed at any time with `SyscallTrace::debug_print`.
+
+use core::cell::Cell;
+use core::fmt::Write;
+use core::mem;
+
+use common::cells::TakeCell;
+use hil::time::Alarm;
+use returncode::ReturnCode;
+use syscall::Syscall;
+
+/// The trace the kernel records syscalls in, if the board set one.
+static mut SYSCALL_TRACE: Option<&'static SyscallTrace> = None;
+
+pub(crate) fn set_syscall_trace(syscall_trace: &'static SyscallTrace) {
+    unsafe {
+        SYSCALL_TRACE = Some(syscall_trace);
+    }
+}
+
+pub(crate) fn syscall_trace() -> Option<&'static SyscallTrace> {
+    unsafe { SYSCALL_TRACE }
+}
+
+/// Record a syscall in the kernel's trace, if there is one.
+pub(crate) fn record(
+    app_idx: usize,
+    syscall: Syscall,
+    args: [usize; 4],
+    return_code: Option<ReturnCode>,
+) {
+    syscall_trace().map(|syscall_trace| {
+        syscall_trace.record(app_idx, syscall, args, return_code);
+    });
+}
+
+/// Source of timestamps for syscall records.
+pub trait TraceClock {
+    /// The current time, in ticks.
+    fn now(&self) -> u32;
+}
+
+impl<A: Alarm> TraceClock for A {
+    fn now(&self) -> u32 {
+        Alarm::now(self)
+    }
+}
+
+/// One syscall made by a process.
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallRecord {
+    /// The index of the process that made the syscall.
+    pub app_idx: usize,
+    pub syscall: Syscall,
+    /// The driver number for `subscribe`, `command` and `allow`, and the
+    /// first argument of other syscalls.
+    pub driver_num: usize,
+    /// The remaining arguments in `r1` to `r3`.
+    pub args: [usize; 3],
+    /// What the syscall returned to the process. `yield` and `exit` do not
+    /// return a code.
+    pub return_code: Option<ReturnCode>,
+    /// When the syscall was made, in ticks of the trace's clock, or `0` if
+    /// the trace has no clock.
+    pub timestamp: u32,
+}
+
+impl SyscallRecord {
+    /// Initial value for the records of a trace.
+    pub const EMPTY: SyscallRecord = SyscallRecord {
+        app_idx: 0,
+        syscall: Syscall::YIELD,
+        driver_num: 0,
+        args: [0; 3],
+        return_code: None,
+        timestamp: 0,
+    };
+}
+
+/// Ring of the most recent syscalls.
+pub struct SyscallTrace {
+    records: TakeCell<'static, [SyscallRecord]>,
+    /// Where the next record is written.
+    next: Cell<usize>,
+    /// How many of the records are valid.
+    len: Cell<usize>,
+    /// Bit `i` set means the process with index `i` is traced.
+    filter: Cell<usize>,
+    clock: Option<&'static TraceClock>,
+}
+
+impl SyscallTrace {
+    /// Create a trace that keeps as many syscalls as `records` can hold,
+    /// timestamped with `clock`. All processes are traced.
+    pub fn new(
+        records: &'static mut [SyscallRecord],
+        clock: Option<&'static TraceClock>,
+    ) -> SyscallTrace {
+        SyscallTrace {
+            records: TakeCell::new(records),
+            next: Cell::new(0),
+            len: Cell::new(0),
+            filter: Cell::new(!0),
+            clock: clock,
+        }
+    }
+
+    /// Only trace the processes whose bit is set in `app_mask`, where bit `i`
+    /// stands for the process with index `i`. Processes with an index too
+    /// large for the mask are traced only if every bit is set.
+    pub fn set_filter(&self, app_mask: usize) {
+        self.filter.set(app_mask);
+    }
+
+    pub fn filter(&self) -> usize {
+        self.filter.get()
+    }
+
+    fn is_traced(&self, app_idx: usize) -> bool {
+        let filter = self.filter.get();
+        filter == !0 || (app_idx < mem::size_of::<usize>() * 8 && filter & (1 << app_idx) != 0)
+    }
+
+    fn record(
+        &self,
+        app_idx: usize,
+        syscall: Syscall,
+        args: [usize; 4],
+        return_code: Option<ReturnCode>,
+    ) {
+        if !self.is_traced(app_idx) {
+            return;
+        }
+
+        let timestamp = self.clock.map_or(0, |clock| clock.now());
+        self.records.map(|records| {
+            if records.is_empty() {
+                return;
+            }
+            let next = self.next.get();
+            records[next] = SyscallRecord {
+                app_idx: app_idx,
+                syscall: syscall,
+                driver_num: args[0],
+                args: [args[1], args[2], args[3]],
+                return_code: return_code,
+                timestamp: timestamp,
+            };
+            self.next.set((next + 1) % records.len());
+            if self.len.get() < records.len() {
+                self.len.set(self.len.get() + 1);
+            }
+        });
+    }
+
+    /// How many syscalls the trace holds.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// The `index`th oldest syscall in the trace.
+    pub fn get(&self, index: usize) -> Option<SyscallRecord> {
+        let len = self.len.get();
+        if index >= len {
+            return None;
+        }
+        self.records.map_or(None, |records| {
+            let oldest = (self.next.get() + records.len() - len) % records.len();
+            Some(records[(oldest + index) % records.len()])
+        })
+    }
+
+    /// Forget every syscall in the trace.
+    pub fn clear(&self) {
+        self.len.set(0);
+    }
+
+    /// Print the syscalls in the trace, oldest first, with `debug!`.
+    pub fn debug_print(&self) {
+        for index in 0..self.len() {
+            self.get(index).map(|record| {
+                debug!(
+                    "[{}] app {} {:?} {:#x} {:#x} {:#x} {:#x} -> {:?}",
+                    record.timestamp,
+                    record.app_idx,
+                    record.syscall,
+                    record.driver_num,
+                    record.args[0],
+                    record.args[1],
+                    record.args[2],
+                    record.return_code
+                );
+            });
+        }
+    }
+
+    /// Print the syscalls in the trace, oldest first, to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) {
+        for index in 0..self.len() {
+            self.get(index).map(|record| {
+                let _ = writer.write_fmt(format_args!(
+                    " [{}] app {} {:?} {:#x} {:#x} {:#x} {:#x} -> {:?}\r\n",
+                    record.timestamp,
+                    record.app_idx,
+                    record.syscall,
+                    record.driver_num,
+                    record.args[0],
+                    record.args[1],
+                    record.args[2],
+                    record.return_code
+                ));
+            });
+        }
+    }
+}
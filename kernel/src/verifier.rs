@@ -0,0 +1,89 @@
+//! Checking app credentials before apps are started.
+//!
+//! Apps can carry credentials, such as a digest or a signature, in footers
+//! after their binary. A board that wants to only run trusted apps passes an
+//! `AppVerifier` to `procs::load_processes`, which then asks it to check each
+//! app's credentials. Apps that the verifier does not accept are skipped and
+//! reported on the debug console.
+//!
+//! The kernel does not implement any cryptography itself. The board's
+//! verifier decides which credential formats it supports, for example by using
+//! a hardware hash engine, and which keys it trusts.
+//! `capsules::sha256_verifier` checks SHA-256 digests in software.
+
+pub use tock_tbf::types::{TbfFooterV2Credentials, TbfFooterV2CredentialsType};
+
+use tbfheader::{TbfHeader, TbfParseError};
+
+/// What a verifier decided about a single credential.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CredentialCheck {
+    /// The credential is valid and the app may run.
+    Accept,
+    /// The credential is invalid, for example because the binary was modified
+    /// after it was signed. The app must not run.
+    Reject,
+    /// The verifier does not handle this credential, for example because it
+    /// is in a format the verifier does not support or is signed with an
+    /// unknown key. The next credential is checked instead.
+    Pass,
+}
+
+/// Board-supplied policy for which apps may run.
+pub trait AppVerifier {
+    /// Check one credential from the footer of an app.
+    ///
+    /// `binary` is the part of the app that credentials cover: from the start
+    /// of the TBF header to the end of the app binary.
+    fn check_credentials(
+        &self,
+        credentials: &TbfFooterV2Credentials,
+        binary: &[u8],
+    ) -> CredentialCheck;
+
+    /// Whether an app may run if none of its credentials were accepted,
+    /// including apps with no credentials at all.
+    fn require_credentials(&self) -> bool {
+        true
+    }
+}
+
+/// Reasons an app was not verified.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerificationError {
+    /// The footers of the app could not be parsed.
+    BadFooter(TbfParseError),
+    /// The verifier rejected a credential of the given format.
+    Rejected(TbfFooterV2CredentialsType),
+    /// The verifier requires credentials but accepted none of the app's.
+    NoAcceptedCredentials,
+}
+
+/// Check the credentials of the app in `app`, which holds the whole app as
+/// described by `header`. Credentials are checked in the order they appear in
+/// the footers, and the first one the verifier accepts or rejects decides.
+pub(crate) fn verify_app(
+    app_verifier: &AppVerifier,
+    header: &TbfHeader,
+    app: &[u8],
+) -> Result<(), VerificationError> {
+    let binary_end = header.get_binary_end() as usize;
+    let (binary, footers) = app.split_at(binary_end);
+
+    for credentials in ::tock_tbf::parse::parse_tbf_footer_credentials(footers) {
+        let credentials = credentials.map_err(VerificationError::BadFooter)?;
+        match app_verifier.check_credentials(&credentials, binary) {
+            CredentialCheck::Accept => return Ok(()),
+            CredentialCheck::Reject => {
+                return Err(VerificationError::Rejected(credentials.format()))
+            }
+            CredentialCheck::Pass => {}
+        }
+    }
+
+    if app_verifier.require_credentials() {
+        Err(VerificationError::NoAcceptedCredentials)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Resetting the chip when the kernel stops making progress.
+//!
+//! A board can give the kernel a hardware watchdog with
+//! `Kernel::set_watchdog`:
+//!
+//! ```rust
+//! let watchdog = static_init!(
+//!     kernel::procs::KernelWatchdog,
+//!     kernel::procs::KernelWatchdog::new(&sam4l::wdt::WDT, 1000, true)
+//! );
+//! board_kernel.set_watchdog(watchdog, &kernel_configuration_capability);
+//! ```
+//!
+//! The kernel starts the watchdog when the main loop starts, and tickles it
+//! only when the scheduler makes progress: after servicing interrupts and
+//! after each process it runs gives the CPU back. If the kernel gets stuck,
+//! for example in a driver busy-waiting on a peripheral, or in a process that
+//! never yields under a cooperative scheduler, the watchdog resets the chip.
+//!
+//! The kernel makes no progress while the chip sleeps, so it stops the
+//! watchdog right before `Chip::sleep` and starts it again as soon as the chip
+//! wakes up, before any interrupt is serviced. This happens with interrupts
+//! disabled and only when there is nothing left to do, so the watchdog still
+//! catches a kernel that is stuck anywhere but in the sleep instruction.
+//!
+//! If the board asks for it, the kernel also notes which process it is running
+//! in RAM that is not cleared on reset. After the chip reboots,
+//! `last_running_process` returns the index of the process that was running
+//! when it reset, if any. The kernel does not know why the chip reset, so the
+//! board should check the chip's reset cause before blaming the process.
+
+use core::cell::Cell;
+use core::ptr;
+
+use hil::watchdog::Watchdog;
+
+/// Marks a valid `RunningRecord`, since RAM holds garbage after power-on.
+const RECORD_MAGIC: usize = 0x5744_5452;
+
+/// Stands for "no process" in `RunningRecord::app_idx`.
+const NO_PROCESS: usize = usize::max_value();
+
+/// The process the kernel is running, kept across resets.
+#[repr(C)]
+struct RunningRecord {
+    magic: usize,
+    app_idx: usize,
+}
+
+/// Placed in the `.noinit` section, which the startup code neither loads nor
+/// zeroes.
+#[link_section = ".noinit"]
+static mut RUNNING_RECORD: RunningRecord = RunningRecord {
+    magic: 0,
+    app_idx: NO_PROCESS,
+};
+
+/// What `RUNNING_RECORD` held when the board set the watchdog.
+static mut LAST_RUNNING_PROCESS: Option<usize> = None;
+
+/// The index of the process that was running when the chip last reset, if
+/// the kernel was recording it.
+pub fn last_running_process() -> Option<usize> {
+    unsafe { LAST_RUNNING_PROCESS }
+}
+
+/// A hardware watchdog, and how the kernel uses it.
+pub struct KernelWatchdog {
+    watchdog: &'static Watchdog,
+    /// Time in milliseconds after which the watchdog resets the chip if it is
+    /// not tickled.
+    period: usize,
+    record_running_process: bool,
+    /// Whether the watchdog is running, as opposed to stopped for sleep.
+    running: Cell<bool>,
+}
+
+impl KernelWatchdog {
+    /// Reset the chip if the kernel makes no progress for `period`
+    /// milliseconds. With `record_running_process` the kernel notes which
+    /// process it runs, for `last_running_process` after the reset.
+    pub const fn new(
+        watchdog: &'static Watchdog,
+        period: usize,
+        record_running_process: bool,
+    ) -> KernelWatchdog {
+        KernelWatchdog {
+            watchdog: watchdog,
+            period: period,
+            record_running_process: record_running_process,
+            running: Cell::new(false),
+        }
+    }
+
+    /// Keep what the record held before this boot, and start a new one.
+    pub(crate) fn take_record(&self) {
+        if !self.record_running_process {
+            return;
+        }
+        unsafe {
+            let record = &mut RUNNING_RECORD;
+            let magic = ptr::read_volatile(&record.magic);
+            let app_idx = ptr::read_volatile(&record.app_idx);
+            if magic == RECORD_MAGIC && app_idx != NO_PROCESS {
+                LAST_RUNNING_PROCESS = Some(app_idx);
+            }
+            ptr::write_volatile(&mut record.app_idx, NO_PROCESS);
+            ptr::write_volatile(&mut record.magic, RECORD_MAGIC);
+        }
+    }
+
+    pub(crate) fn start(&self) {
+        self.watchdog.start(self.period);
+        self.running.set(true);
+    }
+
+    /// The kernel made progress.
+    pub(crate) fn tickle(&self) {
+        if self.running.get() {
+            self.watchdog.tickle();
+        }
+    }
+
+    /// The chip is about to sleep.
+    pub(crate) fn suspend(&self) {
+        if self.running.get() {
+            self.watchdog.stop();
+            self.running.set(false);
+        }
+    }
+
+    /// The chip woke up.
+    pub(crate) fn resume(&self) {
+        if !self.running.get() {
+            self.start();
+        }
+    }
+
+    /// Note that the process with index `app_idx`, or no process, runs.
+    pub(crate) fn set_running_process(&self, app_idx: Option<usize>) {
+        if self.record_running_process {
+            unsafe {
+                ptr::write_volatile(&mut RUNNING_RECORD.app_idx, app_idx.unwrap_or(NO_PROCESS));
+            }
+        }
+    }
+}
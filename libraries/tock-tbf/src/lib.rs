@@ -0,0 +1,14 @@
+//! Tock Binary Format header types and parsing.
+//!
+//! This crate has no dependencies on the rest of Tock and works on plain byte
+//! slices, so it can be used both by the kernel and by tools on a host
+//! machine.
+
+#![forbid(unsafe_code)]
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod parse;
+pub mod types;
@@ -0,0 +1,757 @@
+//! Bounds-checked parsing of TBF headers from byte slices.
+//!
+//! All multi-byte fields in a TBF header are little-endian. Every read is
+//! checked against the length of the slice, so a malformed or truncated header
+//! results in a `TbfParseError` rather than a read past the end of the app.
+
+use core::{mem, str};
+
+use types::{
+    TbfFooterTypes, TbfFooterV2Credentials, TbfFooterV2CredentialsType, TbfHeader, TbfHeaderTypes,
+    TbfHeaderV1, TbfHeaderV2, TbfHeaderV2Base, TbfHeaderV2DriverPermission, TbfHeaderV2Footer,
+    TbfHeaderV2Main, TbfHeaderV2MaxRestarts, TbfHeaderV2Priority, TbfHeaderV2Timeslice,
+    TbfHeaderV2WriteableFlashRegion, TbfParseError,
+};
+
+/// Takes a value and rounds it up to be aligned % 4
+macro_rules! align4 {
+    ($e:expr) => {
+        ($e) + ((4 - (($e) % 4)) % 4)
+    };
+}
+
+/// Largest total size an app may claim. This is larger than the flash of any
+/// chip Tock runs on, so anything bigger is a corrupt header.
+const MAX_TOTAL_SIZE: u32 = 0x10000000;
+
+/// Size of the TLV type and length fields that start each TLV entry.
+const TLV_HEADER_SIZE: usize = 4;
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16, TbfParseError> {
+    match buf.get(offset..offset + 2) {
+        Some(b) => Ok((b[0] as u16) | (b[1] as u16) << 8),
+        None => Err(TbfParseError::NotEnoughData),
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32, TbfParseError> {
+    match buf.get(offset..offset + 4) {
+        Some(b) => {
+            Ok((b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+        }
+        None => Err(TbfParseError::NotEnoughData),
+    }
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> Result<u64, TbfParseError> {
+    let low = read_u32(buf, offset)? as u64;
+    let high = read_u32(buf, offset + 4)? as u64;
+    Ok(low | high << 32)
+}
+
+/// Check that a fixed-size TLV entry has exactly the expected length.
+fn check_tlv_length<T>(b: &[u8], tipe: TbfHeaderTypes) -> Result<(), TbfParseError> {
+    if b.len() == mem::size_of::<T>() {
+        Ok(())
+    } else {
+        Err(TbfParseError::BadTlvLength(tipe as u16))
+    }
+}
+
+impl TbfHeaderV1 {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV1, TbfParseError> {
+        Ok(TbfHeaderV1 {
+            version: read_u32(b, 0)?,
+            total_size: read_u32(b, 4)?,
+            entry_offset: read_u32(b, 8)?,
+            rel_data_offset: read_u32(b, 12)?,
+            rel_data_size: read_u32(b, 16)?,
+            text_offset: read_u32(b, 20)?,
+            text_size: read_u32(b, 24)?,
+            got_offset: read_u32(b, 28)?,
+            got_size: read_u32(b, 32)?,
+            data_offset: read_u32(b, 36)?,
+            data_size: read_u32(b, 40)?,
+            bss_mem_offset: read_u32(b, 44)?,
+            bss_size: read_u32(b, 48)?,
+            min_stack_len: read_u32(b, 52)?,
+            min_app_heap_len: read_u32(b, 56)?,
+            min_kernel_heap_len: read_u32(b, 60)?,
+            pkg_name_offset: read_u32(b, 64)?,
+            pkg_name_size: read_u32(b, 68)?,
+            checksum: read_u32(b, 72)?,
+        })
+    }
+
+    /// The checksum is the XOR of every field except the checksum itself.
+    fn compute_checksum(&self) -> u32 {
+        self.version
+            ^ self.total_size
+            ^ self.entry_offset
+            ^ self.rel_data_offset
+            ^ self.rel_data_size
+            ^ self.text_offset
+            ^ self.text_size
+            ^ self.got_offset
+            ^ self.got_size
+            ^ self.data_offset
+            ^ self.data_size
+            ^ self.bss_mem_offset
+            ^ self.bss_size
+            ^ self.min_stack_len
+            ^ self.min_app_heap_len
+            ^ self.min_kernel_heap_len
+            ^ self.pkg_name_offset
+            ^ self.pkg_name_size
+    }
+}
+
+impl TbfHeaderV2Base {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2Base, TbfParseError> {
+        Ok(TbfHeaderV2Base {
+            version: read_u16(b, 0)?,
+            header_size: read_u16(b, 2)?,
+            total_size: read_u32(b, 4)?,
+            flags: read_u32(b, 8)?,
+            checksum: read_u32(b, 12)?,
+        })
+    }
+}
+
+impl TbfHeaderV2Main {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2Main, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2Main>(b, TbfHeaderTypes::TbfHeaderMain)?;
+        Ok(TbfHeaderV2Main {
+            init_fn_offset: read_u32(b, 0)?,
+            protected_size: read_u32(b, 4)?,
+            minimum_ram_size: read_u32(b, 8)?,
+        })
+    }
+}
+
+impl TbfHeaderV2WriteableFlashRegion {
+    pub(crate) fn parse(b: &[u8]) -> Result<TbfHeaderV2WriteableFlashRegion, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2WriteableFlashRegion>(
+            b,
+            TbfHeaderTypes::TbfHeaderWriteableFlashRegions,
+        )?;
+        Ok(TbfHeaderV2WriteableFlashRegion {
+            writeable_flash_region_offset: read_u32(b, 0)?,
+            writeable_flash_region_size: read_u32(b, 4)?,
+        })
+    }
+}
+
+impl TbfHeaderV2Priority {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2Priority, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2Priority>(b, TbfHeaderTypes::TbfHeaderPriority)?;
+        Ok(TbfHeaderV2Priority {
+            priority: read_u32(b, 0)?,
+        })
+    }
+}
+
+impl TbfHeaderV2Timeslice {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2Timeslice, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2Timeslice>(b, TbfHeaderTypes::TbfHeaderTimeslice)?;
+        Ok(TbfHeaderV2Timeslice {
+            timeslice_us: read_u32(b, 0)?,
+        })
+    }
+}
+
+impl TbfHeaderV2MaxRestarts {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2MaxRestarts, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2MaxRestarts>(b, TbfHeaderTypes::TbfHeaderMaxRestarts)?;
+        Ok(TbfHeaderV2MaxRestarts {
+            max_restarts: read_u32(b, 0)?,
+        })
+    }
+}
+
+impl TbfHeaderV2DriverPermission {
+    pub(crate) fn parse(b: &[u8]) -> Result<TbfHeaderV2DriverPermission, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2DriverPermission>(b, TbfHeaderTypes::TbfHeaderPermissions)?;
+        Ok(TbfHeaderV2DriverPermission {
+            driver_number: read_u32(b, 0)?,
+            offset: read_u32(b, 4)?,
+            allowed_commands: read_u64(b, 8)?,
+        })
+    }
+}
+
+impl TbfHeaderV2Footer {
+    fn parse(b: &[u8]) -> Result<TbfHeaderV2Footer, TbfParseError> {
+        check_tlv_length::<TbfHeaderV2Footer>(b, TbfHeaderTypes::TbfHeaderFooter)?;
+        Ok(TbfHeaderV2Footer {
+            binary_end_offset: read_u32(b, 0)?,
+        })
+    }
+}
+
+impl<'a> TbfFooterV2Credentials<'a> {
+    /// Parse a credential, or return `None` if it is in a format this parser
+    /// does not understand.
+    fn parse(b: &'a [u8]) -> Result<Option<TbfFooterV2Credentials<'a>>, TbfParseError> {
+        let format = match TbfFooterV2CredentialsType::from_u32(read_u32(b, 0)?) {
+            Some(format) => format,
+            None => return Ok(None),
+        };
+        if b.len() != 4 + format.data_length() {
+            return Err(TbfParseError::BadTlvLength(
+                TbfFooterTypes::TbfFooterCredentials as u16,
+            ));
+        }
+        Ok(Some(TbfFooterV2Credentials {
+            format: format,
+            data: &b[4..],
+        }))
+    }
+}
+
+/// Read the version, header size and total size of the TBF at the start of
+/// `app`.
+///
+/// Only the first 8 bytes of `app` are needed. This lets a caller that only
+/// has a pointer to flash find out how large the app is before handing the
+/// whole app to `parse_tbf_header()`.
+pub fn parse_tbf_header_lengths(app: &[u8]) -> Result<(u16, u16, u32), TbfParseError> {
+    let version = read_u16(app, 0)?;
+    let header_size = match version {
+        1 => mem::size_of::<TbfHeaderV1>() as u16,
+        2 => read_u16(app, 2)?,
+        _ => return Err(TbfParseError::UnknownVersion(version)),
+    };
+    let total_size = read_u32(app, 4)?;
+
+    // Make sure the header isn't longer than the total app, and that the total
+    // app fits inside a reasonable size of flash.
+    if (header_size as usize) < mem::size_of::<TbfHeaderV2Base>()
+        || header_size as u32 >= total_size
+        || total_size > MAX_TOTAL_SIZE
+    {
+        return Err(TbfParseError::SizeOverflow);
+    }
+
+    Ok((version, header_size, total_size))
+}
+
+/// Parse and validate the TBF header at the start of `app`.
+///
+/// `app` must contain at least the whole header. Version 1 headers store the
+/// package name elsewhere in the app, so for them `app` should cover the
+/// whole app if the name is wanted.
+///
+/// This validates the header checksum and that every TLV entry fits inside the
+/// header, but does not perform any other sanity or security checking on the
+/// contents.
+pub fn parse_tbf_header(app: &[u8]) -> Result<TbfHeader, TbfParseError> {
+    let (version, header_size, _) = parse_tbf_header_lengths(app)?;
+    let header = app
+        .get(0..header_size as usize)
+        .ok_or(TbfParseError::NotEnoughData)?;
+
+    match version {
+        1 => parse_tbf_header_v1(app, header),
+        2 => parse_tbf_header_v2(header),
+        _ => Err(TbfParseError::UnknownVersion(version)),
+    }
+}
+
+fn parse_tbf_header_v1<'a>(app: &'a [u8], header: &[u8]) -> Result<TbfHeader<'a>, TbfParseError> {
+    let tbf_header = TbfHeaderV1::parse(header)?;
+
+    let checksum = tbf_header.compute_checksum();
+    if checksum != tbf_header.checksum {
+        return Err(TbfParseError::ChecksumMismatch {
+            stored: tbf_header.checksum,
+            computed: checksum,
+        });
+    }
+
+    if tbf_header.minimum_ram_size().is_none() {
+        return Err(TbfParseError::SizeOverflow);
+    }
+
+    let name_start = tbf_header.pkg_name_offset as usize;
+    let name_end = name_start.saturating_add(tbf_header.pkg_name_size as usize);
+    let package_name = app
+        .get(name_start..name_end)
+        .and_then(|name| str::from_utf8(name).ok());
+
+    Ok(TbfHeader::TbfHeaderV1(tbf_header, package_name))
+}
+
+fn parse_tbf_header_v2(header: &[u8]) -> Result<TbfHeader, TbfParseError> {
+    let tbf_header_base = TbfHeaderV2Base::parse(header)?;
+
+    // Calculate checksum. The checksum is the XOR of each 4 byte word in the
+    // header, skipping the checksum field itself. A trailing partial word is
+    // padded with zeros.
+    let mut checksum: u32 = 0;
+    for (i, chunk) in header.chunks(4).enumerate() {
+        if i == 3 {
+            // Skip the checksum field.
+            continue;
+        }
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        checksum ^= read_u32(&word, 0)?;
+    }
+
+    if checksum != tbf_header_base.checksum {
+        return Err(TbfParseError::ChecksumMismatch {
+            stored: tbf_header_base.checksum,
+            computed: checksum,
+        });
+    }
+
+    // Check if this is a real app or just padding. Padding apps are
+    // identified by not having any options.
+    let mut offset = mem::size_of::<TbfHeaderV2Base>();
+    if header.len() == offset {
+        return Ok(TbfHeader::Padding(tbf_header_base));
+    }
+
+    // This is an actual app.
+
+    // Places to save fields that we parse out of the header options.
+    let mut tbf_header = TbfHeaderV2 {
+        base: tbf_header_base,
+        main: None,
+        package_name: None,
+        writeable_regions: None,
+        priority: None,
+        timeslice: None,
+        max_restarts: None,
+        permissions: None,
+        footer: None,
+    };
+
+    // Loop through the header looking for known options.
+    while offset + TLV_HEADER_SIZE <= header.len() {
+        let tipe = read_u16(header, offset)?;
+        let length = read_u16(header, offset + 2)? as usize;
+        offset += TLV_HEADER_SIZE;
+
+        let data = header
+            .get(offset..offset + length)
+            .ok_or(TbfParseError::TruncatedTlv(tipe))?;
+
+        // Only parse known TLV blocks. Unknown types are skipped.
+        match TbfHeaderTypes::from_u16(tipe) {
+            Some(TbfHeaderTypes::TbfHeaderMain) => {
+                tbf_header.main = Some(TbfHeaderV2Main::parse(data)?);
+            }
+            Some(TbfHeaderTypes::TbfHeaderWriteableFlashRegions) => {
+                // Length must be a multiple of the size of a region definition.
+                if length % mem::size_of::<TbfHeaderV2WriteableFlashRegion>() != 0 {
+                    return Err(TbfParseError::BadTlvLength(tipe));
+                }
+                tbf_header.writeable_regions = Some(data);
+            }
+            Some(TbfHeaderTypes::TbfHeaderPackageName) => {
+                tbf_header.package_name = str::from_utf8(data).ok();
+            }
+            Some(TbfHeaderTypes::TbfHeaderPriority) => {
+                tbf_header.priority = Some(TbfHeaderV2Priority::parse(data)?);
+            }
+            Some(TbfHeaderTypes::TbfHeaderTimeslice) => {
+                tbf_header.timeslice = Some(TbfHeaderV2Timeslice::parse(data)?);
+            }
+            Some(TbfHeaderTypes::TbfHeaderMaxRestarts) => {
+                tbf_header.max_restarts = Some(TbfHeaderV2MaxRestarts::parse(data)?);
+            }
+            Some(TbfHeaderTypes::TbfHeaderPermissions) => {
+                // Length must be a multiple of the size of a permission entry.
+                if length % mem::size_of::<TbfHeaderV2DriverPermission>() != 0 {
+                    return Err(TbfParseError::BadTlvLength(tipe));
+                }
+                tbf_header.permissions = Some(data);
+            }
+            Some(TbfHeaderTypes::TbfHeaderFooter) => {
+                let footer = TbfHeaderV2Footer::parse(data)?;
+                // The binary must end after the header and footers must fit
+                // inside the app.
+                if footer.binary_end_offset < header.len() as u32
+                    || footer.binary_end_offset > tbf_header_base.total_size
+                {
+                    return Err(TbfParseError::SizeOverflow);
+                }
+                tbf_header.footer = Some(footer);
+            }
+            None => {}
+        }
+
+        // All TLV blocks are padded to 4 bytes, so we need to skip more if the
+        // length is not a multiple of 4.
+        offset += align4!(length);
+    }
+
+    if tbf_header.init_fn_offset().is_none() || tbf_header.protected_size().is_none() {
+        return Err(TbfParseError::SizeOverflow);
+    }
+
+    Ok(TbfHeader::TbfHeaderV2(tbf_header))
+}
+
+/// Iterate over the credentials in the footers of an app.
+///
+/// `footers` is the part of the app from `TbfHeader::get_binary_end()` to its
+/// total size. Footers that are not credentials, and credentials in formats
+/// this parser does not understand, are skipped.
+pub fn parse_tbf_footer_credentials(footers: &[u8]) -> TbfFooterCredentialsIter {
+    TbfFooterCredentialsIter {
+        footers: footers,
+        offset: 0,
+    }
+}
+
+/// Iterator returned by `parse_tbf_footer_credentials()`.
+///
+/// After an error the iterator ends, since the position of the next footer is
+/// no longer known.
+pub struct TbfFooterCredentialsIter<'a> {
+    footers: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for TbfFooterCredentialsIter<'a> {
+    type Item = Result<TbfFooterV2Credentials<'a>, TbfParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset + TLV_HEADER_SIZE <= self.footers.len() {
+            let tipe = match read_u16(self.footers, self.offset) {
+                Ok(tipe) => tipe,
+                Err(err) => return self.fail(err),
+            };
+            let length = match read_u16(self.footers, self.offset + 2) {
+                Ok(length) => length as usize,
+                Err(err) => return self.fail(err),
+            };
+            let start = self.offset + TLV_HEADER_SIZE;
+            let data = match self.footers.get(start..start + length) {
+                Some(data) => data,
+                None => return self.fail(TbfParseError::TruncatedTlv(tipe)),
+            };
+            self.offset = start + align4!(length);
+
+            if tipe == TbfFooterTypes::TbfFooterCredentials as u16 {
+                match TbfFooterV2Credentials::parse(data) {
+                    Ok(Some(credentials)) => return Some(Ok(credentials)),
+                    Ok(None) => {}
+                    Err(err) => return self.fail(err),
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a> TbfFooterCredentialsIter<'a> {
+    fn fail(
+        &mut self,
+        err: TbfParseError,
+    ) -> Option<Result<TbfFooterV2Credentials<'a>, TbfParseError>> {
+        self.offset = self.footers.len();
+        Some(Err(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+    use types::DefaultPermissions;
+
+    fn push_u32(buf: &mut Vec<u8>, value: u32) {
+        buf.extend_from_slice(&[
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ]);
+    }
+
+    fn push_tlv(buf: &mut Vec<u8>, tipe: u16, data: &[u8]) {
+        push_u32(buf, tipe as u32 | (data.len() as u32) << 16);
+        buf.extend_from_slice(data);
+        while buf.len() % 4 != 0 {
+            buf.push(0);
+        }
+    }
+
+    fn main_tlv(init_fn_offset: u32, protected_size: u32, minimum_ram_size: u32) -> Vec<u8> {
+        let mut main = Vec::new();
+        push_u32(&mut main, init_fn_offset);
+        push_u32(&mut main, protected_size);
+        push_u32(&mut main, minimum_ram_size);
+        main
+    }
+
+    /// A v2 app of `total_size` bytes whose header holds `tlvs`, with a
+    /// correct checksum.
+    fn v2_app(tlvs: &[u8], total_size: u32) -> Vec<u8> {
+        let header_size = 16 + tlvs.len() as u32;
+        let mut app = Vec::new();
+        push_u32(&mut app, 2 | header_size << 16);
+        push_u32(&mut app, total_size);
+        // Enabled.
+        push_u32(&mut app, 1);
+        // Checksum, filled in below.
+        push_u32(&mut app, 0);
+        app.extend_from_slice(tlvs);
+        let checksum = app
+            .chunks(4)
+            .map(|word| read_u32(word, 0).unwrap())
+            .fold(0, |checksum, word| checksum ^ word);
+        app[12..16].copy_from_slice(&[
+            checksum as u8,
+            (checksum >> 8) as u8,
+            (checksum >> 16) as u8,
+            (checksum >> 24) as u8,
+        ]);
+        app.resize(total_size as usize, 0);
+        app
+    }
+
+    /// A v1 app with the given fields after the version and total size, and
+    /// a correct checksum. `fields[14]` and `fields[15]` are the offset and
+    /// size of the package name.
+    fn v1_app(fields: &[u32; 16], total_size: u32) -> Vec<u8> {
+        let mut app = Vec::new();
+        push_u32(&mut app, 1);
+        push_u32(&mut app, total_size);
+        let mut checksum = 1 ^ total_size;
+        for &field in fields.iter() {
+            push_u32(&mut app, field);
+            checksum ^= field;
+        }
+        push_u32(&mut app, checksum);
+        app.resize(total_size as usize, 0);
+        app
+    }
+
+    #[test]
+    fn parses_v2_header() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0x10, 4096));
+        push_tlv(&mut tlvs, 3, b"blink");
+        let mut permission = Vec::new();
+        push_u32(&mut permission, 0x1);
+        push_u32(&mut permission, 0);
+        push_u32(&mut permission, 0b101);
+        push_u32(&mut permission, 0);
+        push_tlv(&mut tlvs, 8, &permission);
+        let app = v2_app(&tlvs, 1024);
+
+        let header = parse_tbf_header(&app).unwrap();
+        let header_size = 16 + tlvs.len() as u32;
+        assert!(header.is_app());
+        assert!(header.enabled());
+        assert_eq!(header.get_total_size(), 1024);
+        assert_eq!(header.get_package_name(), "blink");
+        assert_eq!(header.get_minimum_app_ram_size(), 4096);
+        assert_eq!(header.get_init_function_offset(), 0x41 + header_size);
+        assert_eq!(header.get_protected_size(), 0x10 + header_size);
+        assert!(header.has_permissions());
+        assert!(header.driver_permitted(0x1, Some(2), DefaultPermissions::DenyAll));
+        assert!(!header.driver_permitted(0x1, Some(1), DefaultPermissions::DenyAll));
+        assert!(!header.driver_permitted(0x2, None, DefaultPermissions::DenyAll));
+    }
+
+    #[test]
+    fn default_permissions_apply_without_permissions_entry() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0x10, 4096));
+        let app = v2_app(&tlvs, 1024);
+
+        let header = parse_tbf_header(&app).unwrap();
+        assert!(!header.has_permissions());
+        assert!(header.driver_permitted(0x1, Some(1), DefaultPermissions::AllowAll));
+        assert!(!header.driver_permitted(0x1, Some(1), DefaultPermissions::DenyAll));
+        assert!(!header.driver_permitted(0x1, None, DefaultPermissions::DenyAll));
+    }
+
+    #[test]
+    fn parses_v2_padding() {
+        let app = v2_app(&[], 512);
+        let header = parse_tbf_header(&app).unwrap();
+        assert!(!header.is_app());
+        assert_eq!(header.get_total_size(), 512);
+    }
+
+    #[test]
+    fn parses_v1_header() {
+        let mut fields = [0; 16];
+        // Data, GOT and BSS sizes.
+        fields[8] = 100;
+        fields[6] = 20;
+        fields[10] = 4;
+        // Stack, app heap and kernel heap sizes.
+        fields[11] = 1000;
+        fields[12] = 1024;
+        fields[13] = 1023;
+        // Package name.
+        fields[14] = 100;
+        fields[15] = 4;
+        let mut app = v1_app(&fields, 256);
+        app[100..104].copy_from_slice(b"test");
+
+        let header = parse_tbf_header(&app).unwrap();
+        assert!(header.is_app());
+        assert_eq!(header.get_package_name(), "test");
+        assert_eq!(header.get_protected_size(), 76);
+        // align8(124 + 1000) + 1024 + 1024
+        assert_eq!(header.get_minimum_app_ram_size(), 1128 + 2048);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0, 4096));
+        let mut app = v2_app(&tlvs, 1024);
+        app[16 + 4] ^= 0x80;
+        match parse_tbf_header(&app) {
+            Err(TbfParseError::ChecksumMismatch { stored, computed }) => {
+                assert_eq!(stored ^ computed, 0x80)
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_tlv() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0, 4096));
+        // A package name claiming more bytes than are left in the header.
+        push_u32(&mut tlvs, 3 | 64 << 16);
+        tlvs.extend_from_slice(b"name");
+        let app = v2_app(&tlvs, 1024);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::TruncatedTlv(3)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut app = v2_app(&[], 512);
+        app[0] = 7;
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::UnknownVersion(7)
+        );
+    }
+
+    #[test]
+    fn rejects_header_larger_than_app() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0, 4096));
+        let mut app = v2_app(&tlvs, 1024);
+        // Total size no larger than the header.
+        app[4..8].copy_from_slice(&[16, 0, 0, 0]);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::SizeOverflow
+        );
+    }
+
+    #[test]
+    fn rejects_v1_sizes_that_overflow() {
+        let mut fields = [0; 16];
+        fields[8] = 0xFFFF_FFF0;
+        fields[6] = 0x20;
+        let app = v1_app(&fields, 256);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::SizeOverflow
+        );
+
+        let mut fields = [0; 16];
+        fields[12] = 0xFFFF_FFFF;
+        let app = v1_app(&fields, 256);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::SizeOverflow
+        );
+    }
+
+    #[test]
+    fn rejects_v2_offsets_that_overflow() {
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0xFFFF_FFFF, 0, 4096));
+        let app = v2_app(&tlvs, 1024);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::SizeOverflow
+        );
+
+        let mut tlvs = Vec::new();
+        push_tlv(&mut tlvs, 1, &main_tlv(0x41, 0xFFFF_FFF0, 4096));
+        let app = v2_app(&tlvs, 1024);
+        assert_eq!(
+            parse_tbf_header(&app).unwrap_err(),
+            TbfParseError::SizeOverflow
+        );
+    }
+
+    fn credential(format: u32, data: &[u8]) -> Vec<u8> {
+        let mut credential = Vec::new();
+        push_u32(&mut credential, format);
+        credential.extend_from_slice(data);
+        credential
+    }
+
+    #[test]
+    fn parses_footer_credentials() {
+        let mut footers = Vec::new();
+        push_tlv(&mut footers, 128, &credential(1, &[1; 32]));
+        // Footers that are not credentials and unknown formats are skipped.
+        push_tlv(&mut footers, 200, &[0; 6]);
+        push_tlv(&mut footers, 128, &credential(99, &[0; 10]));
+        push_tlv(&mut footers, 128, &credential(3, &[3; 64]));
+
+        let credentials: Vec<_> = parse_tbf_footer_credentials(&footers)
+            .map(|credentials| credentials.unwrap())
+            .collect();
+        assert_eq!(credentials.len(), 2);
+        assert_eq!(credentials[0].format(), TbfFooterV2CredentialsType::Sha256);
+        assert_eq!(credentials[0].data(), &[1; 32][..]);
+        assert_eq!(
+            credentials[1].format(),
+            TbfFooterV2CredentialsType::EcdsaNistP256
+        );
+        assert_eq!(credentials[1].data(), &[3; 64][..]);
+
+        assert!(parse_tbf_footer_credentials(&[]).next().is_none());
+    }
+
+    #[test]
+    fn rejects_credentials_of_the_wrong_length() {
+        let mut footers = Vec::new();
+        push_tlv(&mut footers, 128, &credential(2, &[2; 31]));
+        push_tlv(&mut footers, 128, &credential(1, &[1; 32]));
+
+        let mut credentials = parse_tbf_footer_credentials(&footers);
+        assert_eq!(
+            credentials.next().unwrap().unwrap_err(),
+            TbfParseError::BadTlvLength(128)
+        );
+        assert!(credentials.next().is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_footer() {
+        let mut footers = Vec::new();
+        push_tlv(&mut footers, 128, &credential(1, &[1; 32]));
+        footers.truncate(20);
+
+        let mut credentials = parse_tbf_footer_credentials(&footers);
+        assert_eq!(
+            credentials.next().unwrap().unwrap_err(),
+            TbfParseError::TruncatedTlv(128)
+        );
+        assert!(credentials.next().is_none());
+    }
+}
@@ -0,0 +1,504 @@
+//! Types that represent the parts of a Tock Binary Format header.
+
+use core::mem;
+
+/// Takes a value and rounds it up to be aligned % 8, or `None` if that
+/// overflows.
+fn checked_align8(value: u32) -> Option<u32> {
+    value.checked_add((8 - (value % 8)) % 8)
+}
+
+/// Reasons a TBF header can fail to parse.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TbfParseError {
+    /// The buffer ends before the header does.
+    NotEnoughData,
+
+    /// The header version is not one this parser understands. The version
+    /// that was found is included.
+    UnknownVersion(u16),
+
+    /// The header claims a size that cannot be right: the header is shorter
+    /// than the base header or at least as long as the whole app, the app is
+    /// larger than any flash, or the sizes and offsets in the header overflow
+    /// when added up.
+    SizeOverflow,
+
+    /// The checksum stored in the header does not match the checksum computed
+    /// over the header.
+    ChecksumMismatch { stored: u32, computed: u32 },
+
+    /// The TLV entry of the given type extends past the end of the header.
+    TruncatedTlv(u16),
+
+    /// The TLV entry of the given type has the wrong length for its type.
+    BadTlvLength(u16),
+}
+
+/// Which drivers an app may use when its header has no Permissions entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DefaultPermissions {
+    /// Every driver, as for apps built before permissions existed.
+    AllowAll,
+
+    /// No driver.
+    DenyAll,
+}
+
+/// Legacy Tock Binary Format header.
+///
+/// Version 1 of the header is deprecated but can still be parsed by the kernel
+/// to support any apps that were compiled with an older version of elf2tbf.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV1 {
+    pub(crate) version: u32,
+    pub(crate) total_size: u32,
+    pub(crate) entry_offset: u32,
+    pub(crate) rel_data_offset: u32,
+    pub(crate) rel_data_size: u32,
+    pub(crate) text_offset: u32,
+    pub(crate) text_size: u32,
+    pub(crate) got_offset: u32,
+    pub(crate) got_size: u32,
+    pub(crate) data_offset: u32,
+    pub(crate) data_size: u32,
+    pub(crate) bss_mem_offset: u32,
+    pub(crate) bss_size: u32,
+    pub(crate) min_stack_len: u32,
+    pub(crate) min_app_heap_len: u32,
+    pub(crate) min_kernel_heap_len: u32,
+    pub(crate) pkg_name_offset: u32,
+    pub(crate) pkg_name_size: u32,
+    pub(crate) checksum: u32,
+}
+
+impl TbfHeaderV1 {
+    /// Add up the RAM the app needs, or `None` if the sizes in the header
+    /// overflow.
+    pub(crate) fn minimum_ram_size(&self) -> Option<u32> {
+        let heap_len = checked_align8(self.min_app_heap_len)?
+            .checked_add(checked_align8(self.min_kernel_heap_len)?)?;
+        let data_len = self
+            .data_size
+            .checked_add(self.got_size)?
+            .checked_add(self.bss_size)?;
+        let stack_size = checked_align8(self.min_stack_len)?;
+        checked_align8(data_len.checked_add(stack_size)?)?.checked_add(heap_len)
+    }
+}
+
+/// TBF fields that must be present in all v2 headers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Base {
+    pub(crate) version: u16,
+    pub(crate) header_size: u16,
+    pub(crate) total_size: u32,
+    pub(crate) flags: u32,
+    pub(crate) checksum: u32,
+}
+
+/// Types in TLV structures for each optional block of the header.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug)]
+pub enum TbfHeaderTypes {
+    TbfHeaderMain = 1,
+    TbfHeaderWriteableFlashRegions = 2,
+    TbfHeaderPackageName = 3,
+    // Type 4 is reserved for the PIC Option 1 fields.
+    TbfHeaderPriority = 5,
+    TbfHeaderTimeslice = 6,
+    TbfHeaderMaxRestarts = 7,
+    TbfHeaderPermissions = 8,
+    TbfHeaderFooter = 9,
+}
+
+impl TbfHeaderTypes {
+    /// Map a raw TLV type to a known type, or `None` if the type is not one
+    /// the parser understands.
+    pub fn from_u16(tipe: u16) -> Option<TbfHeaderTypes> {
+        match tipe {
+            1 => Some(TbfHeaderTypes::TbfHeaderMain),
+            2 => Some(TbfHeaderTypes::TbfHeaderWriteableFlashRegions),
+            3 => Some(TbfHeaderTypes::TbfHeaderPackageName),
+            5 => Some(TbfHeaderTypes::TbfHeaderPriority),
+            6 => Some(TbfHeaderTypes::TbfHeaderTimeslice),
+            7 => Some(TbfHeaderTypes::TbfHeaderMaxRestarts),
+            8 => Some(TbfHeaderTypes::TbfHeaderPermissions),
+            9 => Some(TbfHeaderTypes::TbfHeaderFooter),
+            _ => None,
+        }
+    }
+}
+
+/// The v2 main section for apps.
+///
+/// All apps must have a main section. Without it, the header is considered as
+/// only padding.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Main {
+    pub(crate) init_fn_offset: u32,
+    pub(crate) protected_size: u32,
+    pub(crate) minimum_ram_size: u32,
+}
+
+/// Writeable flash regions only need an offset and size.
+///
+/// There can be multiple (or zero) flash regions defined, so this is its own
+/// struct.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2WriteableFlashRegion {
+    pub(crate) writeable_flash_region_offset: u32,
+    pub(crate) writeable_flash_region_size: u32,
+}
+
+/// Scheduling priority of the app.
+///
+/// Only used by schedulers that take priorities into account. Larger values
+/// are higher priority.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Priority {
+    pub(crate) priority: u32,
+}
+
+/// Length of the app's scheduling quantum in microseconds.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Timeslice {
+    pub(crate) timeslice_us: u32,
+}
+
+/// How many times the kernel may restart the app after it faults.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2MaxRestarts {
+    pub(crate) max_restarts: u32,
+}
+
+/// One driver the app is allowed to use, and which of its commands.
+///
+/// `allowed_commands` is a bitmask of the command numbers from `offset * 64`
+/// to `offset * 64 + 63` the app may call. There can be several entries for
+/// the same driver to allow commands in different ranges.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2DriverPermission {
+    pub(crate) driver_number: u32,
+    pub(crate) offset: u32,
+    pub(crate) allowed_commands: u64,
+}
+
+/// Where the app binary ends and its footers start.
+///
+/// Footers hold data about the binary, such as credentials, that cannot be in
+/// the header because they are computed over it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2Footer {
+    pub(crate) binary_end_offset: u32,
+}
+
+/// Types in TLV structures for each footer entry.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug)]
+pub enum TbfFooterTypes {
+    TbfFooterCredentials = 128,
+}
+
+/// Kinds of credential an app can carry in a Credentials footer.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TbfFooterV2CredentialsType {
+    /// SHA-256 digest of the binary.
+    Sha256 = 1,
+    /// HMAC-SHA256 of the binary.
+    HmacSha256 = 2,
+    /// ECDSA NIST P-256 signature over the SHA-256 digest of the binary, as
+    /// the 32-byte `r` followed by the 32-byte `s`.
+    EcdsaNistP256 = 3,
+}
+
+impl TbfFooterV2CredentialsType {
+    /// Map a raw credential format to a known format, or `None` if the format
+    /// is not one the parser understands.
+    pub fn from_u32(format: u32) -> Option<TbfFooterV2CredentialsType> {
+        match format {
+            1 => Some(TbfFooterV2CredentialsType::Sha256),
+            2 => Some(TbfFooterV2CredentialsType::HmacSha256),
+            3 => Some(TbfFooterV2CredentialsType::EcdsaNistP256),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes of credential data for this format.
+    pub fn data_length(&self) -> usize {
+        match *self {
+            TbfFooterV2CredentialsType::Sha256 => 32,
+            TbfFooterV2CredentialsType::HmacSha256 => 32,
+            TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
+        }
+    }
+}
+
+/// A credential from the footer of an app.
+///
+/// Credentials cover the app from the start of its header up to the end of
+/// its binary.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfFooterV2Credentials<'a> {
+    pub(crate) format: TbfFooterV2CredentialsType,
+    pub(crate) data: &'a [u8],
+}
+
+impl<'a> TbfFooterV2Credentials<'a> {
+    pub fn format(&self) -> TbfFooterV2CredentialsType {
+        self.format
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Single header that can contain all parts of a v2 header.
+///
+/// Writeable flash regions and driver permissions are kept as the raw bytes of
+/// their TLV entry and decoded when they are asked for, since there can be any
+/// number of them.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2<'a> {
+    pub(crate) base: TbfHeaderV2Base,
+    pub(crate) main: Option<TbfHeaderV2Main>,
+    pub(crate) package_name: Option<&'a str>,
+    pub(crate) writeable_regions: Option<&'a [u8]>,
+    pub(crate) priority: Option<TbfHeaderV2Priority>,
+    pub(crate) timeslice: Option<TbfHeaderV2Timeslice>,
+    pub(crate) max_restarts: Option<TbfHeaderV2MaxRestarts>,
+    pub(crate) permissions: Option<&'a [u8]>,
+    pub(crate) footer: Option<TbfHeaderV2Footer>,
+}
+
+impl<'a> TbfHeaderV2<'a> {
+    /// Offset of the app's init function from the start of its flash region,
+    /// or `None` if it overflows.
+    pub(crate) fn init_fn_offset(&self) -> Option<u32> {
+        self.main
+            .map_or(0, |m| m.init_fn_offset)
+            .checked_add(self.base.header_size as u32)
+    }
+
+    /// Size of the part of the app's flash region only the kernel may write,
+    /// or `None` if it overflows.
+    pub(crate) fn protected_size(&self) -> Option<u32> {
+        self.main
+            .map_or(0, |m| m.protected_size)
+            .checked_add(self.base.header_size as u32)
+    }
+}
+
+/// Type that represents the fields of the Tock Binary Format header.
+///
+/// This specifies the locations of the different code and memory sections
+/// in the tock binary, as well as other information about the application.
+/// The kernel can also use this header to keep persistent state about
+/// the application.
+///
+/// Version 1 headers store the package name elsewhere in the app, so it is
+/// looked up while parsing and kept alongside the header.
+#[derive(Clone, Copy, Debug)]
+pub enum TbfHeader<'a> {
+    TbfHeaderV1(TbfHeaderV1, Option<&'a str>),
+    TbfHeaderV2(TbfHeaderV2<'a>),
+    Padding(TbfHeaderV2Base),
+}
+
+impl<'a> TbfHeader<'a> {
+    /// Return whether this is an app or just padding between apps.
+    pub fn is_app(&self) -> bool {
+        match *self {
+            TbfHeader::TbfHeaderV1(..) => true,
+            TbfHeader::TbfHeaderV2(_) => true,
+            TbfHeader::Padding(_) => false,
+        }
+    }
+
+    /// Return whether the application is enabled or not.
+    /// Disabled applications are not started by the kernel.
+    pub fn enabled(&self) -> bool {
+        match *self {
+            // Header v1 has no flag for this, and therefore all apps are
+            // always enabled.
+            TbfHeader::TbfHeaderV1(..) => true,
+            TbfHeader::TbfHeaderV2(hd) => {
+                // Bit 1 of flags is the enable/disable bit.
+                hd.base.flags & 0x00000001 == 1
+            }
+            TbfHeader::Padding(_) => false,
+        }
+    }
+
+    /// Get the total size in flash of this app or padding.
+    pub fn get_total_size(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV1(hd, _) => hd.total_size,
+            TbfHeader::TbfHeaderV2(hd) => hd.base.total_size,
+            TbfHeader::Padding(hd) => hd.total_size,
+        }
+    }
+
+    /// Add up all of the relevant fields in header version 1, or just used the
+    /// app provided value in version 2 to get the total amount of RAM that is
+    /// needed for this app.
+    pub fn get_minimum_app_ram_size(&self) -> u32 {
+        match *self {
+            // The parser rejects headers whose sizes overflow.
+            TbfHeader::TbfHeaderV1(hd, _) => hd.minimum_ram_size().unwrap_or(0),
+            TbfHeader::TbfHeaderV2(hd) => hd.main.map_or(0, |m| m.minimum_ram_size),
+            _ => 0,
+        }
+    }
+
+    /// Get the number of bytes from the start of the app's region in flash that
+    /// is for kernel use only. The app cannot write this region.
+    pub fn get_protected_size(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV1(..) => mem::size_of::<TbfHeaderV1>() as u32,
+            TbfHeader::TbfHeaderV2(hd) => hd.protected_size().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Get the offset from the beginning of the app's flash region where the
+    /// app should start executing.
+    pub fn get_init_function_offset(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV1(hd, _) => hd.entry_offset,
+            TbfHeader::TbfHeaderV2(hd) => hd.init_fn_offset().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Get the name of the app.
+    pub fn get_package_name(&self) -> &'a str {
+        match *self {
+            TbfHeader::TbfHeaderV1(_, package_name) => package_name.unwrap_or(""),
+            TbfHeader::TbfHeaderV2(hd) => hd.package_name.unwrap_or(""),
+            _ => "",
+        }
+    }
+
+    /// Get the scheduling priority of the app. Apps that do not specify a
+    /// priority get the lowest priority, `0`.
+    pub fn get_priority(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.priority.map_or(0, |p| p.priority),
+            _ => 0,
+        }
+    }
+
+    /// Get the scheduling quantum the app asked for in microseconds, if any.
+    pub fn get_timeslice_us(&self) -> Option<u32> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.timeslice.map(|t| t.timeslice_us),
+            _ => None,
+        }
+    }
+
+    /// Get how many times the app may be restarted after a fault, if the app
+    /// limits it.
+    pub fn get_max_restarts(&self) -> Option<u32> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.max_restarts.map(|m| m.max_restarts),
+            _ => None,
+        }
+    }
+
+    /// Get the offset from the beginning of the app's flash region where the
+    /// app binary ends and its footers begin. Apps without footers end at
+    /// their total size.
+    pub fn get_binary_end(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .footer
+                .map_or(hd.base.total_size, |f| f.binary_end_offset),
+            _ => self.get_total_size(),
+        }
+    }
+
+    /// Whether the app's header has a Permissions entry restricting which
+    /// drivers it may use.
+    pub fn has_permissions(&self) -> bool {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.permissions.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Check whether the app may use the driver numbered `driver_num` and, if
+    /// `command_num` is given, whether it may call that command on it.
+    ///
+    /// Apps without a Permissions entry in their header may use the drivers
+    /// `default` permits.
+    pub fn driver_permitted(
+        &self,
+        driver_num: usize,
+        command_num: Option<usize>,
+        default: DefaultPermissions,
+    ) -> bool {
+        let permitted_by_default = default == DefaultPermissions::AllowAll;
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.permissions.map_or(permitted_by_default, |perms| {
+                perms
+                    .chunks(mem::size_of::<TbfHeaderV2DriverPermission>())
+                    .filter_map(|perm| TbfHeaderV2DriverPermission::parse(perm).ok())
+                    .filter(|perm| perm.driver_number as usize == driver_num)
+                    .any(|perm| {
+                        command_num.map_or(true, |command_num| {
+                            perm.offset as usize == command_num / 64
+                                && (perm.allowed_commands >> (command_num % 64)) & 1 == 1
+                        })
+                    })
+            }),
+            _ => permitted_by_default,
+        }
+    }
+
+    /// Get the number of flash regions this app has specified in its header.
+    pub fn number_writeable_flash_regions(&self) -> usize {
+        match *self {
+            TbfHeader::TbfHeaderV1(..) => 0,
+            TbfHeader::TbfHeaderV2(hd) => hd.writeable_regions.map_or(0, |wr| {
+                wr.len() / mem::size_of::<TbfHeaderV2WriteableFlashRegion>()
+            }),
+            _ => 0,
+        }
+    }
+
+    /// Get the offset and size of a given flash region.
+    pub fn get_writeable_flash_region(&self, index: usize) -> (u32, u32) {
+        match *self {
+            TbfHeader::TbfHeaderV1(..) => (0, 0),
+            TbfHeader::TbfHeaderV2(hd) => {
+                let region_size = mem::size_of::<TbfHeaderV2WriteableFlashRegion>();
+                hd.writeable_regions
+                    .and_then(|wr| {
+                        let start = index.checked_mul(region_size)?;
+                        let end = start.checked_add(region_size)?;
+                        wr.get(start..end)
+                    })
+                    .and_then(|region| TbfHeaderV2WriteableFlashRegion::parse(region).ok())
+                    .map_or((0, 0), |region| {
+                        (
+                            region.writeable_flash_region_offset,
+                            region.writeable_flash_region_size,
+                        )
+                    })
+            }
+            _ => (0, 0),
+        }
+    }
+}